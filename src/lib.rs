@@ -512,20 +512,89 @@ macro_rules! err_trim {
 }
 
 // region: mod, extern and use statements
+#[cfg(feature = "arrow")]
+mod qvs20_arrow_mod;
+mod qvs20_constraints_mod;
+mod qvs20_dialect_mod;
+mod qvs20_group_by_mod;
+mod qvs20_json_schema_mod;
+mod qvs20_ndjson_mod;
+mod qvs20_package_mod;
+#[cfg(feature = "parquet")]
+mod qvs20_parquet_mod;
+mod qvs20_partition_mod;
+mod qvs20_profile_mod;
+mod qvs20_query_mod;
 mod qvs20_reader_mod;
+mod qvs20_render_mod;
+mod qvs20_row_stream_mod;
+mod qvs20_schema_evolution_mod;
+mod qvs20_schema_registry_mod;
+mod qvs20_sniff_mod;
+mod qvs20_sort_mod;
+mod qvs20_split_mod;
+mod qvs20_sql_mod;
+mod qvs20_table_diff_mod;
+mod qvs20_table_index_mod;
 mod qvs20_table_mod;
+mod qvs20_table_ops_mod;
 mod qvs20_table_rows_mod;
 mod qvs20_table_schema_mod;
+mod qvs20_type_inference_mod;
 mod qvs20_writer_mod;
+#[cfg(feature = "xlsx")]
+mod qvs20_xlsx_mod;
+#[cfg(feature = "xml")]
+mod qvs20_xml_mod;
 
 // reexport objects for callers of the library
+pub use qvs20_constraints_mod::ColumnConstraints;
+pub use qvs20_constraints_mod::SchemaConstraints;
+pub use qvs20_dialect_mod::detect_dialect;
+pub use qvs20_dialect_mod::Dialect;
+pub use qvs20_dialect_mod::SubTableDowngrade;
+pub use qvs20_group_by_mod::Aggregate;
+pub use qvs20_ndjson_mod::ndjson_to_qvs21;
+pub use qvs20_ndjson_mod::ndjson_to_qvs21_infer;
+pub use qvs20_ndjson_mod::qvs21_to_ndjson;
+pub use qvs20_package_mod::ForeignKey;
+pub use qvs20_package_mod::Package;
+#[cfg(feature = "parquet")]
+pub use qvs20_parquet_mod::parquet_to_qvs21;
+#[cfg(feature = "parquet")]
+pub use qvs20_parquet_mod::qvs21_to_parquet;
+pub use qvs20_partition_mod::PartitionBy;
+pub use qvs20_partition_mod::PartitionWriter;
+pub use qvs20_query_mod::Query;
 pub use qvs20_reader_mod::remove_src_loc;
 pub use qvs20_reader_mod::Qvs20Error;
 pub use qvs20_reader_mod::ReaderForQvs20;
+pub use qvs20_render_mod::SubTableDisplay;
+pub use qvs20_row_stream_mod::RowStream;
+pub use qvs20_schema_evolution_mod::Compatibility;
+pub use qvs20_schema_evolution_mod::SchemaChange;
+pub use qvs20_schema_evolution_mod::SchemaComparison;
+pub use qvs20_schema_registry_mod::SchemaRegistry;
+pub use qvs20_sniff_mod::open;
+pub use qvs20_sniff_mod::sniff;
+pub use qvs20_sniff_mod::FileInfo;
+pub use qvs20_sniff_mod::FileType;
+pub use qvs20_sniff_mod::QvsFile;
+pub use qvs20_sort_mod::SortOrder;
+pub use qvs20_split_mod::SplitWriter;
+pub use qvs20_sql_mod::SqlDialect;
+pub use qvs20_table_diff_mod::CellChange;
+pub use qvs20_table_diff_mod::RowUpdate;
+pub use qvs20_table_diff_mod::TableDiff;
+pub use qvs20_table_index_mod::TableIndex;
 pub use qvs20_table_mod::Table;
+pub use qvs20_table_ops_mod::RowView;
 pub use qvs20_table_rows_mod::Row;
 pub use qvs20_table_rows_mod::TableRows;
 pub use qvs20_table_rows_mod::Value;
 pub use qvs20_table_schema_mod::TableSchema;
 pub use qvs20_table_schema_mod::DataType;
+pub use qvs20_type_inference_mod::ConversionIssue;
 pub use qvs20_writer_mod::WriterForQvs20;
+#[cfg(feature = "xml")]
+pub use qvs20_xml_mod::XmlImport;
@@ -0,0 +1,468 @@
+// qvs20_arrow_mod
+
+//! Conversion between Table and Arrow RecordBatch. Only with the cargo feature `arrow`.
+//!
+//! | QVS21               | Arrow                                     |
+//! |---------------------|-------------------------------------------|
+//! | String              | Utf8                                      |
+//! | Integer             | Int64                                     |
+//! | Decimal             | Decimal128, precision and scale from data |
+//! | Float               | Float64                                   |
+//! | Bool                | Boolean                                   |
+//! | DateTimeFixedOffset | Timestamp(Nanosecond, offset)             |
+//! | Date                | Date32                                    |
+//! | Time                | Time64(Nanosecond)                        |
+//! | SubTable            | List of Struct from the sub table schema  |
+//!
+//! Null is null in the validity bitmap.
+//! The Timestamp column has one offset: the offset of the first value.
+//! The other values are converted to this offset, the instant stays the same.
+//! Table name, description and additional properties are kept in the Arrow metadata.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use arrow::array::*;
+use arrow::buffer::{NullBuffer, OffsetBuffer, ScalarBuffer};
+use arrow::datatypes::{
+    DataType as ArrowDataType, Date32Type, Decimal128Type, Field, Fields, Float64Type, Int64Type, Schema, Time64NanosecondType, TimeUnit,
+    TimestampNanosecondType,
+};
+use arrow::record_batch::RecordBatch;
+use chrono::prelude::*;
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+const TABLE_NAME_KEY: &str = "qvs21.table_name";
+const DESCRIPTION_KEY: &str = "qvs21.description";
+const ADDITIONAL_PROPERTIES_KEY: &str = "qvs21.additional_properties";
+/// days from 0001-01-01 to 1970-01-01
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+fn arrow_error(e: arrow::error::ArrowError) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("Arrow error: {}", e),
+    }
+}
+
+fn table_metadata(schema: &TableSchema) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    metadata.insert(s!(TABLE_NAME_KEY), schema.table_name.clone());
+    metadata.insert(s!(DESCRIPTION_KEY), schema.table_description.clone());
+    metadata
+}
+
+fn nulls(validity: Vec<bool>) -> Option<NullBuffer> {
+    if validity.iter().all(|v| *v) {
+        None
+    } else {
+        Some(NullBuffer::from(validity))
+    }
+}
+
+fn nanos_from_datetime(d: &DateTime<FixedOffset>) -> Result<i64, Qvs20Error> {
+    d.timestamp()
+        .checked_mul(1_000_000_000)
+        .and_then(|n| n.checked_add(d.timestamp_subsec_nanos() as i64))
+        .ok_or_else(|| Qvs20Error::Error {
+            msg: format!("DateTime {} is out of range for Arrow Timestamp.", d.to_rfc3339()),
+        })
+}
+
+/// offset of the Arrow timezone: +09:00, -03:30, UTC or Z
+fn offset_from_timezone(timezone: Option<&str>) -> Result<FixedOffset, Qvs20Error> {
+    let timezone = match timezone {
+        None | Some("UTC") | Some("Z") => "+00:00",
+        Some(tz) => tz,
+    };
+    match DateTime::parse_from_rfc3339(&format!("1970-01-01T00:00:00{}", timezone)) {
+        Ok(d) => Ok(*d.offset()),
+        Err(_) => Err(Qvs20Error::Error {
+            msg: format!("Arrow timezone {} is not a fixed offset.", timezone),
+        }),
+    }
+}
+
+/// the Arrow array of one column and the field for the array
+fn column_to_array(schema: &TableSchema, column: usize, values: &[&Value]) -> Result<(Field, ArrayRef), Qvs20Error> {
+    let array: ArrayRef = match &schema.data_types[column] {
+        DataType::String => Arc::new(StringArray::from_iter(values.iter().map(|v| match v {
+            Value::String(s) => Some(s.as_str()),
+            _ => None,
+        }))),
+        DataType::Integer => Arc::new(Int64Array::from_iter(values.iter().map(|v| match v {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }))),
+        DataType::Float => Arc::new(Float64Array::from_iter(values.iter().map(|v| match v {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }))),
+        DataType::Bool => Arc::new(BooleanArray::from_iter(values.iter().map(|v| match v {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }))),
+        DataType::Date => Arc::new(Date32Array::from_iter(values.iter().map(|v| match v {
+            Value::Date(d) => Some(d.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE),
+            _ => None,
+        }))),
+        DataType::Time => Arc::new(Time64NanosecondArray::from_iter(values.iter().map(|v| match v {
+            Value::Time(t) => Some(t.num_seconds_from_midnight() as i64 * 1_000_000_000 + t.nanosecond() as i64),
+            _ => None,
+        }))),
+        DataType::Decimal => {
+            // the scale is the biggest scale, the precision is for the biggest number
+            let scale = values
+                .iter()
+                .filter_map(|v| if let Value::Decimal(d) = v { Some(d.scale()) } else { None })
+                .max()
+                .unwrap_or(0);
+            let mut precision = scale.max(1);
+            let mut mantissas = vec![];
+            for v in values.iter() {
+                match v {
+                    Value::Decimal(d) => {
+                        let mut d = *d;
+                        d.rescale(scale);
+                        precision = precision.max(d.mantissa().unsigned_abs().to_string().len() as u32);
+                        mantissas.push(Some(d.mantissa()));
+                    }
+                    _ => mantissas.push(None),
+                }
+            }
+            Arc::new(
+                Decimal128Array::from(mantissas)
+                    .with_precision_and_scale(precision as u8, scale as i8)
+                    .map_err(arrow_error)?,
+            )
+        }
+        DataType::DateTimeFixedOffset => {
+            let timezone = values
+                .iter()
+                .find_map(|v| if let Value::DateTimeFixedOffset(d) = v { Some(d.offset().to_string()) } else { None })
+                .unwrap_or_else(|| s!("+00:00"));
+            let mut nanos = vec![];
+            for v in values.iter() {
+                match v {
+                    Value::DateTimeFixedOffset(d) => nanos.push(Some(nanos_from_datetime(d)?)),
+                    _ => nanos.push(None),
+                }
+            }
+            Arc::new(TimestampNanosecondArray::from(nanos).with_timezone(timezone))
+        }
+        DataType::SubTable => {
+            let sub_schema = match &schema.sub_table_schemas[column] {
+                Some(s) => s,
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("SubTable column {} does not have a schema.", schema.column_names[column]),
+                    })
+                }
+            };
+            // all sub rows are in one struct array, the offsets divide them
+            let mut offsets = vec![0_i32];
+            let mut validity = vec![];
+            let mut sub_rows: Vec<&Row> = vec![];
+            for v in values.iter() {
+                match v {
+                    Value::SubTable(sub_table_rows) => {
+                        sub_rows.extend(sub_table_rows.rows.iter());
+                        validity.push(true);
+                    }
+                    _ => validity.push(false),
+                }
+                offsets.push(i32::try_from(sub_rows.len()).map_err(|_| Qvs20Error::Error {
+                    msg: format!("SubTable column {} has too many rows for Arrow List.", schema.column_names[column]),
+                })?);
+            }
+            let (fields, arrays) = columns_to_arrays(sub_schema, &sub_rows)?;
+            let fields = Fields::from(fields);
+            let struct_array = StructArray::try_new(fields.clone(), arrays, None).map_err(arrow_error)?;
+            let item = Field::new(&sub_schema.table_name, ArrowDataType::Struct(fields), false).with_metadata(table_metadata(sub_schema));
+            Arc::new(
+                ListArray::try_new(
+                    Arc::new(item),
+                    OffsetBuffer::new(ScalarBuffer::from(offsets)),
+                    Arc::new(struct_array),
+                    nulls(validity),
+                )
+                .map_err(arrow_error)?,
+            )
+        }
+    };
+    let mut field = Field::new(
+        &schema.column_names[column],
+        array.data_type().clone(),
+        is_nullable(&schema.data_types[column]),
+    );
+    if !schema.additional_properties[column].is_empty() {
+        let mut metadata = HashMap::new();
+        metadata.insert(s!(ADDITIONAL_PROPERTIES_KEY), schema.additional_properties[column].clone());
+        field = field.with_metadata(metadata);
+    }
+    //return
+    Ok((field, array))
+}
+
+fn columns_to_arrays(schema: &TableSchema, rows: &[&Row]) -> Result<(Vec<Field>, Vec<ArrayRef>), Qvs20Error> {
+    let mut fields = vec![];
+    let mut arrays = vec![];
+    for column in 0..schema.column_names.len() {
+        let values: Vec<&Value> = rows.iter().map(|row| &row.values[column]).collect();
+        let (field, array) = column_to_array(schema, column, &values)?;
+        fields.push(field);
+        arrays.push(array);
+    }
+    //return
+    Ok((fields, arrays))
+}
+
+/// data type, sub table schema and values of the Arrow array
+fn array_to_values(field: &Field, array: &ArrayRef) -> Result<(DataType, Option<TableSchema>, Vec<Value>), Qvs20Error> {
+    let len = array.len();
+    let mut values = Vec::with_capacity(len);
+    let cast = |to: &ArrowDataType| arrow::compute::cast(array, to).map_err(arrow_error);
+    let data_type = match array.data_type() {
+        ArrowDataType::Utf8 | ArrowDataType::LargeUtf8 => {
+            let array = cast(&ArrowDataType::Utf8)?;
+            let array = array.as_string::<i32>();
+            // String is not nullable, null is an empty string
+            for i in 0..len {
+                values.push(Value::String(if array.is_null(i) { s!() } else { s!(array.value(i)) }));
+            }
+            DataType::String
+        }
+        ArrowDataType::Int8
+        | ArrowDataType::Int16
+        | ArrowDataType::Int32
+        | ArrowDataType::Int64
+        | ArrowDataType::UInt8
+        | ArrowDataType::UInt16
+        | ArrowDataType::UInt32 => {
+            let array = cast(&ArrowDataType::Int64)?;
+            let array = array.as_primitive::<Int64Type>();
+            for i in 0..len {
+                values.push(if array.is_null(i) { Value::Null } else { Value::Integer(array.value(i)) });
+            }
+            DataType::Integer
+        }
+        ArrowDataType::Float32 | ArrowDataType::Float64 => {
+            let array = cast(&ArrowDataType::Float64)?;
+            let array = array.as_primitive::<Float64Type>();
+            for i in 0..len {
+                values.push(if array.is_null(i) { Value::Null } else { Value::Float(array.value(i)) });
+            }
+            DataType::Float
+        }
+        ArrowDataType::Boolean => {
+            let array = array.as_boolean();
+            for i in 0..len {
+                values.push(if array.is_null(i) { Value::Null } else { Value::Bool(array.value(i)) });
+            }
+            DataType::Bool
+        }
+        ArrowDataType::Decimal128(_, scale) => {
+            if *scale < 0 || *scale as u32 > 28 {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Arrow Decimal128 scale {} of column {} is not supported.", scale, field.name()),
+                });
+            }
+            let array = array.as_primitive::<Decimal128Type>();
+            for i in 0..len {
+                if array.is_null(i) {
+                    values.push(Value::Null);
+                    continue;
+                }
+                // a big scale does not fit in Decimal: remove the trailing zeros
+                let (mut mantissa, mut scale) = (array.value(i), *scale as u32);
+                while scale > 0 && mantissa % 10 == 0 && Decimal::try_from_i128_with_scale(mantissa, scale).is_err() {
+                    mantissa /= 10;
+                    scale -= 1;
+                }
+                match Decimal::try_from_i128_with_scale(mantissa, scale) {
+                    Ok(d) => values.push(Value::Decimal(d)),
+                    Err(e) => {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Arrow Decimal128 of column {} row {}: {}", field.name(), i, e),
+                        })
+                    }
+                }
+            }
+            DataType::Decimal
+        }
+        ArrowDataType::Date32 => {
+            let array = array.as_primitive::<Date32Type>();
+            for i in 0..len {
+                let value = if array.is_null(i) {
+                    Value::Null
+                } else {
+                    match array.value(i).checked_add(UNIX_EPOCH_DAYS_FROM_CE).and_then(NaiveDate::from_num_days_from_ce_opt) {
+                        Some(d) => Value::Date(d),
+                        None => {
+                            return Err(Qvs20Error::Error {
+                                msg: format!("Arrow Date32 of column {} row {} is out of range.", field.name(), i),
+                            })
+                        }
+                    }
+                };
+                values.push(value);
+            }
+            DataType::Date
+        }
+        ArrowDataType::Time32(_) | ArrowDataType::Time64(_) => {
+            let array = cast(&ArrowDataType::Time64(TimeUnit::Nanosecond))?;
+            let array = array.as_primitive::<Time64NanosecondType>();
+            for i in 0..len {
+                let value = if array.is_null(i) {
+                    Value::Null
+                } else {
+                    let nanos = array.value(i);
+                    match NaiveTime::from_num_seconds_from_midnight_opt(
+                        (nanos / 1_000_000_000) as u32,
+                        (nanos % 1_000_000_000) as u32,
+                    ) {
+                        Some(t) => Value::Time(t),
+                        None => {
+                            return Err(Qvs20Error::Error {
+                                msg: format!("Arrow Time64 of column {} row {} is out of range.", field.name(), i),
+                            })
+                        }
+                    }
+                };
+                values.push(value);
+            }
+            DataType::Time
+        }
+        ArrowDataType::Timestamp(_, timezone) => {
+            let offset = offset_from_timezone(timezone.as_deref())?;
+            let array = cast(&ArrowDataType::Timestamp(TimeUnit::Nanosecond, timezone.clone()))?;
+            let array = array.as_primitive::<TimestampNanosecondType>();
+            for i in 0..len {
+                values.push(if array.is_null(i) {
+                    Value::Null
+                } else {
+                    Value::DateTimeFixedOffset(offset.timestamp_nanos(array.value(i)))
+                });
+            }
+            DataType::DateTimeFixedOffset
+        }
+        ArrowDataType::List(item) => {
+            let array = array.as_list::<i32>();
+            let struct_array = match array.values().data_type() {
+                ArrowDataType::Struct(_) => array.values().as_struct(),
+                other => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Arrow List of column {} must be a List of Struct and not of {}.", field.name(), other),
+                    })
+                }
+            };
+            let (mut sub_schema, sub_table_rows) = struct_to_table(item, struct_array)?;
+            sub_schema.table_name = item.metadata().get(TABLE_NAME_KEY).cloned().unwrap_or_else(|| s!(field.name()));
+            let offsets = array.value_offsets();
+            for i in 0..len {
+                let mut sub_table = TableRows::default();
+                sub_table.table_name = sub_schema.table_name.clone();
+                if !array.is_null(i) {
+                    let range = offsets[i] as usize..offsets[i + 1] as usize;
+                    sub_table.rows.extend(sub_table_rows.rows[range].iter().cloned());
+                }
+                values.push(Value::SubTable(sub_table));
+            }
+            return Ok((DataType::SubTable, Some(sub_schema), values));
+        }
+        other => {
+            return Err(Qvs20Error::Error {
+                msg: format!("Arrow data type {} of column {} is not supported.", other, field.name()),
+            })
+        }
+    };
+    //return
+    Ok((data_type, None, values))
+}
+
+/// schema and rows from the struct array
+fn struct_to_table(field: &Field, struct_array: &StructArray) -> Result<(TableSchema, TableRows), Qvs20Error> {
+    let mut schema = TableSchema::new_simple_strings(0);
+    schema.table_description = field.metadata().get(DESCRIPTION_KEY).cloned().unwrap_or_default();
+    let mut table_rows = TableRows::default();
+    table_rows.rows = (0..struct_array.len()).map(|_| Row { values: vec![] }).collect();
+    for (field, array) in struct_array.fields().iter().zip(struct_array.columns()) {
+        let (data_type, sub_schema, values) = array_to_values(field, array)?;
+        let additional_property = field.metadata().get(ADDITIONAL_PROPERTIES_KEY).cloned().unwrap_or_default();
+        schema.push_column(field.name(), data_type, sub_schema, &additional_property);
+        for (row, value) in table_rows.rows.iter_mut().zip(values) {
+            row.values.push(value);
+        }
+    }
+    //return
+    Ok((schema, table_rows))
+}
+
+impl Table {
+    /// convert the table to an Arrow RecordBatch
+    pub fn to_record_batch(&self) -> Result<RecordBatch, Qvs20Error> {
+        let rows: Vec<&Row> = self.table_rows.rows.iter().collect();
+        let (fields, arrays) = columns_to_arrays(&self.schema, &rows)?;
+        let schema = Schema::new_with_metadata(fields, table_metadata(&self.schema));
+        //return
+        RecordBatch::try_new(Arc::new(schema), arrays).map_err(arrow_error)
+    }
+
+    /// convert an Arrow RecordBatch to a table.
+    /// Without metadata the table name is `table`.
+    pub fn from_record_batch(batch: &RecordBatch) -> Result<Table, Qvs20Error> {
+        let metadata = batch.schema().metadata().clone();
+        let field = Field::new("table", ArrowDataType::Null, false).with_metadata(metadata.clone());
+        let struct_array = StructArray::from(batch.clone());
+        let (mut schema, mut table_rows) = struct_to_table(&field, &struct_array)?;
+        schema.table_name = metadata.get(TABLE_NAME_KEY).cloned().unwrap_or_else(|| s!("table"));
+        table_rows.table_name = schema.table_name.clone();
+        schema.set_depth(0)?;
+        table_rows.set_depth(0)?;
+        //return
+        Ok(Table { schema, table_rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_record_batch_round_trip() {
+        let s = r"[T][countries][description]
+[String][Integer][Decimal][Float][Bool][DateTimeFixedOffset][Date][Time][SubTable]
+[][][][][][][][][1[U][cities][sub table]1[String][Integer]1[][]1[primary_key=1][]1[city][population]1]
+[primary_key=1][min=0][][][][][][][]
+[country][population][area][density][eu][updated][founded][opens][cities]
+[Slovenia][2000000][20271.5][103.2][T][2020-06-27T23:59:59+03:30][1991-06-25][08:00:00][1[Ljubljana][300000]1[Koper][]1]
+[Switzerland][][41285.25][][F][2014-11-28T21:00:09+09:00][][][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let batch = unwrap!(table.to_record_batch());
+        assert_eq!(batch.num_rows(), 2);
+        let schema = batch.schema();
+        assert_eq!(schema.field(2).data_type(), &ArrowDataType::Decimal128(7, 2));
+        assert_eq!(
+            schema.field(5).data_type(),
+            &ArrowDataType::Timestamp(TimeUnit::Nanosecond, Some("+03:30".into()))
+        );
+        assert!(!schema.field(0).is_nullable());
+        assert_eq!(batch.column(1).null_count(), 1);
+        assert_eq!(batch.column(8).as_list::<i32>().value(0).len(), 2);
+
+        let back = unwrap!(Table::from_record_batch(&batch));
+        // the second datetime has the offset of the column
+        assert_eq!(
+            back.write_table(),
+            s.replace("[2014-11-28T21:00:09+09:00]", "[2014-11-28T15:30:09+03:30]")
+                .replace("[20271.5]", "[20271.50]")
+        );
+    }
+}
@@ -0,0 +1,315 @@
+// qvs20_constraints_mod
+
+//! Column constraints are declared in the Schema 4th row - additional properties.
+//! The additional properties are free strings and cannot be standardized,
+//! so the constraints use a simple convention: `key=value` pairs separated by `;`.
+//! Text without `=` and unknown keys are ignored, because other use-cases need this row too.
+//!
+//! Recognized keys:
+//!
+//! - `min` and `max` - for Integer, Decimal, Float, Date, Time and DateTimeFixedOffset
+//! - `max_length` - count of characters for String
+//! - `regex` - for String. Use `^` and `$` for a full match. The `;` cannot be used inside.
+//! - `enum` - allowed values separated by `|`
+//! - `precision` and `scale` - for Decimal
+//! - `primary_key` - position of the column in the primary key: 1, 2,... for a composite key
+//! - `unique` - `T` if the values in the column must be unique
+//!
+//! The reader checks the constraints while parsing. `write_table()` stays infallible and returns a String
+//! like before, because it is used for every output, also for tables still in work.
+//! The writer checks the constraints with `Table::write_table_checked()` before the data leaves the program.
+//!
+//! Example of a 4th row:
+//!
+//! ```QVS20
+//! [min=0;max=150][max_length=20;regex=^\[A-Z\]][enum=red|green|blue][precision=10;scale=2][min=2020-01-01]
+//! ```
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use regex::Regex;
+
+/// Constraints for one column, parsed from the additional property.
+#[derive(Clone, Debug, Default)]
+pub struct ColumnConstraints {
+    /// minimum value, the same data type as the column
+    pub min: Option<Value>,
+    /// maximum value, the same data type as the column
+    pub max: Option<Value>,
+    /// maximum count of characters for String
+    pub max_length: Option<usize>,
+    /// the String must match the regex
+    pub regex: Option<Regex>,
+    /// enum: the value must be one of this values
+    pub allowed_values: Option<Vec<Value>>,
+    /// maximum count of all digits for Decimal
+    pub precision: Option<u32>,
+    /// maximum count of digits after the decimal point for Decimal
+    pub scale: Option<u32>,
+    /// position of the column in the primary key, starting with 1
+    pub primary_key: Option<u32>,
+    /// the values in this column must be unique
+    pub unique: bool,
+}
+
+/// Constraints of all columns of a schema and of its sub table schemas,
+/// parsed once and used for all rows and all sub table fields.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaConstraints {
+    pub columns: Vec<Option<ColumnConstraints>>,
+    /// for the SubTable columns with a sub table schema
+    pub sub_tables: Vec<Option<SchemaConstraints>>,
+}
+
+impl SchemaConstraints {
+    pub fn new(schema: &TableSchema) -> Result<SchemaConstraints, Qvs20Error> {
+        let columns = schema.column_constraints()?;
+        let mut sub_tables = vec![];
+        for sub_schema in schema.sub_table_schemas.iter() {
+            sub_tables.push(match sub_schema {
+                Some(sub_schema) => Some(SchemaConstraints::new(sub_schema)?),
+                None => None,
+            });
+        }
+        //return
+        Ok(SchemaConstraints { columns, sub_tables })
+    }
+}
+
+impl ColumnConstraints {
+    /// parse the additional property of one column.
+    /// Returns None if there is no recognized constraint.
+    pub fn parse(property: &str, data_type: &DataType) -> Result<Option<ColumnConstraints>, Qvs20Error> {
+        let mut constraints = ColumnConstraints::default();
+        let mut found = false;
+        for pair in property.split(';') {
+            let (key, text) = match pair.find('=') {
+                Some(pos) => (pair[..pos].trim(), &pair[pos + 1..]),
+                None => continue,
+            };
+            match key {
+                "min" => constraints.min = Some(Self::parse_value(key, text, data_type)?),
+                "max" => constraints.max = Some(Self::parse_value(key, text, data_type)?),
+                "max_length" | "regex" if *data_type != DataType::String => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Constraint {} is only for String, not for {}", key, data_type),
+                    })
+                }
+                "precision" | "scale" if *data_type != DataType::Decimal => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Constraint {} is only for Decimal, not for {}", key, data_type),
+                    })
+                }
+                "max_length" => constraints.max_length = Some(Self::parse_number(key, text)? as usize),
+                "regex" => match Regex::new(text) {
+                    Ok(r) => constraints.regex = Some(r),
+                    Err(e) => {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Constraint regex is not valid: {} {}", text, e),
+                        })
+                    }
+                },
+                "enum" => {
+                    let mut allowed_values = vec![];
+                    for x in text.split('|') {
+                        allowed_values.push(Self::parse_value(key, x, data_type)?);
+                    }
+                    constraints.allowed_values = Some(allowed_values);
+                }
+                "precision" => constraints.precision = Some(Self::parse_number(key, text)?),
+                "scale" => constraints.scale = Some(Self::parse_number(key, text)?),
+                "primary_key" => constraints.primary_key = Some(Self::parse_number(key, text)?),
+                "unique" => constraints.unique = TableRows::from_u8_to_bool(text.as_bytes())?,
+                // unknown keys are not constraints
+                _ => continue,
+            }
+            found = true;
+        }
+        //return
+        if found {
+            Ok(Some(constraints))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// the constraint value is written like the data of the column
+    fn parse_value(key: &str, text: &str, data_type: &DataType) -> Result<Value, Qvs20Error> {
+        match TableRows::value_from_str(text, data_type) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(Qvs20Error::Error {
+                msg: format!("Constraint {} for {}: {}", key, data_type, err_trim!(e)),
+            }),
+        }
+    }
+
+    fn parse_number(key: &str, text: &str) -> Result<u32, Qvs20Error> {
+        match text.trim().parse::<u32>() {
+            Ok(n) => Ok(n),
+            Err(e) => Err(Qvs20Error::Error {
+                msg: format!("Constraint {} must be a positive integer: {} {}", key, text, e),
+            }),
+        }
+    }
+
+    /// check one value. The error does not have the position, the caller adds it.
+    /// SubTable and Null values are not checked.
+    pub fn check(&self, value: &Value) -> Result<(), Qvs20Error> {
+        if let Value::SubTable(_) | Value::Null = value {
+            return Ok(());
+        }
+        if let Some(min) = &self.min {
+            if value < min {
+                return Err(Qvs20Error::Error {
+                    msg: format!("value {} is less than min {}", value_to_string(value), value_to_string(min)),
+                });
+            }
+        }
+        if let Some(max) = &self.max {
+            if value > max {
+                return Err(Qvs20Error::Error {
+                    msg: format!("value {} is greater than max {}", value_to_string(value), value_to_string(max)),
+                });
+            }
+        }
+        if let Some(allowed_values) = &self.allowed_values {
+            if !allowed_values.contains(value) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("value {} is not in enum", value_to_string(value)),
+                });
+            }
+        }
+        if let Value::String(s) = value {
+            if let Some(max_length) = self.max_length {
+                if s.chars().count() > max_length {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("value {} is longer than max_length {}", s, max_length),
+                    });
+                }
+            }
+            if let Some(regex) = &self.regex {
+                if !regex.is_match(s) {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("value {} does not match regex {}", s, regex),
+                    });
+                }
+            }
+        }
+        if let Value::Decimal(d) = value {
+            let fraction_digits = d.scale();
+            if let Some(scale) = self.scale {
+                if fraction_digits > scale {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("value {} has more than scale {} decimal digits", d, scale),
+                    });
+                }
+            }
+            if let Some(precision) = self.precision {
+                // count of digits before the decimal point, without leading zeros
+                let integer_digits = d.trunc().abs().to_string().trim_start_matches('0').len() as u32;
+                if integer_digits + fraction_digits > precision {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("value {} has more than precision {} digits", d, precision),
+                    });
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+}
+
+/// the value of the key in the additional property `key1=value1;key2=value2`.
+/// Other modules use the same convention for their keys.
+pub fn property_value<'a>(property: &'a str, key: &str) -> Option<&'a str> {
+    for pair in property.split(';') {
+        if let Some(pos) = pair.find('=') {
+            if pair[..pos].trim() == key {
+                return Some(&pair[pos + 1..]);
+            }
+        }
+    }
+    //return
+    None
+}
+
+/// the additional property without the key
+pub fn remove_property_key(property: &str, key: &str) -> String {
+    property
+        .split(';')
+        .filter(|pair| match pair.find('=') {
+            Some(pos) => pair[..pos].trim() != key,
+            None => true,
+        })
+        .collect::<Vec<&str>>()
+        .join(";")
+}
+
+/// short text of the value for error messages
+pub fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Integer(i) => i.to_string(),
+        Value::Decimal(d) => d.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bool(b) => {
+            if *b {
+                s!("T")
+            } else {
+                s!("F")
+            }
+        }
+        Value::DateTimeFixedOffset(d) => d.to_rfc3339(),
+        Value::Date(d) => d.to_string(),
+        Value::Time(t) => t.to_string(),
+        Value::SubTable(t) => format!("SubTable with {} rows", t.rows.len()),
+        Value::Null => s!(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_parse_constraints() {
+        // text without = is not a constraint
+        assert!(unwrap!(ColumnConstraints::parse("prop1", &DataType::Integer)).is_none());
+        let c = unwrap!(unwrap!(ColumnConstraints::parse("min=0;max=150;note=x", &DataType::Integer)));
+        assert!(c.check(&Value::Integer(0)).is_ok());
+        assert!(c.check(&Value::Integer(150)).is_ok());
+        assert_eq!(remove_src_loc(c.check(&Value::Integer(151)).unwrap_err()), "Error: value 151 is greater than max 150");
+        assert_eq!(remove_src_loc(c.check(&Value::Integer(-1)).unwrap_err()), "Error: value -1 is less than min 0");
+
+        let err = ColumnConstraints::parse("min=a", &DataType::Integer).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Constraint min for Integer: Failed conversion to integer. invalid digit found in string"
+        );
+    }
+    #[test]
+    pub fn t02_string_and_decimal_constraints() {
+        let c = unwrap!(unwrap!(ColumnConstraints::parse("max_length=5;regex=^[A-Z]", &DataType::String)));
+        assert!(c.check(&Value::String(s!("Abc"))).is_ok());
+        assert_eq!(remove_src_loc(c.check(&Value::String(s!("Abcdef"))).unwrap_err()), "Error: value Abcdef is longer than max_length 5");
+        assert_eq!(remove_src_loc(c.check(&Value::String(s!("abc"))).unwrap_err()), "Error: value abc does not match regex ^[A-Z]");
+        let err = ColumnConstraints::parse("max_length=5", &DataType::Integer).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Constraint max_length is only for String, not for Integer");
+        let err = ColumnConstraints::parse("precision=5", &DataType::Float).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Constraint precision is only for Decimal, not for Float");
+
+        let c = unwrap!(unwrap!(ColumnConstraints::parse("enum=red|green", &DataType::String)));
+        assert!(c.check(&Value::String(s!("red"))).is_ok());
+        assert_eq!(remove_src_loc(c.check(&Value::String(s!("blue"))).unwrap_err()), "Error: value blue is not in enum");
+
+        let c = unwrap!(unwrap!(ColumnConstraints::parse("precision=5;scale=2", &DataType::Decimal)));
+        let d = |x: &str| Value::Decimal(unwrap!(TableRows::from_u8_to_decimal(x.as_bytes())));
+        assert!(c.check(&d("999.99")).is_ok());
+        assert!(c.check(&d("0.5")).is_ok());
+        assert_eq!(remove_src_loc(c.check(&d("1.123")).unwrap_err()), "Error: value 1.123 has more than scale 2 decimal digits");
+        assert_eq!(remove_src_loc(c.check(&d("10000.1")).unwrap_err()), "Error: value 10000.1 has more than precision 5 digits");
+    }
+}
@@ -0,0 +1,382 @@
+// qvs20_dialect_mod
+
+//! QVS20 and QVS21 are the same format, but QVS20 has no sub tables.
+//! A QVS20 file is already a valid QVS21 file. A QVS21 file is valid QVS20 only without sub tables.
+//!
+//! The detector reads the tokens and reports the deepest sub table.
+//! The downgrade to QVS20 rejects sub tables or flattens them:
+//! every sub row is a row with the values of the parent row repeated.
+//! The sub table columns are named with the path like `cities.city`.
+//! A row with an empty sub table is one row with empty sub table fields.
+//! If a row has more SubTable columns, the sub rows are side by side and not multiplied.
+//! The primary_key and unique properties are removed, because the values repeat.
+//!
+//! The upgrade makes sub tables from the flattened columns again.
+//! A sub table is a group of at least 2 consecutive columns with the same prefix before the dot.
+//! A single column like `e.mail` stays a column, so a sub table with only one column does not come back.
+//! If there are sub tables, consecutive rows with the same parent values are one row.
+//! A flat row with all sub table fields empty is an empty sub table, so a sub row
+//! with only empty fields is dropped by the round trip.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// what the downgrade does with sub tables
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubTableDowngrade {
+    /// error if there is a SubTable column
+    Reject,
+    /// one row for every sub row, the sub table columns are named `column.sub_column`
+    Flatten,
+}
+
+/// the dialect of a QVS file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dialect {
+    pub uses_sub_tables: bool,
+    /// 0 without sub tables, 1 for sub tables, 2 for sub tables inside sub tables,...
+    pub max_depth: usize,
+}
+
+impl Dialect {
+    /// the file is valid QVS20
+    pub fn is_qvs20(&self) -> bool {
+        !self.uses_sub_tables
+    }
+}
+
+/// detect QVS20 or QVS21 from the sub table delimiters in the schema and in the rows
+pub fn detect_dialect(input: &str) -> Result<Dialect, Qvs20Error> {
+    let mut max_depth = 0;
+    for token in ReaderForQvs20::new(input.as_bytes()) {
+        if let Token::StartSubTable(delimiter) = token? {
+            max_depth = max_depth.max(depth_for_row_delimiter(delimiter));
+        }
+    }
+    //return
+    Ok(Dialect {
+        uses_sub_tables: max_depth > 0,
+        max_depth,
+    })
+}
+
+/// schema with the sub table columns flattened into the table
+fn flatten_schema(schema: &TableSchema) -> TableSchema {
+    let mut flat = TableSchema::new_simple_strings(0);
+    flat.table_name = schema.table_name.clone();
+    flat.table_description = schema.table_description.clone();
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        match &schema.sub_table_schemas[column] {
+            Some(sub_schema) => {
+                let sub_flat = flatten_schema(sub_schema);
+                for sub_column in 0..sub_flat.column_names.len() {
+                    flat.push_column(
+                        &format!("{}.{}", column_name, sub_flat.column_names[sub_column]),
+                        sub_flat.data_types[sub_column].clone(),
+                        None,
+                        &sub_flat.additional_properties[sub_column],
+                    );
+                }
+            }
+            None => {
+                let property = remove_property_key(&schema.additional_properties[column], "primary_key");
+                let property = remove_property_key(&property, "unique");
+                flat.push_column(column_name, schema.data_types[column].clone(), None, &property);
+            }
+        }
+    }
+    //return
+    flat
+}
+
+/// the empty field of the data type
+fn empty_value(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::String => Value::String(s!()),
+        DataType::SubTable => Value::SubTable(TableRows::default()),
+        _ => Value::Null,
+    }
+}
+
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.is_empty(),
+        Value::SubTable(t) => t.rows.is_empty(),
+        Value::Null => true,
+        _ => false,
+    }
+}
+
+/// flattened sub rows of one SubTable field and the empty fields for the missing sub rows
+struct FlatSubRows {
+    rows: Vec<Vec<Value>>,
+    empty_values: Vec<Value>,
+}
+
+/// the flattened rows of one row, at least one
+fn flatten_row(schema: &TableSchema, row: &Row) -> Vec<Vec<Value>> {
+    let mut sub_rows: Vec<Option<FlatSubRows>> = vec![];
+    let mut count_of_rows = 1;
+    for (column, value) in row.values.iter().enumerate() {
+        match (value, &schema.sub_table_schemas[column]) {
+            (Value::SubTable(sub_table_rows), Some(sub_schema)) => {
+                let flat_rows: Vec<Vec<Value>> = sub_table_rows.rows.iter().flat_map(|r| flatten_row(sub_schema, r)).collect();
+                count_of_rows = count_of_rows.max(flat_rows.len());
+                sub_rows.push(Some(FlatSubRows {
+                    rows: flat_rows,
+                    empty_values: flatten_schema(sub_schema).data_types.iter().map(empty_value).collect(),
+                }));
+            }
+            _ => sub_rows.push(None),
+        }
+    }
+    let mut flat_rows = vec![];
+    for i in 0..count_of_rows {
+        let mut values = vec![];
+        for (column, value) in row.values.iter().enumerate() {
+            match &sub_rows[column] {
+                Some(sub) if i < sub.rows.len() => values.extend(sub.rows[i].iter().cloned()),
+                Some(sub) => values.extend(sub.empty_values.iter().cloned()),
+                None => values.push(value.clone()),
+            }
+        }
+        flat_rows.push(values);
+    }
+    //return
+    flat_rows
+}
+
+/// the columns of this level: direct column index or the name of the sub table and the columns of it
+enum NestedColumn {
+    Direct(usize),
+    SubTable(String, Vec<usize>),
+}
+
+/// the sub table name of a flattened column name like `cities.city`
+fn flattened_prefix(column_name: &str) -> Option<&str> {
+    match column_name.find('.') {
+        Some(pos) if pos > 0 && pos + 1 < column_name.len() => Some(&column_name[..pos]),
+        _ => None,
+    }
+}
+
+/// a sub table is a group of at least 2 consecutive columns with the same prefix
+/// and no other column with the name of the prefix, else the columns stay as they are
+fn nested_columns(flat: &TableSchema) -> Vec<NestedColumn> {
+    let mut columns: Vec<NestedColumn> = vec![];
+    let mut column = 0;
+    while column < flat.column_names.len() {
+        let mut end = column + 1;
+        if let Some(name) = flattened_prefix(&flat.column_names[column]) {
+            while end < flat.column_names.len() && flattened_prefix(&flat.column_names[end]) == Some(name) {
+                end += 1;
+            }
+            if end - column >= 2 && !flat.column_names.iter().any(|n| n == name) {
+                columns.push(NestedColumn::SubTable(s!(name), (column..end).collect()));
+                column = end;
+                continue;
+            }
+        }
+        for direct in column..end {
+            columns.push(NestedColumn::Direct(direct));
+        }
+        column = end;
+    }
+    //return
+    columns
+}
+
+/// the flat schema of the sub table columns with the names without the prefix
+fn sub_flat_schema(flat: &TableSchema, name: &str, sub_columns: &[usize]) -> TableSchema {
+    let mut sub_flat = TableSchema::new_simple_strings(0);
+    sub_flat.table_name = s!(name);
+    sub_flat.table_description = s!();
+    for &column in sub_columns.iter() {
+        sub_flat.push_column(
+            &flat.column_names[column][name.len() + 1..],
+            flat.data_types[column].clone(),
+            None,
+            &flat.additional_properties[column],
+        );
+    }
+    //return
+    sub_flat
+}
+
+fn nest_schema(flat: &TableSchema) -> TableSchema {
+    let mut schema = TableSchema::new_simple_strings(0);
+    schema.table_name = flat.table_name.clone();
+    schema.table_description = flat.table_description.clone();
+    for nested_column in nested_columns(flat) {
+        match nested_column {
+            NestedColumn::Direct(column) => schema.push_column(
+                &flat.column_names[column],
+                flat.data_types[column].clone(),
+                None,
+                &flat.additional_properties[column],
+            ),
+            NestedColumn::SubTable(name, sub_columns) => {
+                let sub_schema = nest_schema(&sub_flat_schema(flat, &name, &sub_columns));
+                schema.push_column(&name, DataType::SubTable, Some(sub_schema), "");
+            }
+        }
+    }
+    //return
+    schema
+}
+
+fn nest_rows(flat: &TableSchema, rows: &[Row]) -> Vec<Row> {
+    let columns = nested_columns(flat);
+    // without sub tables equal rows are different rows
+    if !columns.iter().any(|c| matches!(c, NestedColumn::SubTable(_, _))) {
+        return rows.to_vec();
+    }
+    let direct_values = |row: &Row| -> Vec<Value> {
+        columns
+            .iter()
+            .filter_map(|c| match c {
+                NestedColumn::Direct(column) => Some(row.values[*column].clone()),
+                NestedColumn::SubTable(_, _) => None,
+            })
+            .collect()
+    };
+    let mut nested_rows = vec![];
+    let mut start = 0;
+    while start < rows.len() {
+        let parent_values = direct_values(&rows[start]);
+        let mut end = start + 1;
+        while end < rows.len() && direct_values(&rows[end]) == parent_values {
+            end += 1;
+        }
+        let mut values = vec![];
+        for nested_column in columns.iter() {
+            match nested_column {
+                NestedColumn::Direct(column) => values.push(rows[start].values[*column].clone()),
+                NestedColumn::SubTable(name, sub_columns) => {
+                    let sub_flat = sub_flat_schema(flat, name, sub_columns);
+                    let sub_rows: Vec<Row> = rows[start..end]
+                        .iter()
+                        .map(|row| Row {
+                            values: sub_columns.iter().map(|c| row.values[*c].clone()).collect(),
+                        })
+                        .filter(|row| !row.values.iter().all(is_empty_value))
+                        .collect();
+                    let mut sub_table_rows = TableRows::default();
+                    sub_table_rows.rows = nest_rows(&sub_flat, &sub_rows);
+                    values.push(Value::SubTable(sub_table_rows));
+                }
+            }
+        }
+        nested_rows.push(Row { values });
+        start = end;
+    }
+    //return
+    nested_rows
+}
+
+impl Table {
+    /// the table without SubTable columns, valid for QVS20
+    pub fn downgrade_to_qvs20(&self, mode: SubTableDowngrade) -> Result<Table, Qvs20Error> {
+        if mode == SubTableDowngrade::Reject {
+            if let Some(column) = self.schema.data_types.iter().position(|d| *d == DataType::SubTable) {
+                return Err(Qvs20Error::Error {
+                    msg: format!(
+                        "Column {} is a SubTable. QVS20 does not have sub tables.",
+                        self.schema.column_names[column]
+                    ),
+                });
+            }
+            return Ok(self.clone());
+        }
+        let mut schema = flatten_schema(&self.schema);
+        schema.set_depth(0)?;
+        let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+        for row in self.table_rows.rows.iter() {
+            for values in flatten_row(&self.schema, row) {
+                table_rows.rows.push(Row { values });
+            }
+        }
+        //return
+        Ok(Table { schema, table_rows })
+    }
+
+    /// the QVS21 table with sub tables from the flattened columns named `column.sub_column`
+    pub fn upgrade_flattened(&self) -> Result<Table, Qvs20Error> {
+        let mut schema = nest_schema(&self.schema);
+        schema.set_depth(0)?;
+        let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+        table_rows.rows = nest_rows(&self.schema, &self.table_rows.rows);
+        table_rows.set_depth(0)?;
+        //return
+        Ok(Table { schema, table_rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_detect_downgrade_upgrade() {
+        let s = r"[T][countries][description]
+[String][Integer][SubTable]
+[][][1[U][cities][]1[String][SubTable]1[][2[U][streets][]2[String][Integer]2[][]2[][]2[street][number]2]1[][]1[city][streets]1]
+[primary_key=1][min=0][]
+[country][population][cities]
+[Slovenia][2000000][1[Ljubljana][2[Čopova][1]2[Trubarjeva][2]2]1[Koper][]1]
+[Croatia][][]
+";
+        assert_eq!(
+            unwrap!(detect_dialect(s)),
+            Dialect {
+                uses_sub_tables: true,
+                max_depth: 2
+            }
+        );
+        let table = unwrap!(Table::from_qvs20_str_with_schema(s));
+        let err = table.downgrade_to_qvs20(SubTableDowngrade::Reject).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Column cities is a SubTable. QVS20 does not have sub tables.");
+
+        let flat = unwrap!(table.downgrade_to_qvs20(SubTableDowngrade::Flatten));
+        let flat_text = flat.write_table();
+        assert_eq!(
+            flat_text,
+            r"[T][countries][description]
+[String][Integer][String][String][Integer]
+[][][][][]
+[][min=0][][][]
+[country][population][cities.city][cities.streets.street][cities.streets.number]
+[Slovenia][2000000][Ljubljana][Čopova][1]
+[Slovenia][2000000][Ljubljana][Trubarjeva][2]
+[Slovenia][2000000][Koper][][]
+[Croatia][][][][]
+"
+        );
+        assert!(unwrap!(detect_dialect(&flat_text)).is_qvs20());
+
+        let upgraded = unwrap!(flat.upgrade_flattened());
+        assert_eq!(upgraded.write_table(), table.write_table().replace("[primary_key=1]", "[]"));
+
+        // a single column with a dot is not a sub table
+        let s = r"[T][contacts][]
+[String][String][String]
+[][][]
+[][][]
+[name][e.mail][.hidden]
+[Ana][ana@example.com][x]
+";
+        let flat = unwrap!(Table::from_qvs20_str_with_schema(s));
+        assert_eq!(unwrap!(flat.upgrade_flattened()).write_table(), s);
+
+        // without sub tables the equal rows stay
+        let s = "[T][log][]\n[String][String]\n[][]\n[][]\n[level][message]\n[info][started]\n[info][started]\n";
+        let flat = unwrap!(Table::from_qvs20_str_with_schema(s));
+        let upgraded = unwrap!(flat.upgrade_flattened());
+        assert_eq!(upgraded.table_rows.rows.len(), 2);
+        assert_eq!(upgraded.write_table(), s);
+    }
+}
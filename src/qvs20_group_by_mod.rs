@@ -0,0 +1,322 @@
+// qvs20_group_by_mod
+
+//! Grouping is the natural way to make sub tables.
+//! group_by() returns one row per key, the other columns go into a SubTable column.
+//! add_aggregates() adds typed columns computed from the rows of a SubTable column.
+//! It works also for sub tables read from a QVS21 file.
+//!
+//! Null values are skipped by sum, avg, min and max. Without values the result is Null.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+/// Aggregate function over a column of the sub table.
+#[derive(Clone, Copy, Debug)]
+pub enum Aggregate<'a> {
+    /// count of rows: Integer
+    Count,
+    /// Integer, Decimal or Float
+    Sum(&'a str),
+    /// Decimal for Integer and Decimal, Float for Float
+    Avg(&'a str),
+    Min(&'a str),
+    Max(&'a str),
+    First(&'a str),
+    Last(&'a str),
+}
+
+impl<'a> Aggregate<'a> {
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum(_) => "sum",
+            Aggregate::Avg(_) => "avg",
+            Aggregate::Min(_) => "min",
+            Aggregate::Max(_) => "max",
+            Aggregate::First(_) => "first",
+            Aggregate::Last(_) => "last",
+        }
+    }
+    fn column_name(&self) -> Option<&'a str> {
+        match self {
+            Aggregate::Count => None,
+            Aggregate::Sum(c) | Aggregate::Avg(c) | Aggregate::Min(c) | Aggregate::Max(c) | Aggregate::First(c) | Aggregate::Last(c) => Some(c),
+        }
+    }
+}
+
+/// data type of the result and the column of the sub table
+fn aggregate_data_type(sub_schema: &TableSchema, aggregate: &Aggregate) -> Result<(DataType, usize), Qvs20Error> {
+    let column_name = match aggregate.column_name() {
+        Some(c) => c,
+        None => return Ok((DataType::Integer, 0)),
+    };
+    let column = match sub_schema.column_index(column_name) {
+        Some(c) => c,
+        None => {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} does not have column {}.", sub_schema.table_name, column_name),
+            })
+        }
+    };
+    let data_type = sub_schema.data_types[column].clone();
+    let is_number = matches!(data_type, DataType::Integer | DataType::Decimal | DataType::Float);
+    let result_type = match aggregate {
+        Aggregate::Sum(_) | Aggregate::Avg(_) if !is_number => {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "Aggregate {} needs an Integer, Decimal or Float column. Column {} is {}.",
+                    aggregate.name(),
+                    column_name,
+                    data_type
+                ),
+            })
+        }
+        _ if data_type == DataType::SubTable => {
+            return Err(Qvs20Error::Error {
+                msg: format!("Aggregate {} cannot use the SubTable column {}.", aggregate.name(), column_name),
+            })
+        }
+        Aggregate::Avg(_) if data_type == DataType::Integer => DataType::Decimal,
+        _ => data_type,
+    };
+    //return
+    Ok((result_type, column))
+}
+
+fn aggregate_value(aggregate: &Aggregate, data_type: &DataType, table_rows: &TableRows, column: usize) -> Result<Value, Qvs20Error> {
+    let values = table_rows.rows.iter().map(|row| &row.values[column]).filter(|v| !matches!(v, Value::Null));
+    let value = match aggregate {
+        Aggregate::Count => Value::Integer(table_rows.rows.len() as i64),
+        Aggregate::First(_) => table_rows.rows.first().map_or(Value::Null, |row| row.values[column].clone()),
+        Aggregate::Last(_) => table_rows.rows.last().map_or(Value::Null, |row| row.values[column].clone()),
+        Aggregate::Min(_) => values.min().cloned().unwrap_or(Value::Null),
+        Aggregate::Max(_) => values.max().cloned().unwrap_or(Value::Null),
+        Aggregate::Sum(_) | Aggregate::Avg(_) => {
+            let values: Vec<&Value> = values.collect();
+            if values.is_empty() {
+                return Ok(Value::Null);
+            }
+            let overflow = || Qvs20Error::Error {
+                msg: format!("Aggregate {} overflows {}.", aggregate.name(), data_type),
+            };
+            let sum = match values[0] {
+                Value::Integer(_) => {
+                    let mut sum: i64 = 0;
+                    for v in values.iter() {
+                        if let Value::Integer(i) = v {
+                            sum = sum.checked_add(*i).ok_or_else(overflow)?;
+                        }
+                    }
+                    Value::Integer(sum)
+                }
+                Value::Decimal(_) => {
+                    let mut sum = Decimal::ZERO;
+                    for v in values.iter() {
+                        if let Value::Decimal(d) = v {
+                            sum = sum.checked_add(*d).ok_or_else(overflow)?;
+                        }
+                    }
+                    Value::Decimal(sum)
+                }
+                _ => Value::Float(values.iter().map(|v| if let Value::Float(f) = v { *f } else { 0.0 }).sum()),
+            };
+            match (aggregate, sum) {
+                (Aggregate::Avg(_), Value::Integer(i)) => Value::Decimal(Decimal::from(i) / Decimal::from(values.len())),
+                (Aggregate::Avg(_), Value::Decimal(d)) => Value::Decimal(d / Decimal::from(values.len())),
+                (Aggregate::Avg(_), Value::Float(f)) => Value::Float(f / values.len() as f64),
+                (_, sum) => sum,
+            }
+        }
+    };
+    //return
+    Ok(value)
+}
+
+impl Table {
+    /// one row for every key in the order of the first appearance.
+    /// The other columns are moved to the new SubTable column.
+    /// The key columns become the primary key, but not if a key has a Null value,
+    /// because a primary key cannot have Null. The Null is still one group.
+    pub fn group_by(&self, key_columns: &[&str], sub_table_column: &str) -> Result<Table, Qvs20Error> {
+        let key = self.column_indexes(key_columns)?;
+        for column in key.iter() {
+            if self.schema.data_types[*column] == DataType::SubTable {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Key column {} cannot be a SubTable.", self.schema.column_names[*column]),
+                });
+            }
+        }
+        if key_columns.contains(&sub_table_column) {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} already has column {}.", self.schema.table_name, sub_table_column),
+            });
+        }
+        let rest: Vec<usize> = (0..self.schema.column_names.len()).filter(|c| !key.contains(c)).collect();
+        // the primary key stays in the sub table only if all key columns are there
+        let primary_key = self.schema.primary_key_columns()?;
+        let whole_key = primary_key.iter().all(|c| rest.contains(c));
+
+        let mut sub_schema = TableSchema::new_simple_strings(0);
+        sub_schema.table_name = s!(sub_table_column);
+        sub_schema.table_description = s!();
+        for column in rest.iter() {
+            let mut additional_property = self.schema.additional_properties[*column].clone();
+            if !whole_key {
+                additional_property = remove_property_key(&additional_property, "primary_key");
+            }
+            sub_schema.push_column(
+                &self.schema.column_names[*column],
+                self.schema.data_types[*column].clone(),
+                self.schema.sub_table_schemas[*column].clone(),
+                &additional_property,
+            );
+        }
+        sub_schema.set_depth(1)?;
+
+        let null_in_key = self.table_rows.rows.iter().any(|row| key.iter().any(|c| row.values[*c] == Value::Null));
+        let mut schema = TableSchema::new_simple_strings(0);
+        schema.table_name = self.schema.table_name.clone();
+        schema.table_description = self.schema.table_description.clone();
+        for (position, column) in key.iter().enumerate() {
+            let additional_property = remove_property_key(&self.schema.additional_properties[*column], "primary_key");
+            let additional_property = remove_property_key(&additional_property, "unique");
+            let additional_property = if null_in_key {
+                additional_property
+            } else if additional_property.is_empty() {
+                format!("primary_key={}", position + 1)
+            } else {
+                format!("{};primary_key={}", additional_property, position + 1)
+            };
+            schema.push_column(
+                &self.schema.column_names[*column],
+                self.schema.data_types[*column].clone(),
+                None,
+                &additional_property,
+            );
+        }
+        schema.push_column(sub_table_column, DataType::SubTable, Some(sub_schema.clone()), "");
+
+        let mut table_rows = TableRows::default();
+        table_rows.table_name = self.table_rows.table_name.clone();
+        table_rows.row_delimiter = b'\n';
+        let mut groups: HashMap<RowKey, usize> = HashMap::new();
+        for row in self.table_rows.rows.iter() {
+            let group = *groups.entry(row_key(row, &key)).or_insert_with(|| {
+                let mut sub_table_rows = TableRows::default();
+                sub_table_rows.table_name = s!(sub_table_column);
+                sub_table_rows.row_delimiter = sub_schema.row_delimiter;
+                let mut values: Vec<Value> = key.iter().map(|c| row.values[*c].clone()).collect();
+                values.push(Value::SubTable(sub_table_rows));
+                table_rows.rows.push(Row { values });
+                table_rows.rows.len() - 1
+            });
+            let mut values = vec![];
+            for column in rest.iter() {
+                let mut value = row.values[*column].clone();
+                if let Value::SubTable(sub_table_rows) = &mut value {
+                    sub_table_rows.set_depth(2)?;
+                }
+                values.push(value);
+            }
+            if let Some(Value::SubTable(sub_table_rows)) = table_rows.rows[group].values.last_mut() {
+                sub_table_rows.rows.push(Row { values });
+            }
+        }
+        //return
+        Ok(Table { schema, table_rows })
+    }
+
+    /// new table with added columns computed from the rows of the SubTable column
+    pub fn add_aggregates(&self, sub_table_column: &str, aggregates: &[(&str, Aggregate)]) -> Result<Table, Qvs20Error> {
+        let column = self.column_indexes(&[sub_table_column])?[0];
+        let sub_schema = match &self.schema.sub_table_schemas[column] {
+            Some(s) => s,
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Column {} is not a SubTable.", sub_table_column),
+                })
+            }
+        };
+        let mut table = self.clone();
+        for (column_name, aggregate) in aggregates.iter() {
+            if table.schema.column_index(column_name).is_some() {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Table {} already has column {}.", self.schema.table_name, column_name),
+                });
+            }
+            let (data_type, sub_column) = aggregate_data_type(sub_schema, aggregate)?;
+            for row in table.table_rows.rows.iter_mut() {
+                let value = match &row.values[column] {
+                    Value::SubTable(sub_table_rows) => aggregate_value(aggregate, &data_type, sub_table_rows, sub_column)?,
+                    _ => Value::Null,
+                };
+                row.values.push(value);
+            }
+            table.schema.push_column(column_name, data_type, None, "");
+        }
+        //return
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_group_by_and_aggregates() {
+        let s = r"[T][cities][description]
+[String][String][Integer]
+[][][]
+[][primary_key=1][min=0]
+[country][city][population]
+[Slovenia][Ljubljana][300000]
+[Italia][Milano][1400000]
+[Slovenia][Koper][]
+[Slovenia][Maribor][95000]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let grouped = unwrap!(table.group_by(&["country"], "cities"));
+        assert_eq!(
+            grouped.write_table(),
+            "[T][cities][description]\n[String][SubTable]\n[][1[U][cities][]1[String][Integer]1[][]1[primary_key=1][min=0]1[city][population]1]\n[primary_key=1][]\n[country][cities]\n[Slovenia][1[Ljubljana][300000]1[Koper][]1[Maribor][95000]1]\n[Italia][1[Milano][1400000]1]\n"
+        );
+
+        // a Null key is a group, but not a primary key
+        let by_population = unwrap!(table.group_by(&["population"], "cities"));
+        assert_eq!(by_population.table_rows.rows.len(), 4);
+        assert_eq!(by_population.schema.additional_properties[0], "min=0");
+        assert!(Table::from_qvs20_str_with_schema(&by_population.write_table()).is_ok());
+
+        let aggregated = unwrap!(grouped.add_aggregates(
+            "cities",
+            &[
+                ("count", Aggregate::Count),
+                ("total", Aggregate::Sum("population")),
+                ("average", Aggregate::Avg("population")),
+                ("largest", Aggregate::Max("population")),
+                ("first_city", Aggregate::First("city")),
+            ]
+        ));
+        let aggregated = unwrap!(aggregated.select(&["country", "count", "total", "average", "largest", "first_city"]));
+        assert_eq!(
+            aggregated.write_table(),
+            "[T][cities][description]\n[String][Integer][Integer][Decimal][Integer][String]\n[][][][][][]\n[primary_key=1][][][][][]\n[country][count][total][average][largest][first_city]\n[Slovenia][3][395000][197500][300000][Ljubljana]\n[Italia][1][1400000][1400000][1400000][Milano]\n"
+        );
+
+        let err = grouped.add_aggregates("cities", &[("x", Aggregate::Sum("city"))]).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Aggregate sum needs an Integer, Decimal or Float column. Column city is String."
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// qvs20_json_schema_mod
+
+//! JSON Schema and OpenAPI export of the TableSchema.
+//! The QVS21 schema is the single source of truth and the JSON schemas are generated from it.
+//!
+//! The JSON representation of a row is an object with one property for every column:
+//!
+//! - String is a string, Integer is an integer, Float is a number, Bool is a boolean
+//! - Decimal is a string, because a JSON number would lose the exact digits
+//! - Date and DateTimeFixedOffset are strings with the format `date` and `date-time`
+//! - Time is a string with a pattern for the local time, because the format `time` requires an offset
+//! - SubTable is an array of row objects of the sub table
+//! - empty fields of the other data types are `null`
+//!
+//! The constraints from the 4th schema row become `minimum`, `maximum`, `maxLength`, `pattern` and `enum`.
+//! OpenAPI 3.1 uses the same JSON Schema, so the components differ only in the references for sub tables.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use serde_json::{json, Map};
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+const DECIMAL_PATTERN: &str = r"^-?[0-9]+(\.[0-9]+)?$";
+/// local time without offset like `08:30:00` or `08:30:00.5`
+const TIME_PATTERN: &str = r"^([01][0-9]|2[0-3]):[0-5][0-9](:[0-5][0-9](\.[0-9]+)?)?$";
+
+/// the JSON value of the row representation. SubTable needs the schema for the objects.
+pub fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Integer(i) => json!(i),
+        Value::Float(f) => json!(f),
+        Value::Bool(b) => json!(b),
+        Value::Null => serde_json::Value::Null,
+        value => json!(value_to_string(value)),
+    }
+}
+
+fn json_error(e: serde_json::Error) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("JSON error: {}", e),
+    }
+}
+
+/// component names in OpenAPI can have only letters, digits and `.-_`
+fn component_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || ".-_".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// schema of one column without the sub table
+fn column_json_schema(data_type: &DataType, constraints: &Option<ColumnConstraints>) -> Map<String, serde_json::Value> {
+    let mut schema = Map::new();
+    let (json_type, format) = match data_type {
+        DataType::String => ("string", None),
+        DataType::Integer => ("integer", None),
+        DataType::Decimal => ("string", None),
+        DataType::Float => ("number", None),
+        DataType::Bool => ("boolean", None),
+        DataType::DateTimeFixedOffset => ("string", Some("date-time")),
+        DataType::Date => ("string", Some("date")),
+        DataType::Time => ("string", None),
+        DataType::SubTable => ("array", None),
+    };
+    if is_nullable(data_type) {
+        schema.insert(s!("type"), json!([json_type, "null"]));
+    } else {
+        schema.insert(s!("type"), json!(json_type));
+    }
+    if let Some(format) = format {
+        schema.insert(s!("format"), json!(format));
+    }
+    match data_type {
+        DataType::Decimal => {
+            schema.insert(s!("pattern"), json!(DECIMAL_PATTERN));
+        }
+        DataType::Time => {
+            schema.insert(s!("pattern"), json!(TIME_PATTERN));
+        }
+        _ => (),
+    }
+    if let Some(constraints) = constraints {
+        // JSON Schema has minimum and maximum only for numbers
+        if let DataType::Integer | DataType::Float = data_type {
+            if let Some(min) = &constraints.min {
+                schema.insert(s!("minimum"), value_to_json(min));
+            }
+            if let Some(max) = &constraints.max {
+                schema.insert(s!("maximum"), value_to_json(max));
+            }
+        }
+        if let Some(max_length) = constraints.max_length {
+            schema.insert(s!("maxLength"), json!(max_length));
+        }
+        if let Some(regex) = &constraints.regex {
+            schema.insert(s!("pattern"), json!(regex.as_str()));
+        }
+        if let Some(allowed_values) = &constraints.allowed_values {
+            let mut values: Vec<serde_json::Value> = allowed_values.iter().map(value_to_json).collect();
+            if is_nullable(data_type) {
+                values.push(serde_json::Value::Null);
+            }
+            schema.insert(s!("enum"), serde_json::Value::Array(values));
+        }
+    }
+    //return
+    schema
+}
+
+/// object schema of the row.
+/// For OpenAPI the sub tables are added to components and referenced.
+fn row_json_schema(
+    schema: &TableSchema,
+    component_prefix: Option<&str>,
+    components: &mut Map<String, serde_json::Value>,
+) -> Result<Map<String, serde_json::Value>, Qvs20Error> {
+    let constraints = schema.column_constraints()?;
+    let mut properties = Map::new();
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        let mut column_schema = column_json_schema(&schema.data_types[column], &constraints[column]);
+        if let Some(sub_schema) = &schema.sub_table_schemas[column] {
+            let sub_row_schema = match component_prefix {
+                Some(prefix) => {
+                    let sub_name = component_name(&format!("{}_{}", prefix, column_name));
+                    let sub_row_schema = row_json_schema(sub_schema, Some(&sub_name), components)?;
+                    components.insert(sub_name.clone(), serde_json::Value::Object(sub_row_schema));
+                    json!({ "$ref": format!("#/components/schemas/{}", sub_name) })
+                }
+                None => serde_json::Value::Object(row_json_schema(sub_schema, None, components)?),
+            };
+            column_schema.insert(s!("items"), sub_row_schema);
+        }
+        properties.insert(column_name.clone(), serde_json::Value::Object(column_schema));
+    }
+    let mut row_schema = Map::new();
+    row_schema.insert(s!("title"), json!(schema.table_name));
+    if !schema.table_description.is_empty() {
+        row_schema.insert(s!("description"), json!(schema.table_description));
+    }
+    row_schema.insert(s!("type"), json!("object"));
+    row_schema.insert(s!("properties"), serde_json::Value::Object(properties));
+    // every row has all the columns, empty fields are null
+    row_schema.insert(s!("required"), json!(schema.column_names));
+    row_schema.insert(s!("additionalProperties"), json!(false));
+    //return
+    Ok(row_schema)
+}
+
+impl TableSchema {
+    /// JSON Schema (draft 2020-12) of the JSON representation of one row
+    pub fn to_json_schema(&self) -> Result<String, Qvs20Error> {
+        let mut json_schema = Map::new();
+        json_schema.insert(s!("$schema"), json!(JSON_SCHEMA_DIALECT));
+        json_schema.extend(row_json_schema(self, None, &mut Map::new())?);
+        //return
+        serde_json::to_string_pretty(&serde_json::Value::Object(json_schema)).map_err(json_error)
+    }
+
+    /// OpenAPI 3.1 `components` with the row schema.
+    /// Every sub table is a separate component named `{table}_{column}`.
+    pub fn to_openapi_components(&self) -> Result<String, Qvs20Error> {
+        let name = component_name(&self.table_name);
+        let mut schemas = Map::new();
+        let row_schema = row_json_schema(self, Some(&name), &mut schemas)?;
+        // the main component first
+        let mut components = Map::new();
+        components.insert(name, serde_json::Value::Object(row_schema));
+        components.extend(schemas);
+        //return
+        serde_json::to_string_pretty(&json!({ "components": { "schemas": components } })).map_err(json_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_json_schema_and_openapi() {
+        let s = r"[S][countries][European countries]
+[String][Integer][Decimal][Date][SubTable]
+[][][][][1[U][cities][]1[String]1[]1[]1[city]1]
+[max_length=20;primary_key=1][min=0][][enum=2020-01-01|2021-01-01][]
+[country][population][area][founded][cities]
+";
+        let schema = unwrap!(TableSchema::schema_from_qvs20_str(s));
+        let json_schema: serde_json::Value = unwrap!(serde_json::from_str(&unwrap!(schema.to_json_schema())));
+        assert_eq!(
+            json_schema,
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "title": "countries",
+                "description": "European countries",
+                "type": "object",
+                "properties": {
+                    "country": { "type": "string", "maxLength": 20 },
+                    "population": { "type": ["integer", "null"], "minimum": 0 },
+                    "area": { "type": ["string", "null"], "pattern": r"^-?[0-9]+(\.[0-9]+)?$" },
+                    "founded": { "type": ["string", "null"], "format": "date", "enum": ["2020-01-01", "2021-01-01", null] },
+                    "cities": {
+                        "type": "array",
+                        "items": {
+                            "title": "cities",
+                            "type": "object",
+                            "properties": { "city": { "type": "string" } },
+                            "required": ["city"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["country", "population", "area", "founded", "cities"],
+                "additionalProperties": false
+            })
+        );
+        let openapi: serde_json::Value = unwrap!(serde_json::from_str(&unwrap!(schema.to_openapi_components())));
+        assert_eq!(
+            openapi["components"]["schemas"]["countries"]["properties"]["cities"]["items"],
+            json!({ "$ref": "#/components/schemas/countries_cities" })
+        );
+        assert_eq!(openapi["components"]["schemas"]["countries_cities"]["title"], json!("cities"));
+
+        let schema = unwrap!(TableSchema::schema_from_qvs20_str("[S][shops][]\n[Time]\n[]\n[]\n[opens]\n"));
+        let json_schema: serde_json::Value = unwrap!(serde_json::from_str(&unwrap!(schema.to_json_schema())));
+        assert_eq!(
+            json_schema["properties"]["opens"],
+            json!({ "type": ["string", "null"], "pattern": TIME_PATTERN })
+        );
+    }
+}
@@ -0,0 +1,415 @@
+// qvs20_ndjson_mod
+
+//! Streaming bridge between NDJSON (newline-delimited JSON) and QVS21.
+//! The rows are converted one by one, the Table is never in memory.
+//! The QVS21 input is read line by line: the 5 schema rows and then one data row per line.
+//!
+//! Every line is one JSON object with the column names as keys, like in `TableSchema::to_json_schema()`.
+//! Missing keys and `null` are empty fields. Keys that are not columns are ignored.
+//! A String column accepts any JSON value, other than strings are stored as JSON text.
+//! The other data types must match, else the error has the line number of the input.
+//! Empty lines are skipped. Float NaN and infinity are rejected, because JSON does not have them.
+//!
+//! Without a schema, the schema is inferred from the first records:
+//! the columns are in the order of the first appearance of the keys.
+//! Strings that look like Date, Time or DateTimeFixedOffset get that data type.
+//! Arrays of objects become sub tables.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_json_schema_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+use crate::qvs20_type_inference_mod::*;
+use crate::qvs20_writer_mod::*;
+
+use serde_json::Map;
+use std::io::{BufRead, Write};
+
+fn line_error(line_number: usize, msg: &str) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("Line {}: {}", line_number, msg),
+    }
+}
+
+/// the JSON object of one line
+fn parse_line(line: &str, line_number: usize) -> Result<Map<String, serde_json::Value>, Qvs20Error> {
+    match serde_json::from_str(line) {
+        Ok(serde_json::Value::Object(object)) => Ok(object),
+        Ok(_) => Err(line_error(line_number, "The line is not a JSON object.")),
+        Err(e) => Err(line_error(line_number, &e.to_string())),
+    }
+}
+
+/// value of the data type from the JSON value
+fn json_to_value(json: &serde_json::Value, schema: &TableSchema, column: usize) -> Result<Value, String> {
+    let data_type = &schema.data_types[column];
+    let mismatch = || {
+        format!(
+            "column {}: expected {} and found {}",
+            schema.column_names[column], data_type, json
+        )
+    };
+    let value = match (data_type, json) {
+        (DataType::SubTable, serde_json::Value::Null) => Value::SubTable(TableRows::default()),
+        (DataType::String, serde_json::Value::Null) => Value::String(s!()),
+        (_, serde_json::Value::Null) => Value::Null,
+        (DataType::String, serde_json::Value::String(s)) => Value::String(s.clone()),
+        (DataType::String, json) => Value::String(json.to_string()),
+        (DataType::Integer, serde_json::Value::Number(n)) => Value::Integer(n.as_i64().ok_or_else(mismatch)?),
+        (DataType::Float, serde_json::Value::Number(n)) => Value::Float(n.as_f64().ok_or_else(mismatch)?),
+        (DataType::Decimal, serde_json::Value::Number(n)) => {
+            TableRows::value_from_str(&n.to_string(), data_type).map_err(|_| mismatch())?
+        }
+        (DataType::Bool, serde_json::Value::Bool(b)) => Value::Bool(*b),
+        (DataType::SubTable, serde_json::Value::Array(array)) => {
+            let sub_schema = match &schema.sub_table_schemas[column] {
+                Some(s) => s,
+                None => return Err(mismatch()),
+            };
+            let mut sub_table_rows = TableRows::default();
+            for element in array.iter() {
+                match element {
+                    serde_json::Value::Object(object) => sub_table_rows.rows.push(json_to_row(object, sub_schema)?),
+                    _ => return Err(mismatch()),
+                }
+            }
+            Value::SubTable(sub_table_rows)
+        }
+        (DataType::Decimal | DataType::DateTimeFixedOffset | DataType::Date | DataType::Time, serde_json::Value::String(s)) => {
+            TableRows::value_from_str(s, data_type).map_err(|_| mismatch())?
+        }
+        _ => return Err(mismatch()),
+    };
+    //return
+    Ok(value)
+}
+
+fn json_to_row(object: &Map<String, serde_json::Value>, schema: &TableSchema) -> Result<Row, String> {
+    let mut values = vec![];
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        let json = object.get(column_name).unwrap_or(&serde_json::Value::Null);
+        values.push(json_to_value(json, schema, column)?);
+    }
+    //return
+    Ok(Row { values })
+}
+
+/// JSON has no NaN and infinity, they would come back as null
+fn row_to_json(row: &Row, schema: &TableSchema) -> Result<serde_json::Value, String> {
+    let mut object = Map::new();
+    for (column, value) in row.values.iter().enumerate() {
+        let json = match (value, &schema.sub_table_schemas[column]) {
+            (Value::SubTable(sub_table_rows), Some(sub_schema)) => {
+                let mut array = vec![];
+                for sub_row in sub_table_rows.rows.iter() {
+                    array.push(row_to_json(sub_row, sub_schema)?);
+                }
+                serde_json::Value::Array(array)
+            }
+            (Value::Float(f), _) if !f.is_finite() => {
+                return Err(format!(
+                    "column {}: Float {} cannot be written to JSON",
+                    schema.column_names[column], f
+                ));
+            }
+            (value, _) => value_to_json(value),
+        };
+        object.insert(schema.column_names[column].clone(), json);
+    }
+    //return
+    Ok(serde_json::Value::Object(object))
+}
+
+/// data type of one JSON value, None for null
+fn infer_json(json: &serde_json::Value) -> Option<DataType> {
+    match json {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(_) => Some(DataType::Bool),
+        serde_json::Value::Number(n) if n.is_i64() => Some(DataType::Integer),
+        serde_json::Value::Number(_) => Some(DataType::Float),
+        serde_json::Value::String(s) => match infer_text(s) {
+            data_type @ (DataType::Date | DataType::Time | DataType::DateTimeFixedOffset) => Some(data_type),
+            _ => Some(DataType::String),
+        },
+        serde_json::Value::Array(array) if array.iter().all(|e| e.is_object()) => Some(DataType::SubTable),
+        _ => Some(DataType::String),
+    }
+}
+
+/// schema from the objects. Columns in the order of the first appearance of the keys.
+fn infer_schema(table_name: &str, objects: &[&Map<String, serde_json::Value>]) -> TableSchema {
+    let mut schema = TableSchema::new_simple_strings(0);
+    schema.table_name = s!(table_name);
+    schema.table_description = s!();
+    let mut column_names: Vec<&String> = vec![];
+    for object in objects.iter() {
+        for key in object.keys() {
+            if !column_names.contains(&key) {
+                column_names.push(key);
+            }
+        }
+    }
+    for column_name in column_names {
+        let values: Vec<&serde_json::Value> = objects.iter().filter_map(|o| o.get(column_name)).collect();
+        let mut data_type: Option<DataType> = None;
+        for json_data_type in values.iter().filter_map(|v| infer_json(v)) {
+            data_type = Some(match (data_type, json_data_type) {
+                (None, d) => d,
+                (Some(DataType::SubTable), DataType::SubTable) => DataType::SubTable,
+                (Some(DataType::SubTable), _) | (Some(_), DataType::SubTable) => DataType::String,
+                (Some(a), b) => wider_data_type(&a, &b),
+            });
+        }
+        let data_type = data_type.unwrap_or(DataType::String);
+        let sub_schema = if data_type == DataType::SubTable {
+            let sub_objects: Vec<&Map<String, serde_json::Value>> = values
+                .iter()
+                .filter_map(|v| v.as_array())
+                .flatten()
+                .filter_map(|e| e.as_object())
+                .collect();
+            Some(infer_schema(column_name, &sub_objects))
+        } else {
+            None
+        };
+        schema.push_column(column_name, data_type, sub_schema, "");
+    }
+    //return
+    schema
+}
+
+/// the schema and the rows as `[T]`, one row at a time
+struct QvsRowWriter<W: Write> {
+    writer: W,
+    schema: TableSchema,
+    constraints: SchemaConstraints,
+    /// the keys of all rows written so far
+    unique_keys: UniqueKeys,
+    /// the row to write with the row delimiters of the schema
+    table_rows: TableRows,
+    rows_written: usize,
+}
+
+impl<W: Write> QvsRowWriter<W> {
+    fn new(schema: &TableSchema, mut writer: W) -> Result<QvsRowWriter<W>, Qvs20Error> {
+        let mut wrt = WriterForQvs20::new();
+        schema.write_schema_to_writer(&mut wrt, false);
+        writer.write_all(wrt.return_and_finish().as_bytes()).map_err(io_error)?;
+        let constraints = SchemaConstraints::new(schema)?;
+        let unique_keys = UniqueKeys::new(schema, &constraints.columns)?;
+        //return
+        Ok(QvsRowWriter {
+            writer,
+            schema: schema.clone(),
+            constraints,
+            unique_keys,
+            table_rows: TableRows::new(&schema.table_name, schema.row_delimiter)?,
+            rows_written: 0,
+        })
+    }
+
+    /// check the constraints and unique keys of the row before writing it
+    fn write_row(&mut self, row: Row, line_number: usize) -> Result<(), Qvs20Error> {
+        self.table_rows
+            .check_one_row(&row, self.rows_written, &self.schema, &self.constraints, &mut self.unique_keys)
+            .map_err(|e| line_error(line_number, err_trim!(e)))?;
+        self.table_rows.rows = vec![row];
+        self.table_rows.set_depth(0)?;
+        let mut wrt = WriterForQvs20::new();
+        self.table_rows.write_data_rows_to_writer(&mut wrt);
+        self.writer.write_all(wrt.return_and_finish().as_bytes()).map_err(io_error)?;
+        self.rows_written += 1;
+        //return
+        Ok(())
+    }
+}
+
+/// convert the remaining lines after the line number
+fn convert_lines<R: BufRead, W: Write>(
+    reader: R,
+    mut line_number: usize,
+    schema: &TableSchema,
+    row_writer: &mut QvsRowWriter<W>,
+) -> Result<(), Qvs20Error> {
+    for line in reader.lines() {
+        let line = line.map_err(io_error)?;
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object = parse_line(&line, line_number)?;
+        let row = json_to_row(&object, schema).map_err(|msg| line_error(line_number, &msg))?;
+        row_writer.write_row(row, line_number)?;
+    }
+    //return
+    Ok(())
+}
+
+/// convert NDJSON to a QVS21 `[T]` file with the schema. Returns the count of rows.
+pub fn ndjson_to_qvs21<R: BufRead, W: Write>(reader: R, schema: &TableSchema, writer: W) -> Result<usize, Qvs20Error> {
+    let mut schema = schema.clone();
+    schema.set_depth(0)?;
+    let mut row_writer = QvsRowWriter::new(&schema, writer)?;
+    convert_lines(reader, 0, &schema, &mut row_writer)?;
+    //return
+    Ok(row_writer.rows_written)
+}
+
+/// convert NDJSON to a QVS21 `[T]` file with the schema inferred from the first records.
+/// Returns the schema and the count of rows.
+pub fn ndjson_to_qvs21_infer<R: BufRead, W: Write>(
+    mut reader: R,
+    table_name: &str,
+    infer_records: usize,
+    writer: W,
+) -> Result<(TableSchema, usize), Qvs20Error> {
+    // only the first records are in memory
+    let mut first_objects = vec![];
+    let mut line_number = 0;
+    let mut line = s!();
+    while first_objects.len() < infer_records {
+        line.clear();
+        if reader.read_line(&mut line).map_err(io_error)? == 0 {
+            break;
+        }
+        line_number += 1;
+        if !line.trim().is_empty() {
+            first_objects.push((line_number, parse_line(&line, line_number)?));
+        }
+    }
+    let objects: Vec<&Map<String, serde_json::Value>> = first_objects.iter().map(|(_, o)| o).collect();
+    let mut schema = infer_schema(table_name, &objects);
+    schema.set_depth(0)?;
+    let mut row_writer = QvsRowWriter::new(&schema, writer)?;
+    for (line_number, object) in first_objects.iter() {
+        let row = json_to_row(object, &schema).map_err(|msg| line_error(*line_number, &msg))?;
+        row_writer.write_row(row, *line_number)?;
+    }
+    convert_lines(reader, line_number, &schema, &mut row_writer)?;
+    let rows_written = row_writer.rows_written;
+    //return
+    Ok((schema, rows_written))
+}
+
+/// convert a QVS21 `[T]` file to NDJSON, one line for every row. Returns the count of rows.
+/// The schema has 5 lines and every data row is one line, like the writer writes them.
+pub fn qvs21_to_ndjson<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> Result<usize, Qvs20Error> {
+    let mut header = s!();
+    let mut line_number = 0;
+    while line_number < 5 {
+        if reader.read_line(&mut header).map_err(io_error)? == 0 {
+            break;
+        }
+        line_number += 1;
+    }
+    let schema = TableSchema::schema_from_qvs20_str(&header)?;
+    let constraints = SchemaConstraints::new(&schema)?;
+    let mut unique_keys = UniqueKeys::new(&schema, &constraints.columns)?;
+    // holds the row position for error messages, but not the rows
+    let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+    let mut rows_written = 0;
+    for line in reader.lines() {
+        let line = line.map_err(io_error)?;
+        line_number += 1;
+        if line.is_empty() {
+            continue;
+        }
+        let text = format!("{}\n", line);
+        let mut rdr = ReaderForQvs20::new(text.as_bytes());
+        let row = match table_rows.next_data_row(&mut rdr, &schema, &constraints, &mut unique_keys) {
+            Ok(Some(row)) => row,
+            Ok(None) => continue,
+            Err(e) => return Err(line_error(line_number, err_trim!(e))),
+        };
+        let json = row_to_json(&row, &schema).map_err(|msg| line_error(line_number, &msg))?;
+        writeln!(writer, "{}", json).map_err(io_error)?;
+        rows_written += 1;
+    }
+    //return
+    Ok(rows_written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qvs20_table_mod::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_ndjson_both_directions() {
+        let s = r"[T][logs][]
+[DateTimeFixedOffset][String][Integer][Decimal][SubTable]
+[][][][][1[U][tags][]1[String]1[]1[]1[tag]1]
+[][][][][]
+[time][message][status][amount][tags]
+[2020-06-27T23:59:59+02:00][started][200][1.50][1[a]1[b]1]
+[2020-06-28T00:00:01+02:00][line\nbreak][][][]
+";
+        let mut ndjson = vec![];
+        assert_eq!(unwrap!(qvs21_to_ndjson(s.as_bytes(), &mut ndjson)), 2);
+        let ndjson = unwrap!(String::from_utf8(ndjson));
+        assert_eq!(
+            ndjson,
+            r#"{"time":"2020-06-27T23:59:59+02:00","message":"started","status":200,"amount":"1.50","tags":[{"tag":"a"},{"tag":"b"}]}
+{"time":"2020-06-28T00:00:01+02:00","message":"line\nbreak","status":null,"amount":null,"tags":[]}
+"#
+        );
+        // back with the schema
+        let table = unwrap!(Table::from_qvs20_str_with_schema(s));
+        let schema = table.schema.clone();
+        let mut qvs = vec![];
+        assert_eq!(unwrap!(ndjson_to_qvs21(ndjson.as_bytes(), &schema, &mut qvs)), 2);
+        assert_eq!(unwrap!(String::from_utf8(qvs)), table.write_table());
+
+        // type mismatch with the line number
+        let bad = format!("{}\n{}", ndjson, r#"{"time":"2020-06-28","status":"OK"}"#);
+        let err = ndjson_to_qvs21(bad.as_bytes(), &schema, vec![]).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            r#"Error: Line 4: column time: expected DateTimeFixedOffset and found "2020-06-28""#
+        );
+
+        // constraints and unique keys of every row
+        let s = r"[T][cities][]
+[String][Integer]
+[][]
+[primary_key=1][min=0]
+[city][population]
+";
+        let schema = unwrap!(Table::from_qvs20_str_with_schema(s)).schema;
+        let input = r#"{"city":"Koper","population":-1}"#;
+        let err = ndjson_to_qvs21(input.as_bytes(), &schema, vec![]).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Line 1: Constraint violation row 0 col 1 population: value -1 is less than min 0"
+        );
+        let input = "{\"city\":\"Koper\"}\n\n{\"city\":\"Koper\",\"population\":1}\n";
+        let err = ndjson_to_qvs21(input.as_bytes(), &schema, vec![]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Line 3: Duplicate key row 1 columns city: Koper");
+
+        // NaN is not JSON
+        let s = "[T][measures][]\n[String][Float]\n[][]\n[][]\n[name][value]\n[a][1.5]\n[b][NaN]\n";
+        let err = qvs21_to_ndjson(s.as_bytes(), vec![]).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Line 7: column value: Float NaN cannot be written to JSON"
+        );
+
+        // inferred schema
+        let input = r#"{"id":1,"day":"2020-06-27","tags":[{"tag":"a"}]}
+{"id":2.5,"extra":true}
+{"id":3,"day":"2020-06-28","extra":false}
+"#;
+        let mut qvs = vec![];
+        let (schema, rows) = unwrap!(ndjson_to_qvs21_infer(input.as_bytes(), "events", 2, &mut qvs));
+        assert_eq!(rows, 3);
+        assert_eq!(
+            schema.data_types,
+            vec![DataType::Float, DataType::Date, DataType::SubTable, DataType::Bool]
+        );
+        let err = ndjson_to_qvs21_infer(input.as_bytes(), "events", 1, vec![]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Line 2: column id: expected Integer and found 2.5");
+    }
+}
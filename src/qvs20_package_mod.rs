@@ -0,0 +1,385 @@
+// qvs20_package_mod
+
+//! A package is a small relational database: more named tables and the foreign keys between them.
+//! One QVS20 file contains exactly one table, so the package is written as a manifest table.
+//! Every row of the manifest is one table of the package:
+//!
+//! - table_name - must be the same as the table name in the schema of the table
+//! - foreign_keys - SubTable with the columns: name, column, parent_table, parent_column.
+//!   A composite foreign key has more rows with the same name.
+//! - table - the complete `[T]` table as an escaped String
+//!
+//! Example:
+//!
+//! ```QVS20
+//! [T][geo][countries and cities]
+//! [String][SubTable][String]
+//! [][1[U][foreign_keys][foreign keys of the table]1[String][String][String][String]1[][][][]1[][][][]1[name][column][parent_table][parent_column]1][]
+//! [][][]
+//! [table_name][foreign_keys][table]
+//! [countries][][\[T\]\[countries\]...]
+//! [cities][1[fk_country][country][countries][code]1][\[T\]\[cities\]...]
+//! ```
+//!
+//! The referential integrity is validated when the package is read.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// Foreign key from the child table to the parent table.
+#[derive(Clone, Debug, Default)]
+pub struct ForeignKey {
+    pub name: String,
+    /// child table
+    pub table_name: String,
+    /// columns in the child table
+    pub columns: Vec<String>,
+    pub parent_table: String,
+    /// columns in the parent table, usually the primary key
+    pub parent_columns: Vec<String>,
+}
+
+/// More tables and foreign keys.
+#[derive(Clone, Debug, Default)]
+pub struct Package {
+    pub name: String,
+    pub description: String,
+    pub tables: Vec<Table>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl Package {
+    pub fn new(name: &str, description: &str) -> Package {
+        Package {
+            name: s!(name),
+            description: s!(description),
+            ..Default::default()
+        }
+    }
+
+    /// schema of the manifest table
+    fn manifest_schema(&self) -> TableSchema {
+        let mut fk_schema = TableSchema {
+            table_name: s!("foreign_keys"),
+            table_description: s!("foreign keys of the table"),
+            ..Default::default()
+        };
+        for name in &["name", "column", "parent_table", "parent_column"] {
+            fk_schema.push_column(name, DataType::String, None, "");
+        }
+        let mut schema = TableSchema {
+            table_name: self.name.clone(),
+            table_description: self.description.clone(),
+            ..Default::default()
+        };
+        schema.push_column("table_name", DataType::String, None, "");
+        schema.push_column("foreign_keys", DataType::SubTable, Some(fk_schema), "");
+        schema.push_column("table", DataType::String, None, "");
+        // depth 0 cannot return error
+        let _ = schema.set_depth(0);
+        //return
+        schema
+    }
+
+    /// read the package from the manifest table and validate the foreign keys
+    pub fn from_qvs20_str(input: &str) -> Result<Package, Qvs20Error> {
+        let manifest = Table::from_qvs20_str_with_schema(input)?;
+        if manifest.schema.column_names != vec![s!("table_name"), s!("foreign_keys"), s!("table")] {
+            return Err(Qvs20Error::Error {
+                msg: s!("Package manifest must have columns table_name, foreign_keys, table."),
+            });
+        }
+        let fk_schema_is_valid = match manifest.schema.sub_table_schemas.get(1) {
+            Some(Some(fk_schema)) => fk_schema.column_names.len() == 4 && fk_schema.data_types.iter().all(|d| *d == DataType::String),
+            _ => false,
+        };
+        if !fk_schema_is_valid {
+            return Err(Qvs20Error::Error {
+                msg: s!("Package manifest foreign_keys must have 4 String columns name, column, parent_table, parent_column."),
+            });
+        }
+        let mut package = Package::new(&manifest.schema.table_name, &manifest.schema.table_description);
+        for (row_index, row) in manifest.table_rows.rows.iter().enumerate() {
+            let (table_name, fk_rows, text) = match (&row.values[0], &row.values[1], &row.values[2]) {
+                (Value::String(n), Value::SubTable(f), Value::String(t)) => (n, f, t),
+                _ => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Package manifest row {} has wrong data types.", row_index),
+                    })
+                }
+            };
+            let table = match Table::from_qvs20_str_with_schema(text) {
+                Ok(t) => t,
+                Err(e) => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Package table {} {}", table_name, err_trim!(e)),
+                    })
+                }
+            };
+            if &table.schema.table_name != table_name {
+                return Err(Qvs20Error::Error {
+                    msg: format!(
+                        "Package manifest table name {} differs from schema table name {}.",
+                        table_name, table.schema.table_name
+                    ),
+                });
+            }
+            for (fk_index, fk_row) in fk_rows.rows.iter().enumerate() {
+                let fk_values = match &fk_row.values[..] {
+                    [Value::String(name), Value::String(column), Value::String(parent_table), Value::String(parent_column)] => {
+                        [name, column, parent_table, parent_column]
+                    }
+                    _ => {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Package manifest row {} foreign key {} has wrong data types.", row_index, fk_index),
+                        })
+                    }
+                };
+                package.add_foreign_key_column(table_name, fk_values[0], fk_values[1], fk_values[2], fk_values[3]);
+            }
+            package.tables.push(table);
+        }
+        package.validate_referential_integrity()?;
+        //return
+        Ok(package)
+    }
+
+    /// composite foreign keys have more columns with the same name
+    fn add_foreign_key_column(&mut self, table_name: &str, name: &str, column: &str, parent_table: &str, parent_column: &str) {
+        match self
+            .foreign_keys
+            .iter_mut()
+            .find(|fk| fk.name == name && fk.table_name == table_name)
+        {
+            Some(fk) => {
+                fk.columns.push(s!(column));
+                fk.parent_columns.push(s!(parent_column));
+            }
+            None => self.foreign_keys.push(ForeignKey {
+                name: s!(name),
+                table_name: s!(table_name),
+                columns: vec![s!(column)],
+                parent_table: s!(parent_table),
+                parent_columns: vec![s!(parent_column)],
+            }),
+        }
+    }
+
+    /// write the manifest table with all tables to String
+    pub fn write_package(&self) -> String {
+        let schema = self.manifest_schema();
+        let mut table_rows = TableRows::default();
+        table_rows.row_delimiter = schema.row_delimiter;
+        for table in self.tables.iter() {
+            let mut fk_rows = TableRows::default();
+            fk_rows.row_delimiter = row_delimiter_for_depth(1).unwrap_or(b'1');
+            for fk in self.foreign_keys.iter().filter(|fk| fk.table_name == table.schema.table_name) {
+                for (column, parent_column) in fk.columns.iter().zip(fk.parent_columns.iter()) {
+                    fk_rows.rows.push(Row {
+                        values: vec![
+                            Value::String(fk.name.clone()),
+                            Value::String(column.clone()),
+                            Value::String(fk.parent_table.clone()),
+                            Value::String(parent_column.clone()),
+                        ],
+                    });
+                }
+            }
+            table_rows.rows.push(Row {
+                values: vec![
+                    Value::String(table.schema.table_name.clone()),
+                    Value::SubTable(fk_rows),
+                    Value::String(table.write_table()),
+                ],
+            });
+        }
+        let manifest = Table { schema, table_rows };
+        //return
+        manifest.write_table()
+    }
+
+    pub fn table(&self, table_name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.schema.table_name == table_name)
+    }
+
+    fn table_position(&self, table_name: &str) -> Result<usize, Qvs20Error> {
+        match self.tables.iter().position(|t| t.schema.table_name == table_name) {
+            Some(p) => Ok(p),
+            None => Err(Qvs20Error::Error {
+                msg: format!("Package does not have table {}.", table_name),
+            }),
+        }
+    }
+
+    /// indexes of the columns by name
+    fn column_indexes(table: &Table, columns: &[String]) -> Result<Vec<usize>, Qvs20Error> {
+        let columns: Vec<&str> = columns.iter().map(|x| x.as_str()).collect();
+        table.column_indexes(&columns)
+    }
+
+    /// every foreign key value in the child table must exist in the parent table
+    pub fn validate_referential_integrity(&self) -> Result<(), Qvs20Error> {
+        for fk in self.foreign_keys.iter() {
+            let child = &self.tables[self.table_position(&fk.table_name)?];
+            let parent = &self.tables[self.table_position(&fk.parent_table)?];
+            let child_columns = Self::column_indexes(child, &fk.columns)?;
+            let parent_columns = Self::column_indexes(parent, &fk.parent_columns)?;
+            let index = match TableIndex::build(&parent.table_rows, parent_columns) {
+                Ok(i) => i,
+                Err(e) => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Foreign key {} parent table {} {}", fk.name, fk.parent_table, err_trim!(e)),
+                    })
+                }
+            };
+            for (row_index, row) in child.table_rows.rows.iter().enumerate() {
+                let key: Vec<Value> = child_columns.iter().map(|i| row.values[*i].clone()).collect();
+                if index.get(&key).is_none() {
+                    return Err(Qvs20Error::Error {
+                        msg: format!(
+                            "Foreign key {} table {} row {}: key {} does not exist in table {}.",
+                            fk.name,
+                            fk.table_name,
+                            row_index,
+                            key_to_string(&row_key(row, &child_columns)),
+                            fk.parent_table
+                        ),
+                    });
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+
+    /// Embed the rows of the child table into the parent table as a SubTable column.
+    /// The column name is the child table name.
+    /// The foreign key columns are not repeated in the sub table.
+    /// The child table and the foreign key are removed from the package.
+    pub fn embed_child_table(&mut self, fk_name: &str) -> Result<(), Qvs20Error> {
+        let fk_position = match self.foreign_keys.iter().position(|fk| fk.name == fk_name) {
+            Some(p) => p,
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Package does not have foreign key {}.", fk_name),
+                })
+            }
+        };
+        let fk = self.foreign_keys[fk_position].clone();
+        let child_position = self.table_position(&fk.table_name)?;
+        let parent_position = self.table_position(&fk.parent_table)?;
+        let child = self.tables[child_position].clone();
+        let parent_key: Vec<&str> = fk.parent_columns.iter().map(|x| x.as_str()).collect();
+        let child_key: Vec<&str> = fk.columns.iter().map(|x| x.as_str()).collect();
+        self.tables[parent_position].nest(&child, &parent_key, &child_key, &fk.table_name)?;
+        self.tables.remove(child_position);
+        self.foreign_keys.remove(fk_position);
+        // return
+        Ok(())
+    }
+
+    /// Extract the SubTable column into a new child table with the name of the column.
+    /// The parent key is repeated in the child table and a new foreign key is added to the package.
+    /// See Table::flatten_sub_table() for the parent key.
+    pub fn extract_sub_table(&mut self, table_name: &str, column_name: &str) -> Result<(), Qvs20Error> {
+        let parent_position = self.table_position(table_name)?;
+        if self.table_position(column_name).is_ok() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Package already has table {}.", column_name),
+            });
+        }
+        let parent = &mut self.tables[parent_position];
+        let child = parent.flatten_sub_table(column_name)?;
+        let key_names: Vec<String> = parent
+            .schema
+            .primary_key_columns()?
+            .iter()
+            .map(|i| parent.schema.column_names[*i].clone())
+            .collect();
+        self.foreign_keys.push(ForeignKey {
+            name: format!("fk_{}_{}", column_name, table_name),
+            table_name: s!(column_name),
+            columns: key_names.clone(),
+            parent_table: s!(table_name),
+            parent_columns: key_names,
+        });
+        self.tables.push(child);
+        // return
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    fn sample_package() -> Package {
+        let countries = unwrap!(Table::from_qvs20_str_with_schema(
+            "[T][countries][all countries]\n[String][String]\n[][]\n[primary_key=1][]\n[code][name]\n[SI][Slovenia]\n[IT][Italia]\n"
+        ));
+        let cities = unwrap!(Table::from_qvs20_str_with_schema(
+            "[T][cities][all cities]\n[String][String][Integer]\n[][][]\n[][][]\n[country][city][population]\n[SI][Ljubljana][300000]\n[IT][Milano][1400000]\n[SI][Koper][30000]\n"
+        ));
+        let mut package = Package::new("geo", "countries and cities");
+        package.tables.push(countries);
+        package.tables.push(cities);
+        package.add_foreign_key_column("cities", "fk_country", "country", "countries", "code");
+        package
+    }
+
+    #[test]
+    pub fn t01_write_and_read_package() {
+        let package = sample_package();
+        let text = package.write_package();
+        let package2 = unwrap!(Package::from_qvs20_str(&text));
+        assert_eq!(package2.tables.len(), 2);
+        assert_eq!(package2.foreign_keys[0].parent_columns, vec![s!("code")]);
+        assert_eq!(text, package2.write_package());
+
+        // referential integrity is validated on read
+        let text = text.replace("[SI\\]\\[Koper", "[HR\\]\\[Koper");
+        let err = Package::from_qvs20_str(&text).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Foreign key fk_country table cities row 2: key HR does not exist in table countries."
+        );
+
+        // the foreign_keys sub table must have 4 String columns
+        let text = r"[T][geo][]
+[String][SubTable][String]
+[][1[U][foreign_keys][]1[String][String][String]1[][][]1[][][]1[name][column][parent_table]1][]
+[][][]
+[table_name][foreign_keys][table]
+";
+        let err = Package::from_qvs20_str(text).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Package manifest foreign_keys must have 4 String columns name, column, parent_table, parent_column."
+        );
+    }
+    #[test]
+    pub fn t02_embed_and_extract() {
+        let mut package = sample_package();
+        unwrap!(package.embed_child_table("fk_country"));
+        assert_eq!(package.tables.len(), 1);
+        let countries = unwrap!(package.table("countries"));
+        assert_eq!(
+            countries.write_table(),
+            "[T][countries][all countries]\n[String][String][SubTable]\n[][][1[U][cities][all cities]1[String][Integer]1[][]1[][]1[city][population]1]\n[primary_key=1][][]\n[code][name][cities]\n[SI][Slovenia][1[Ljubljana][300000]1[Koper][30000]1]\n[IT][Italia][1[Milano][1400000]1]\n"
+        );
+        // the written table can be read again
+        unwrap!(Table::from_qvs20_str_with_schema(&countries.write_table()));
+
+        unwrap!(package.extract_sub_table("countries", "cities"));
+        assert_eq!(package.tables.len(), 2);
+        unwrap!(package.validate_referential_integrity());
+        assert_eq!(
+            unwrap!(package.table("cities")).write_table(),
+            "[T][cities][all cities]\n[String][String][Integer]\n[][][]\n[][][]\n[code][city][population]\n[SI][Ljubljana][300000]\n[SI][Koper][30000]\n[IT][Milano][1400000]\n"
+        );
+    }
+}
@@ -0,0 +1,363 @@
+// qvs20_parquet_mod
+
+//! Parquet export and import. Only with the cargo feature `parquet`.
+//!
+//! The columns are converted like in qvs20_arrow_mod: sub tables are nested Parquet groups (list of struct).
+//! The whole QVS21 schema with table name, description and additional properties
+//! is kept in the Parquet key-value metadata `qvs21.schema`.
+//!
+//! The rows are streamed in row groups, so only one row group is in memory.
+//! Decimal columns get the precision and scale from the constraints `precision` and `scale`.
+//! With only `precision` p or without constraints (p is then 29, the most digits of a Decimal),
+//! the Parquet column is Decimal128 with room for p integer digits and p decimal places, at most 38 digits.
+//! Values with more decimal places than the Parquet column are rejected.
+//! Parquet has one scale for the column, so the Decimal values are read without trailing zeros.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_row_stream_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+use crate::qvs20_writer_mod::*;
+
+use arrow::array::ArrayRef;
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType as ArrowDataType, Field, Fields, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::ChunkReader;
+use std::io::Write;
+use std::sync::Arc;
+
+/// rows in one row group
+pub const DEFAULT_ROW_GROUP_ROWS: usize = 8192;
+const SCHEMA_KEY: &str = "qvs21.schema";
+const TABLE_NAME_KEY: &str = "qvs21.table_name";
+const DESCRIPTION_KEY: &str = "qvs21.description";
+
+fn parquet_error(e: ParquetError) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("Parquet error: {}", e),
+    }
+}
+
+/// max precision and scale of Decimal128
+const FILE_PRECISION: u32 = 38;
+const FILE_SCALE: u32 = 28;
+/// max count of digits of a Decimal value
+const DECIMAL_DIGITS: u32 = 29;
+
+/// the fields for the whole file: Decimal128 with precision and scale from the constraints or the fixed ones
+fn file_fields(fields: &Fields, schema: &TableSchema) -> Result<Vec<Field>, Qvs20Error> {
+    let constraints = schema.column_constraints()?;
+    let mut vec_of_fields = vec![];
+    for (column, field) in fields.iter().enumerate() {
+        let data_type = match field.data_type() {
+            ArrowDataType::Decimal128(_, _) => {
+                let c = constraints.get(column).and_then(|c| c.as_ref());
+                let (precision, scale) = match c.and_then(|c| c.scale) {
+                    Some(scale) => (c.and_then(|c| c.precision).unwrap_or(FILE_PRECISION).max(scale), scale),
+                    None => {
+                        // room for the integer digits first, the rest for decimal places
+                        let digits = c.and_then(|c| c.precision).unwrap_or(DECIMAL_DIGITS).min(DECIMAL_DIGITS);
+                        let precision = (2 * digits).min(FILE_PRECISION);
+                        (precision, precision - digits)
+                    }
+                };
+                if precision > FILE_PRECISION || scale > FILE_SCALE {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Decimal column {} precision {} and scale {} is too big for Parquet.", field.name(), precision, scale),
+                    });
+                }
+                ArrowDataType::Decimal128(precision as u8, scale as i8)
+            }
+            ArrowDataType::List(item) => match (item.data_type(), schema.sub_table_schemas.get(column)) {
+                (ArrowDataType::Struct(sub_fields), Some(Some(sub_schema))) => {
+                    let struct_type = ArrowDataType::Struct(file_fields(sub_fields, sub_schema)?.into());
+                    ArrowDataType::List(Arc::new(item.as_ref().clone().with_data_type(struct_type)))
+                }
+                _ => field.data_type().clone(),
+            },
+            other => other.clone(),
+        };
+        vec_of_fields.push(field.as_ref().clone().with_data_type(data_type));
+    }
+    //return
+    Ok(vec_of_fields)
+}
+
+/// the row group can be cast to the file data type without losing decimal places
+fn check_scales(file_type: &ArrowDataType, data_type: &ArrowDataType, name: &str) -> Result<(), Qvs20Error> {
+    match (file_type, data_type) {
+        (ArrowDataType::Decimal128(_, file_scale), ArrowDataType::Decimal128(_, scale)) if scale > file_scale => Err(Qvs20Error::Error {
+            msg: format!(
+                "Decimal column {} has {} decimal places, but the Parquet schema has only {}.",
+                name, scale, file_scale
+            ),
+        }),
+        (ArrowDataType::List(file_item), ArrowDataType::List(item)) => check_scales(file_item.data_type(), item.data_type(), name),
+        (ArrowDataType::Struct(file_fields), ArrowDataType::Struct(fields)) => {
+            for (file_field, field) in file_fields.iter().zip(fields.iter()) {
+                check_scales(file_field.data_type(), field.data_type(), &format!("{}.{}", name, field.name()))?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// cast the row group to the schema of the file
+fn conform_batch(batch: RecordBatch, file_schema: &SchemaRef) -> Result<RecordBatch, Qvs20Error> {
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let mut columns: Vec<ArrayRef> = vec![];
+    for (field, column) in file_schema.fields().iter().zip(batch.columns()) {
+        if column.data_type() == field.data_type() {
+            columns.push(column.clone());
+            continue;
+        }
+        check_scales(field.data_type(), column.data_type(), field.name())?;
+        match cast_with_options(column, field.data_type(), &options) {
+            Ok(c) => columns.push(c),
+            Err(e) => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Column {} cannot be converted to the Parquet schema: {}", field.name(), e),
+                })
+            }
+        }
+    }
+    //return
+    RecordBatch::try_new(file_schema.clone(), columns).map_err(|e| Qvs20Error::Error {
+        msg: format!("Arrow error: {}", e),
+    })
+}
+
+/// write the rows to Parquet in row groups
+fn write_row_groups<W, I>(schema: &TableSchema, rows: I, writer: W, row_group_rows: usize) -> Result<(), Qvs20Error>
+where
+    W: Write + Send,
+    I: Iterator<Item = Result<Row, Qvs20Error>>,
+{
+    let row_group_rows = row_group_rows.max(1);
+    let mut writer = Some(writer);
+    let mut arrow_writer: Option<(ArrowWriter<W>, SchemaRef)> = None;
+    let mut chunk = Table {
+        schema: schema.clone(),
+        table_rows: TableRows::new(&schema.table_name, b'\n')?,
+    };
+    let mut rows = rows.peekable();
+    loop {
+        if let Some(row) = rows.next() {
+            chunk.table_rows.rows.push(row?);
+        }
+        let is_last = rows.peek().is_none();
+        // an empty table has one empty row group for the schema
+        if chunk.table_rows.rows.len() < row_group_rows && !(is_last && (!chunk.table_rows.rows.is_empty() || arrow_writer.is_none())) {
+            continue;
+        }
+        let batch = chunk.to_record_batch()?;
+        if arrow_writer.is_none() {
+            let batch_schema = batch.schema();
+            let fields = file_fields(batch_schema.fields(), schema)?;
+            let file_schema = Arc::new(Schema::new_with_metadata(fields, batch_schema.metadata().clone()));
+            let properties = WriterProperties::builder()
+                .set_max_row_group_size(row_group_rows)
+                .set_key_value_metadata(Some(vec![
+                    KeyValue::new(s!(SCHEMA_KEY), schema.write_schema()),
+                    KeyValue::new(s!(TABLE_NAME_KEY), schema.table_name.clone()),
+                    KeyValue::new(s!(DESCRIPTION_KEY), schema.table_description.clone()),
+                ]))
+                .build();
+            if let Some(writer) = writer.take() {
+                let new_writer = ArrowWriter::try_new(writer, file_schema.clone(), Some(properties)).map_err(parquet_error)?;
+                arrow_writer = Some((new_writer, file_schema));
+            }
+        }
+        if let Some((arrow_writer, file_schema)) = arrow_writer.as_mut() {
+            let batch = conform_batch(batch, file_schema)?;
+            arrow_writer.write(&batch).map_err(parquet_error)?;
+            // flush closes the row group
+            arrow_writer.flush().map_err(parquet_error)?;
+        }
+        chunk.table_rows.rows.clear();
+        if is_last {
+            break;
+        }
+    }
+    if let Some((arrow_writer, _)) = arrow_writer {
+        arrow_writer.close().map_err(parquet_error)?;
+    }
+    //return
+    Ok(())
+}
+
+/// the QVS21 schema from the Parquet metadata, or from the Arrow schema for other Parquet files
+fn schema_from_metadata(builder: &ParquetRecordBatchReaderBuilder<impl ChunkReader + 'static>) -> Result<Option<TableSchema>, Qvs20Error> {
+    let key_value_metadata = builder.metadata().file_metadata().key_value_metadata();
+    let text = key_value_metadata.and_then(|kv| kv.iter().find(|x| x.key == SCHEMA_KEY).and_then(|x| x.value.clone()));
+    match text {
+        Some(text) => Ok(Some(TableSchema::schema_from_qvs20_str(&text)?)),
+        None => Ok(None),
+    }
+}
+
+/// the schema and the reader of the batches.
+/// Files without the qvs21.schema metadata get the schema from the Arrow schema.
+fn open_batches<R: ChunkReader + 'static>(reader: R, batch_rows: usize) -> Result<(TableSchema, ParquetRecordBatchReader), Qvs20Error> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(reader).map_err(parquet_error)?;
+    let schema = match schema_from_metadata(&builder)? {
+        Some(schema) => schema,
+        None => Table::from_record_batch(&RecordBatch::new_empty(builder.schema().clone()))?.schema,
+    };
+    let reader = builder.with_batch_size(batch_rows.max(1)).build().map_err(parquet_error)?;
+    //return
+    Ok((schema, reader))
+}
+
+/// the Decimal values in Parquet have the scale of the column: remove the trailing zeros
+fn normalize_decimals(rows: &mut [Row]) {
+    for row in rows.iter_mut() {
+        for value in row.values.iter_mut() {
+            match value {
+                Value::Decimal(d) => *d = d.normalize(),
+                Value::SubTable(sub_table_rows) => normalize_decimals(&mut sub_table_rows.rows),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// the rows of one batch
+fn rows_from_batch(batch: Result<RecordBatch, arrow::error::ArrowError>) -> Result<Vec<Row>, Qvs20Error> {
+    let batch = batch.map_err(|e| Qvs20Error::Error {
+        msg: format!("Parquet error: {}", e),
+    })?;
+    let mut rows = Table::from_record_batch(&batch)?.table_rows.rows;
+    normalize_decimals(&mut rows);
+    //return
+    Ok(rows)
+}
+
+/// stream a `[T]` string to Parquet, one row group at a time
+pub fn qvs21_to_parquet<W: Write + Send>(input: &str, writer: W, row_group_rows: usize) -> Result<(), Qvs20Error> {
+    let stream = RowStream::from_qvs20_str_with_schema(input)?;
+    let schema = stream.schema.clone();
+    write_row_groups(&schema, stream, writer, row_group_rows)
+}
+
+/// stream Parquet to a `[T]` QVS21 file, one batch of rows at a time
+pub fn parquet_to_qvs21<R, W>(reader: R, mut writer: W, batch_rows: usize) -> Result<(), Qvs20Error>
+where
+    R: ChunkReader + 'static,
+    W: Write,
+{
+    let (schema, batches) = open_batches(reader, batch_rows)?;
+    // the schema is written also for a file without rows
+    let mut wrt = WriterForQvs20::new();
+    schema.write_schema_to_writer(&mut wrt, false);
+    writer.write_all(wrt.return_and_finish().as_bytes()).map_err(io_error)?;
+    let mut table_rows = TableRows::new(&schema.table_name, b'\n')?;
+    for batch in batches {
+        table_rows.rows = rows_from_batch(batch)?;
+        let mut wrt = WriterForQvs20::new();
+        table_rows.write_data_rows_to_writer(&mut wrt);
+        writer.write_all(wrt.return_and_finish().as_bytes()).map_err(io_error)?;
+    }
+    //return
+    Ok(())
+}
+
+impl Table {
+    /// write the table to Parquet
+    pub fn write_parquet<W: Write + Send>(&self, writer: W) -> Result<(), Qvs20Error> {
+        write_row_groups(&self.schema, self.table_rows.rows.iter().cloned().map(Ok), writer, DEFAULT_ROW_GROUP_ROWS)
+    }
+
+    /// read the whole Parquet file to a table
+    pub fn read_parquet<R: ChunkReader + 'static>(reader: R) -> Result<Table, Qvs20Error> {
+        let (schema, batches) = open_batches(reader, DEFAULT_ROW_GROUP_ROWS)?;
+        let mut table_rows = TableRows::new(&schema.table_name, b'\n')?;
+        for batch in batches {
+            table_rows.rows.extend(rows_from_batch(batch)?);
+        }
+        //return
+        Ok(Table { schema, table_rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    const COUNTRIES: &str = r"[T][countries][description]
+[String][Decimal][SubTable]
+[][][1[U][cities][sub table]1[String][Integer]1[][]1[primary_key=1][min=0]1[city][population]1]
+[primary_key=1][][]
+[country][area][cities]
+[Slovenia][20271][1[Ljubljana][300000]1[Koper][]1]
+[Croatia][56594.5][]
+[Italia][301230.75][1[Roma][2800000]1]
+";
+
+    #[test]
+    pub fn t01_parquet_round_trip() {
+        let path = std::env::temp_dir().join(format!("qvs21_t01_parquet_round_trip_{}.parquet", std::process::id()));
+        // 3 rows in 2 row groups with different decimal scales
+        let file = unwrap!(std::fs::File::create(&path));
+        unwrap!(qvs21_to_parquet(COUNTRIES, file, 2));
+        let builder = unwrap!(ParquetRecordBatchReaderBuilder::try_new(unwrap!(std::fs::File::open(&path))));
+        assert_eq!(builder.schema().field(1).data_type(), &ArrowDataType::Decimal128(38, 9));
+        let mut output = vec![];
+        unwrap!(parquet_to_qvs21(unwrap!(std::fs::File::open(&path)), &mut output, 1));
+        assert_eq!(unwrap!(String::from_utf8(output)), COUNTRIES);
+
+        let table = unwrap!(Table::read_parquet(unwrap!(std::fs::File::open(&path))));
+        assert_eq!(table.schema.sub_table_schemas[2].as_ref().map(|s| s.additional_properties.clone()), Some(vec![s!("primary_key=1"), s!("min=0")]));
+        assert_eq!(table.table_rows.rows.len(), 3);
+
+        // precision and scale from the constraints
+        let text = COUNTRIES.replace("[primary_key=1][][]", "[primary_key=1][precision=10;scale=2][]");
+        let file = unwrap!(std::fs::File::create(&path));
+        unwrap!(qvs21_to_parquet(&text, file, 2));
+        let builder = unwrap!(ParquetRecordBatchReaderBuilder::try_new(unwrap!(std::fs::File::open(&path))));
+        assert_eq!(builder.schema().field(1).data_type(), &ArrowDataType::Decimal128(10, 2));
+
+        // only precision: room for the integer digits and the decimal places
+        let text = COUNTRIES.replace("[primary_key=1][][]", "[primary_key=1][precision=9][]");
+        let file = unwrap!(std::fs::File::create(&path));
+        unwrap!(qvs21_to_parquet(&text, file, 2));
+        let builder = unwrap!(ParquetRecordBatchReaderBuilder::try_new(unwrap!(std::fs::File::open(&path))));
+        assert_eq!(builder.schema().field(1).data_type(), &ArrowDataType::Decimal128(18, 9));
+
+        // big values and too many decimal places without constraints
+        let text = COUNTRIES.replace("[56594.5]", "[12345678901234567890123456.5]");
+        let file = unwrap!(std::fs::File::create(&path));
+        unwrap!(qvs21_to_parquet(&text, file, 2));
+        let mut output = vec![];
+        unwrap!(parquet_to_qvs21(unwrap!(std::fs::File::open(&path)), &mut output, 1));
+        assert_eq!(unwrap!(String::from_utf8(output)), text);
+        let text = COUNTRIES.replace("[56594.5]", "[0.0123456789]");
+        let file = unwrap!(std::fs::File::create(&path));
+        let err = qvs21_to_parquet(&text, file, 2).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Decimal column area has 10 decimal places, but the Parquet schema has only 9."
+        );
+
+        // the schema without rows
+        let schema_only = &COUNTRIES[..COUNTRIES.find("[Slovenia]").unwrap_or(0)];
+        let file = unwrap!(std::fs::File::create(&path));
+        unwrap!(qvs21_to_parquet(schema_only, file, 2));
+        let mut output = vec![];
+        unwrap!(parquet_to_qvs21(unwrap!(std::fs::File::open(&path)), &mut output, 1));
+        assert_eq!(unwrap!(String::from_utf8(output)), schema_only);
+        unwrap!(std::fs::remove_file(&path));
+    }
+}
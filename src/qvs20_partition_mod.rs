@@ -0,0 +1,491 @@
+// qvs20_partition_mod
+
+//! Very large tables are split into parts for parallel transfer.
+//! The partitioned dataset in a directory has:
+//!
+//! - `{table}.schema.qvs21` - the `[S]` schema file
+//! - `{table}.part0001.qvs21`,... - the `[R]` rows files
+//! - `{table}.manifest.qvs21` - the manifest table with one row for every file
+//!
+//! The rows are split by the count of rows, by the size of the files in bytes
+//! or by the value of a partition column. For the partition column every value has its own part.
+//! Only the last `MAX_OPEN_PARTS` parts stay open, the others are closed and reopened for append.
+//!
+//! The reader reads the manifest, the schema and all parts and checks the counts of rows
+//! and the constraints and unique keys of all the rows together. The writer checks them for every row.
+//! The file names in the manifest must be in the same directory.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_split_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+use crate::qvs20_writer_mod::*;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_SCHEMA: &str = "[T][manifest][]
+[String][String][Integer][Integer][String]
+[][][][][]
+[][primary_key=1][min=0][min=0][]
+[file_type][file][rows][bytes][partition]
+";
+
+/// max count of open files for PartitionBy::Column
+const MAX_OPEN_PARTS: usize = 64;
+
+/// how to split the rows into parts
+#[derive(Clone, Debug, PartialEq)]
+pub enum PartitionBy {
+    /// max count of rows in a part
+    Rows(usize),
+    /// max size of a part in bytes. A part has at least one row, also if the row is bigger.
+    Bytes(usize),
+    /// one part for every value of the column
+    Column(String),
+}
+
+/// the io error with the path of the file
+fn file_error(path: &Path, e: std::io::Error) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("File {}: {}", path.display(), err_trim!(io_error(e))),
+    }
+}
+
+/// file name part from the table name
+fn file_base_name(table_name: &str) -> String {
+    let base: String = table_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if base.is_empty() {
+        s!("table")
+    } else {
+        base
+    }
+}
+
+/// one rows file
+struct Part {
+    file_name: String,
+    path: PathBuf,
+    /// None if the file is closed
+    writer: Option<BufWriter<File>>,
+    rows: usize,
+    bytes: usize,
+    partition: String,
+}
+
+/// writes the schema file, the parts and at the end the manifest
+pub struct PartitionWriter {
+    dir: PathBuf,
+    base_name: String,
+    split_writer: SplitWriter,
+    partition_by: PartitionBy,
+    /// the column for PartitionBy::Column
+    partition_column: Option<usize>,
+    parts: Vec<Part>,
+    /// part index by the partition value
+    partition_parts: HashMap<String, usize>,
+    /// indexes of the parts with an open file, the oldest first
+    open_parts: VecDeque<usize>,
+    schema_file_name: String,
+    schema_bytes: usize,
+    constraints: SchemaConstraints,
+    /// the keys of all rows written so far, across all parts
+    unique_keys: UniqueKeys,
+    rows_written: usize,
+}
+
+impl PartitionWriter {
+    /// write the schema file to the directory
+    pub fn new(dir: &Path, schema: &TableSchema, partition_by: PartitionBy) -> Result<PartitionWriter, Qvs20Error> {
+        let partition_column = match &partition_by {
+            PartitionBy::Rows(0) | PartitionBy::Bytes(0) => {
+                return Err(Qvs20Error::Error {
+                    msg: s!("The size of a part cannot be 0."),
+                })
+            }
+            PartitionBy::Column(column_name) => match schema.column_index(column_name) {
+                Some(column) if schema.data_types[column] == DataType::SubTable => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("The partition column {} cannot be a SubTable.", column_name),
+                    })
+                }
+                Some(column) => Some(column),
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("The partition column {} is not in the table {}.", column_name, schema.table_name),
+                    })
+                }
+            },
+            _ => None,
+        };
+        fs::create_dir_all(dir).map_err(|e| file_error(dir, e))?;
+        let base_name = file_base_name(&schema.table_name);
+        let schema_file_name = format!("{}.schema.qvs21", base_name);
+        let schema_path = dir.join(&schema_file_name);
+        let schema_file = File::create(&schema_path).map_err(|e| file_error(&schema_path, e))?;
+        let mut schema = schema.clone();
+        schema.set_depth(0)?;
+        let schema_bytes = schema.write_schema().len();
+        let split_writer = SplitWriter::new(&schema, schema_file)?;
+        let constraints = SchemaConstraints::new(&schema)?;
+        let unique_keys = UniqueKeys::new(&schema, &constraints.columns)?;
+        //return
+        Ok(PartitionWriter {
+            dir: dir.to_path_buf(),
+            base_name,
+            split_writer,
+            partition_by,
+            partition_column,
+            parts: vec![],
+            partition_parts: HashMap::new(),
+            open_parts: VecDeque::new(),
+            schema_file_name,
+            schema_bytes,
+            constraints,
+            unique_keys,
+            rows_written: 0,
+        })
+    }
+
+    /// new part with the 1st row `[R][table name]`
+    fn new_part(&mut self, partition: String) -> Result<usize, Qvs20Error> {
+        let file_name = format!("{}.part{:04}.qvs21", self.base_name, self.parts.len() + 1);
+        let path = self.dir.join(&file_name);
+        let file = File::create(&path).map_err(|e| file_error(&path, e))?;
+        let mut writer = BufWriter::new(file);
+        let bytes = self.split_writer.write_rows_header(&mut writer)?;
+        self.close_oldest_part()?;
+        self.parts.push(Part {
+            file_name,
+            path,
+            writer: Some(writer),
+            rows: 0,
+            bytes,
+            partition,
+        });
+        self.open_parts.push_back(self.parts.len() - 1);
+        //return
+        Ok(self.parts.len() - 1)
+    }
+
+    /// close the oldest open part if there are already MAX_OPEN_PARTS open
+    fn close_oldest_part(&mut self) -> Result<(), Qvs20Error> {
+        if self.open_parts.len() >= MAX_OPEN_PARTS {
+            if let Some(index) = self.open_parts.pop_front() {
+                let part = &mut self.parts[index];
+                if let Some(mut writer) = part.writer.take() {
+                    writer.flush().map_err(|e| file_error(&part.path, e))?;
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+
+    /// reopen a closed part to append rows
+    fn reopen_part(&mut self, index: usize) -> Result<(), Qvs20Error> {
+        if self.parts[index].writer.is_none() {
+            self.close_oldest_part()?;
+            let part = &mut self.parts[index];
+            let file = OpenOptions::new()
+                .append(true)
+                .open(&part.path)
+                .map_err(|e| file_error(&part.path, e))?;
+            part.writer = Some(BufWriter::new(file));
+            self.open_parts.push_back(index);
+        }
+        //return
+        Ok(())
+    }
+
+    /// add the row to the right part
+    pub fn write_row(&mut self, row: &Row) -> Result<(), Qvs20Error> {
+        let schema = self.split_writer.schema();
+        if row.values.len() != schema.column_names.len() {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "The row has {} columns, but the schema of {} has {} columns.",
+                    row.values.len(),
+                    schema.table_name,
+                    schema.column_names.len()
+                ),
+            });
+        }
+        let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+        // the reader checks the same
+        table_rows.check_one_row(row, self.rows_written, schema, &self.constraints, &mut self.unique_keys)?;
+        table_rows.rows.push(row.clone());
+        table_rows.set_depth(0)?;
+        let mut wrt = WriterForQvs20::new();
+        table_rows.write_data_rows_to_writer(&mut wrt);
+        let text = wrt.return_and_finish();
+
+        let last_part = self.parts.len().checked_sub(1);
+        let index = match (&self.partition_by, self.partition_column) {
+            (_, Some(column)) => {
+                let partition = value_to_string(&row.values[column]);
+                match self.partition_parts.get(&partition) {
+                    Some(index) => {
+                        let index = *index;
+                        self.reopen_part(index)?;
+                        index
+                    }
+                    None => {
+                        let index = self.new_part(partition.clone())?;
+                        self.partition_parts.insert(partition, index);
+                        index
+                    }
+                }
+            }
+            (PartitionBy::Rows(max_rows), None) => match last_part {
+                Some(index) if self.parts[index].rows < *max_rows => index,
+                _ => self.new_part(s!())?,
+            },
+            (PartitionBy::Bytes(max_bytes), None) => match last_part {
+                Some(index) if self.parts[index].rows == 0 || self.parts[index].bytes + text.len() <= *max_bytes => index,
+                _ => self.new_part(s!())?,
+            },
+            (PartitionBy::Column(_), None) => unreachable!("the partition column is checked in new()"),
+        };
+        let part = &mut self.parts[index];
+        if let Some(writer) = part.writer.as_mut() {
+            writer.write_all(text.as_bytes()).map_err(|e| file_error(&part.path, e))?;
+        }
+        part.rows += 1;
+        part.bytes += text.len();
+        self.rows_written += 1;
+        //return
+        Ok(())
+    }
+
+    /// flush all parts and write the manifest. Returns the manifest table.
+    pub fn finish(mut self) -> Result<Table, Qvs20Error> {
+        let mut manifest = Table::from_qvs20_str_with_schema(MANIFEST_SCHEMA)?;
+        manifest.schema.table_description = self.split_writer.schema().table_name.clone();
+        manifest.table_rows.table_name = s!("manifest");
+        manifest.table_rows.rows.push(Row {
+            values: vec![
+                Value::String(s!("S")),
+                Value::String(self.schema_file_name.clone()),
+                Value::Null,
+                Value::Integer(self.schema_bytes as i64),
+                Value::String(s!()),
+            ],
+        });
+        for part in self.parts.iter_mut() {
+            if let Some(writer) = part.writer.as_mut() {
+                writer.flush().map_err(|e| file_error(&part.path, e))?;
+            }
+            manifest.table_rows.rows.push(Row {
+                values: vec![
+                    Value::String(s!("R")),
+                    Value::String(part.file_name.clone()),
+                    Value::Integer(part.rows as i64),
+                    Value::Integer(part.bytes as i64),
+                    Value::String(part.partition.clone()),
+                ],
+            });
+        }
+        let manifest_path = self.dir.join(format!("{}.manifest.qvs21", self.base_name));
+        fs::write(&manifest_path, manifest.write_table()).map_err(|e| file_error(&manifest_path, e))?;
+        //return
+        Ok(manifest)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, Qvs20Error> {
+    fs::read_to_string(path).map_err(|e| file_error(path, e))
+}
+
+/// the path of a file from the manifest, only in the directory of the manifest
+fn manifest_file_path(dir: &Path, file_name: &str) -> Result<PathBuf, Qvs20Error> {
+    if file_name.is_empty() || file_name.contains('/') || file_name.contains('\\') || file_name.contains("..") {
+        return Err(Qvs20Error::Error {
+            msg: format!("The manifest file name {} is not in the directory of the manifest.", file_name),
+        });
+    }
+    //return
+    Ok(dir.join(file_name))
+}
+
+impl Table {
+    /// write the table to a partitioned dataset in the directory. Returns the manifest table.
+    pub fn write_partitioned(&self, dir: &Path, partition_by: PartitionBy) -> Result<Table, Qvs20Error> {
+        let mut partition_writer = PartitionWriter::new(dir, &self.schema, partition_by)?;
+        for row in self.table_rows.rows.iter() {
+            partition_writer.write_row(row)?;
+        }
+        partition_writer.finish()
+    }
+
+    /// read all the parts of a partitioned dataset from the manifest file
+    pub fn read_partitioned(manifest_path: &Path) -> Result<Table, Qvs20Error> {
+        let dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        let manifest = Table::from_qvs20_str_with_schema(&read_file(manifest_path)?)?;
+        let manifest_schema = TableSchema::schema_from_qvs20_str(MANIFEST_SCHEMA)?;
+        if manifest.schema.column_names != manifest_schema.column_names || manifest.schema.data_types != manifest_schema.data_types {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "The manifest {} must have columns file_type, file, rows, bytes, partition.",
+                    manifest_path.display()
+                ),
+            });
+        }
+        let schema_row = manifest
+            .table_rows
+            .rows
+            .iter()
+            .find(|r| r.values[0] == Value::String(s!("S")));
+        let schema = match schema_row {
+            Some(Row { values }) => {
+                TableSchema::schema_from_qvs20_str(&read_file(&manifest_file_path(dir, &value_to_string(&values[1]))?)?)?
+            }
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("The manifest {} has no schema file.", manifest_path.display()),
+                })
+            }
+        };
+        let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+        for row in manifest.table_rows.rows.iter().filter(|r| r.values[0] == Value::String(s!("R"))) {
+            let file_name = value_to_string(&row.values[1]);
+            let part = TableRows::rows_from_qvs20_str(&read_file(&manifest_file_path(dir, &file_name)?)?, &schema)?;
+            if row.values[2] != Value::Integer(part.rows.len() as i64) {
+                return Err(Qvs20Error::Error {
+                    msg: format!(
+                        "The part {} has {} rows, but the manifest has {}.",
+                        file_name,
+                        part.rows.len(),
+                        value_to_string(&row.values[2])
+                    ),
+                });
+            }
+            table_rows.rows.extend(part.rows);
+        }
+        // the unique keys are checked across all parts
+        table_rows.check_constraints(&schema)?;
+        //return
+        Ok(Table { schema, table_rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_write_and_read_partitioned() {
+        let s = r"[T][cities/europe][big cities]
+[String][String][Integer]
+[][][]
+[primary_key=1][][min=0]
+[city][country][population]
+[Ljubljana][Slovenia][300000]
+[Zagreb][Croatia][800000]
+[Maribor][Slovenia][100000]
+[Split][Croatia][180000]
+[Koper][Slovenia][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(s));
+        let dir = std::env::temp_dir().join(format!("qvs21_t01_write_and_read_partitioned_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let manifest = unwrap!(table.write_partitioned(&dir.join("rows"), PartitionBy::Rows(2)));
+        assert_eq!(
+            manifest.table_rows.write_table_rows(),
+            "[R][manifest]
+[S][cities_europe.schema.qvs21][][117][]
+[R][cities_europe.part0001.qvs21][2][75][]
+[R][cities_europe.part0002.qvs21][2][72][]
+[R][cities_europe.part0003.qvs21][1][39][]
+"
+        );
+        assert_eq!(
+            unwrap!(fs::read_to_string(dir.join("rows/cities_europe.part0003.qvs21"))),
+            "[R][cities/europe]\n[Koper][Slovenia][]\n"
+        );
+        let table2 = unwrap!(Table::read_partitioned(&dir.join("rows/cities_europe.manifest.qvs21")));
+        assert_eq!(table2.write_table(), table.write_table());
+
+        let manifest = unwrap!(table.write_partitioned(&dir.join("bytes"), PartitionBy::Bytes(80)));
+        assert_eq!(manifest.table_rows.rows.len(), 4);
+
+        let manifest = unwrap!(table.write_partitioned(&dir.join("country"), PartitionBy::Column(s!("country"))));
+        let partitions: Vec<String> = manifest.table_rows.rows.iter().map(|r| value_to_string(&r.values[4])).collect();
+        assert_eq!(partitions, vec!["", "Slovenia", "Croatia"]);
+        let table3 = unwrap!(Table::read_partitioned(&dir.join("country/cities_europe.manifest.qvs21")));
+        assert_eq!(table3.table_rows.rows.len(), 5);
+        assert_eq!(table3.table_rows.rows[2].values[0], Value::String(s!("Koper")));
+
+        // the primary key is unique across all parts
+        unwrap!(fs::write(
+            dir.join("rows/cities_europe.part0002.qvs21"),
+            "[R][cities/europe]\n[Maribor][Slovenia][100000]\n[Ljubljana][Slovenia][1]\n"
+        ));
+        let err = Table::read_partitioned(&dir.join("rows/cities_europe.manifest.qvs21")).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Duplicate key row 3 columns city: Ljubljana");
+
+        // only file names in the same directory
+        let manifest_path = dir.join("rows/cities_europe.manifest.qvs21");
+        let manifest_text = unwrap!(fs::read_to_string(&manifest_path));
+        unwrap!(fs::write(
+            &manifest_path,
+            manifest_text.replace("[cities_europe.part0001.qvs21]", "[../country/cities_europe.part0001.qvs21]")
+        ));
+        let err = Table::read_partitioned(&manifest_path).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: The manifest file name ../country/cities_europe.part0001.qvs21 is not in the directory of the manifest."
+        );
+
+        // the manifest schema is checked before use
+        unwrap!(fs::write(&manifest_path, "[T][manifest][]\n[String]\n[]\n[]\n[file_type]\n[R]\n"));
+        let err = Table::read_partitioned(&manifest_path).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            format!(
+                "Error: The manifest {} must have columns file_type, file, rows, bytes, partition.",
+                manifest_path.display()
+            )
+        );
+
+        // the writer checks the rows like the reader
+        let mut writer = unwrap!(PartitionWriter::new(&dir.join("check"), &table.schema, PartitionBy::Rows(2)));
+        unwrap!(writer.write_row(&table.table_rows.rows[0]));
+        let err = writer.write_row(&table.table_rows.rows[0]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Duplicate key row 1 columns city: Ljubljana");
+        let mut row = table.table_rows.rows[1].clone();
+        row.values[2] = Value::Integer(-1);
+        let err = writer.write_row(&row).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Constraint violation row 1 col 2 population: value -1 is less than min 0"
+        );
+
+        // more partitions than open files
+        let mut s = s!("[T][many][]\n[Integer][String]\n[][]\n[][]\n[id][partition]\n");
+        for id in 0..3 * MAX_OPEN_PARTS {
+            s.push_str(&format!("[{}][p{}]\n", id, id % (MAX_OPEN_PARTS + 6)));
+        }
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let manifest = unwrap!(table.write_partitioned(&dir.join("many"), PartitionBy::Column(s!("partition"))));
+        assert_eq!(manifest.table_rows.rows.len(), MAX_OPEN_PARTS + 7);
+        let table4 = unwrap!(Table::read_partitioned(&dir.join("many/many.manifest.qvs21")));
+        assert_eq!(table4.table_rows.rows.len(), 3 * MAX_OPEN_PARTS);
+        // the 1st part was closed and reopened twice
+        let ids: Vec<Value> = table4.table_rows.rows[..3].iter().map(|r| r.values[0].clone()).collect();
+        let step = MAX_OPEN_PARTS as i64 + 6;
+        assert_eq!(ids, vec![Value::Integer(0), Value::Integer(step), Value::Integer(2 * step)]);
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
@@ -0,0 +1,193 @@
+// qvs20_profile_mod
+
+//! Profile of a table: statistics for every column, to know the shape of the data before loading it.
+//! The profile is itself a Table, so it can be written as a QVS21 file.
+//!
+//! One row for every column. Columns of sub tables are profiled over all sub rows
+//! and have names with a dot like `cities.city`.
+//! For SubTable columns the values are the counts of sub rows:
+//! min, max, mean and top_values are the distribution of sub row counts.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use rust_decimal::prelude::*;
+use std::collections::BTreeMap;
+
+/// count of values in top_values
+const TOP_VALUES_COUNT: usize = 5;
+
+fn profile_schema(table_name: &str) -> Result<TableSchema, Qvs20Error> {
+    let mut top_schema = TableSchema::new_simple_strings(0);
+    top_schema.table_name = s!("top_values");
+    top_schema.table_description = s!();
+    top_schema.push_column("value", DataType::String, None, "");
+    top_schema.push_column("count", DataType::Integer, None, "");
+    top_schema.set_depth(1)?;
+
+    let mut schema = TableSchema::new_simple_strings(0);
+    schema.table_name = s!("profile");
+    schema.table_description = format!("profile of {}", table_name);
+    schema.push_column("column", DataType::String, None, "primary_key=1");
+    schema.push_column("data_type", DataType::String, None, "");
+    schema.push_column("rows", DataType::Integer, None, "");
+    schema.push_column("nulls", DataType::Integer, None, "");
+    schema.push_column("distinct", DataType::Integer, None, "");
+    schema.push_column("min", DataType::String, None, "");
+    schema.push_column("max", DataType::String, None, "");
+    schema.push_column("mean", DataType::Float, None, "");
+    schema.push_column("min_length", DataType::Integer, None, "");
+    schema.push_column("max_length", DataType::Integer, None, "");
+    schema.push_column("avg_length", DataType::Float, None, "");
+    schema.push_column("top_values", DataType::SubTable, Some(top_schema), "");
+    //return
+    Ok(schema)
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Decimal(d) => d.to_f64(),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// the profile row of one column
+fn profile_row(column_name: &str, data_type: &DataType, values: &[Value]) -> Row {
+    let not_null: Vec<&Value> = values.iter().filter(|v| !matches!(v, Value::Null)).collect();
+    let mut frequencies: BTreeMap<&Value, i64> = BTreeMap::new();
+    for value in not_null.iter() {
+        *frequencies.entry(value).or_insert(0) += 1;
+    }
+    let min = frequencies.keys().next().map_or(Value::String(s!()), |v| Value::String(value_to_string(v)));
+    let max = frequencies.keys().next_back().map_or(Value::String(s!()), |v| Value::String(value_to_string(v)));
+    let numbers: Vec<f64> = not_null.iter().filter_map(|v| as_f64(v)).collect();
+    let mean = if numbers.is_empty() {
+        Value::Null
+    } else {
+        Value::Float(numbers.iter().sum::<f64>() / numbers.len() as f64)
+    };
+    let lengths: Vec<i64> = not_null
+        .iter()
+        .filter_map(|v| match v {
+            Value::String(s) => Some(s.chars().count() as i64),
+            _ => None,
+        })
+        .collect();
+    let (min_length, max_length, avg_length) = if lengths.is_empty() {
+        (Value::Null, Value::Null, Value::Null)
+    } else {
+        (
+            Value::Integer(*lengths.iter().min().unwrap_or(&0)),
+            Value::Integer(*lengths.iter().max().unwrap_or(&0)),
+            Value::Float(lengths.iter().sum::<i64>() as f64 / lengths.len() as f64),
+        )
+    };
+    // the most frequent first, equal counts in the order of values
+    let mut top: Vec<(&Value, i64)> = frequencies.iter().map(|(v, c)| (*v, *c)).collect();
+    top.sort_by_key(|x| std::cmp::Reverse(x.1));
+    let mut top_values = TableRows::default();
+    top_values.table_name = s!("top_values");
+    top_values.row_delimiter = b'1';
+    for (value, count) in top.into_iter().take(TOP_VALUES_COUNT) {
+        top_values.rows.push(Row {
+            values: vec![Value::String(value_to_string(value)), Value::Integer(count)],
+        });
+    }
+    //return
+    Row {
+        values: vec![
+            Value::String(s!(column_name)),
+            Value::String(data_type.to_string()),
+            Value::Integer(values.len() as i64),
+            Value::Integer((values.len() - not_null.len()) as i64),
+            Value::Integer(frequencies.len() as i64),
+            min,
+            max,
+            mean,
+            min_length,
+            max_length,
+            avg_length,
+            Value::SubTable(top_values),
+        ],
+    }
+}
+
+/// profile rows for all columns of the schema, sub tables after their column
+fn profile_columns(schema: &TableSchema, rows: &[&Row], prefix: &str, profile_rows: &mut Vec<Row>) {
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        let column_name = format!("{}{}", prefix, column_name);
+        let data_type = &schema.data_types[column];
+        let values: Vec<Value> = rows
+            .iter()
+            .map(|row| match &row.values[column] {
+                Value::SubTable(sub_table_rows) => Value::Integer(sub_table_rows.rows.len() as i64),
+                value => value.clone(),
+            })
+            .collect();
+        profile_rows.push(profile_row(&column_name, data_type, &values));
+        if let Some(sub_schema) = &schema.sub_table_schemas[column] {
+            let mut sub_rows = vec![];
+            for row in rows.iter() {
+                if let Value::SubTable(sub_table_rows) = &row.values[column] {
+                    sub_rows.extend(sub_table_rows.rows.iter());
+                }
+            }
+            profile_columns(sub_schema, &sub_rows, &format!("{}.", column_name), profile_rows);
+        }
+    }
+}
+
+impl Table {
+    /// statistics for every column as a new table.
+    /// The distinct count is exact.
+    pub fn profile(&self) -> Result<Table, Qvs20Error> {
+        let schema = profile_schema(&self.schema.table_name)?;
+        let mut table_rows = TableRows::default();
+        table_rows.table_name = s!("profile");
+        table_rows.row_delimiter = b'\n';
+        let rows: Vec<&Row> = self.table_rows.rows.iter().collect();
+        profile_columns(&self.schema, &rows, "", &mut table_rows.rows);
+        //return
+        Ok(Table { schema, table_rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_profile() {
+        let s = r"[T][countries][description]
+[String][Integer][SubTable]
+[][][1[U][cities][sub table]1[String]1[]1[]1[city]1]
+[][][]
+[country][population][cities]
+[Slovenia][2][1[Ljubljana]1[Koper]1]
+[Croatia][][]
+[Italia][5][1[Roma]1]
+[Malta][2][1[Valletta]1]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let profile = unwrap!(table.profile());
+        assert_eq!(
+            profile.write_table(),
+            "[T][profile][profile of countries]
+[String][String][Integer][Integer][Integer][String][String][Float][Integer][Integer][Float][SubTable]
+[][][][][][][][][][][][1[U][top_values][]1[String][Integer]1[][]1[][]1[value][count]1]
+[primary_key=1][][][][][][][][][][][]
+[column][data_type][rows][nulls][distinct][min][max][mean][min_length][max_length][avg_length][top_values]
+[country][String][4][0][4][Croatia][Slovenia][][5][8][6.5][1[Croatia][1]1[Italia][1]1[Malta][1]1[Slovenia][1]1]
+[population][Integer][4][1][2][2][5][3.0][][][][1[2][2]1[5][1]1]
+[cities][SubTable][4][0][3][0][2][1.0][][][][1[1][2]1[0][1]1[2][1]1]
+[cities.city][String][4][0][4][Koper][Valletta][][4][9][6.5][1[Koper][1]1[Ljubljana][1]1[Roma][1]1[Valletta][1]1]
+"
+        );
+    }
+}
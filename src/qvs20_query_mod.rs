@@ -0,0 +1,833 @@
+// qvs20_query_mod
+
+//! A small SQL-like query language over tables:
+//!
+//! ```text
+//! SELECT country, cities.city, COUNT(*) AS n
+//! FROM countries
+//! WHERE population > 1000000 AND NOT capital IS NULL
+//! GROUP BY country
+//! ORDER BY country DESC
+//! LIMIT 10
+//! ```
+//!
+//! - Keywords are case insensitive. Names with spaces are in double quotes: `"Country name"`.
+//! - `cities.city` is the column city of the sub table in the column cities.
+//!   In WHERE it is true if any row of the sub table matches.
+//! - Literals: `'text'` with `''` for a quote, numbers, TRUE, FALSE.
+//!   Text literals are converted to the data type of the column: `founded < '2000-01-01'`.
+//!   Numbers only compare with Integer, Decimal and Float columns.
+//! - Comparisons with Null are false. Use `IS NULL` and `IS NOT NULL`.
+//! - Aggregates: COUNT(*), SUM, AVG, MIN, MAX, FIRST, LAST.
+//!   With GROUP BY the other selected columns are collected into the sub table column `group`.
+//! - Without GROUP BY and ORDER BY the rows are streamed and the reading stops at LIMIT.
+
+use crate::qvs20_group_by_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_row_stream_mod::*;
+use crate::qvs20_sort_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_ops_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// name of the sub table column with the grouped rows
+const GROUP_COLUMN: &str = "group";
+
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "ORDER", "BY", "LIMIT", "AND", "OR", "NOT", "IS", "NULL", "TRUE", "FALSE", "ASC", "DESC", "AS",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum QueryToken {
+    /// keyword or name
+    Word(String),
+    /// name in double quotes
+    QuotedName(String),
+    /// text in single quotes
+    Text(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Text(String),
+    Number(String),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Column(String),
+    Literal(Literal),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Compare(Operand, CompareOp, Operand),
+    IsNull(Operand, bool),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum SelectItem {
+    All,
+    Column { name: String, alias: Option<String> },
+    Aggregate { function: String, column: Option<String>, alias: Option<String> },
+}
+
+/// Parsed query. Execute it on a Table or on a stream of rows.
+#[derive(Clone, Debug)]
+pub struct Query {
+    select: Vec<SelectItem>,
+    from: Option<String>,
+    filter: Option<Expr>,
+    group_by: Vec<String>,
+    order_by: Vec<(String, SortOrder)>,
+    limit: Option<usize>,
+}
+
+fn query_error(msg: String) -> Qvs20Error {
+    Qvs20Error::Error { msg }
+}
+
+/// `-` is the sign of a number only where an operand is expected, so `a-1` is not `a` and `-1`
+fn minus_is_sign(tokens: &[QueryToken]) -> bool {
+    match tokens.last() {
+        None => true,
+        Some(QueryToken::Symbol(s)) => *s != ")",
+        Some(QueryToken::Word(w)) => {
+            KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(w)) && !["NULL", "TRUE", "FALSE"].iter().any(|k| k.eq_ignore_ascii_case(w))
+        }
+        Some(_) => false,
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<QueryToken>, Qvs20Error> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            // '' or "" inside is the escaped quote
+            let mut s = s!();
+            i += 1;
+            loop {
+                if i >= chars.len() {
+                    return Err(query_error(format!("Query has unterminated quote {}.", c)));
+                }
+                if chars[i] == c {
+                    if i + 1 < chars.len() && chars[i + 1] == c {
+                        s.push(c);
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                s.push(chars[i]);
+                i += 1;
+            }
+            tokens.push(if c == '\'' { QueryToken::Text(s) } else { QueryToken::QuotedName(s) });
+        } else if c.is_ascii_digit() || (c == '-' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit() && minus_is_sign(&tokens)) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E') {
+                i += 1;
+            }
+            tokens.push(QueryToken::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(QueryToken::Word(chars[start..i].iter().collect()));
+        } else {
+            let two: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = match two.as_str() {
+                "<=" => "<=",
+                ">=" => ">=",
+                "<>" => "!=",
+                "!=" => "!=",
+                _ => match c {
+                    ',' => ",",
+                    '(' => "(",
+                    ')' => ")",
+                    '*' => "*",
+                    '.' => ".",
+                    '=' => "=",
+                    '<' => "<",
+                    '>' => ">",
+                    _ => return Err(query_error(format!("Query has unexpected character {}.", c))),
+                },
+            };
+            i += symbol.len();
+            tokens.push(QueryToken::Symbol(symbol));
+        }
+    }
+    //return
+    Ok(tokens)
+}
+
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Qvs20Error> {
+        if !self.accept_keyword(keyword) {
+            return Err(self.unexpected(keyword));
+        }
+        Ok(())
+    }
+    fn accept_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(QueryToken::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), Qvs20Error> {
+        if !self.accept_symbol(symbol) {
+            return Err(self.unexpected(symbol));
+        }
+        Ok(())
+    }
+    fn unexpected(&self, expected: &str) -> Qvs20Error {
+        let found = match self.peek() {
+            Some(QueryToken::Word(w)) | Some(QueryToken::QuotedName(w)) | Some(QueryToken::Number(w)) => w.clone(),
+            Some(QueryToken::Text(t)) => format!("'{}'", t),
+            Some(QueryToken::Symbol(s)) => s!(s),
+            None => s!("end of query"),
+        };
+        query_error(format!("Query expected {} but found {}.", expected, found))
+    }
+    /// one part of a name
+    fn name_part(&mut self) -> Result<String, Qvs20Error> {
+        match self.peek().cloned() {
+            Some(QueryToken::Word(w)) if !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(&w)) => {
+                self.pos += 1;
+                Ok(w)
+            }
+            Some(QueryToken::QuotedName(w)) => {
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+    /// name with sub table parts: cities.city
+    fn name(&mut self) -> Result<String, Qvs20Error> {
+        let mut name = self.name_part()?;
+        while self.accept_symbol(".") {
+            name.push('.');
+            name.push_str(&self.name_part()?);
+        }
+        Ok(name)
+    }
+    fn alias(&mut self) -> Result<Option<String>, Qvs20Error> {
+        if self.accept_keyword("AS") {
+            return Ok(Some(self.name_part()?));
+        }
+        Ok(None)
+    }
+    fn select_item(&mut self) -> Result<SelectItem, Qvs20Error> {
+        if self.accept_symbol("*") {
+            return Ok(SelectItem::All);
+        }
+        if let (Some(QueryToken::Word(w)), Some(QueryToken::Symbol("("))) = (self.peek().cloned(), self.tokens.get(self.pos + 1)) {
+            let function = w.to_uppercase();
+            if !["COUNT", "SUM", "AVG", "MIN", "MAX", "FIRST", "LAST"].contains(&function.as_str()) {
+                return Err(query_error(format!("Query has unknown function {}.", w)));
+            }
+            self.pos += 2;
+            let column = if function == "COUNT" && self.accept_symbol("*") {
+                None
+            } else {
+                Some(self.name()?)
+            };
+            self.expect_symbol(")")?;
+            let alias = self.alias()?;
+            return Ok(SelectItem::Aggregate { function, column, alias });
+        }
+        let name = self.name()?;
+        let alias = self.alias()?;
+        Ok(SelectItem::Column { name, alias })
+    }
+    fn operand(&mut self) -> Result<Operand, Qvs20Error> {
+        let literal = match self.peek().cloned() {
+            Some(QueryToken::Text(t)) => Literal::Text(t),
+            Some(QueryToken::Number(n)) => Literal::Number(n),
+            Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case("TRUE") => Literal::Bool(true),
+            Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case("FALSE") => Literal::Bool(false),
+            Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case("NULL") => {
+                return Err(query_error(s!("Query compares with NULL. Use IS NULL or IS NOT NULL.")))
+            }
+            _ => return Ok(Operand::Column(self.name()?)),
+        };
+        self.pos += 1;
+        Ok(Operand::Literal(literal))
+    }
+    fn expr(&mut self) -> Result<Expr, Qvs20Error> {
+        let mut left = self.and_expr()?;
+        while self.accept_keyword("OR") {
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+    fn and_expr(&mut self) -> Result<Expr, Qvs20Error> {
+        let mut left = self.not_expr()?;
+        while self.accept_keyword("AND") {
+            left = Expr::And(Box::new(left), Box::new(self.not_expr()?));
+        }
+        Ok(left)
+    }
+    fn not_expr(&mut self) -> Result<Expr, Qvs20Error> {
+        if self.accept_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        if self.accept_symbol("(") {
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        if self.accept_keyword("IS") {
+            let negated = self.accept_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull(left, negated));
+        }
+        let op = match self.peek() {
+            Some(QueryToken::Symbol("=")) => CompareOp::Eq,
+            Some(QueryToken::Symbol("!=")) => CompareOp::Ne,
+            Some(QueryToken::Symbol("<")) => CompareOp::Lt,
+            Some(QueryToken::Symbol("<=")) => CompareOp::Le,
+            Some(QueryToken::Symbol(">")) => CompareOp::Gt,
+            Some(QueryToken::Symbol(">=")) => CompareOp::Ge,
+            _ => return Err(self.unexpected("a comparison")),
+        };
+        self.pos += 1;
+        let right = self.operand()?;
+        Ok(Expr::Compare(left, op, right))
+    }
+    fn query(&mut self) -> Result<Query, Qvs20Error> {
+        self.expect_keyword("SELECT")?;
+        let mut select = vec![self.select_item()?];
+        while self.accept_symbol(",") {
+            select.push(self.select_item()?);
+        }
+        let from = if self.accept_keyword("FROM") { Some(self.name_part()?) } else { None };
+        let filter = if self.accept_keyword("WHERE") { Some(self.expr()?) } else { None };
+        let mut group_by = vec![];
+        if self.accept_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            group_by.push(self.name()?);
+            while self.accept_symbol(",") {
+                group_by.push(self.name()?);
+            }
+        }
+        let mut order_by = vec![];
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let name = self.name()?;
+                let order = if self.accept_keyword("DESC") {
+                    SortOrder::Desc
+                } else {
+                    self.accept_keyword("ASC");
+                    SortOrder::Asc
+                };
+                order_by.push((name, order));
+                if !self.accept_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.accept_keyword("LIMIT") {
+            match self.peek().cloned() {
+                Some(QueryToken::Number(n)) => match n.parse::<usize>() {
+                    Ok(n) => {
+                        self.pos += 1;
+                        Some(n)
+                    }
+                    Err(_) => return Err(self.unexpected("a positive integer")),
+                },
+                _ => return Err(self.unexpected("a positive integer")),
+            }
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            return Err(self.unexpected("end of query"));
+        }
+        Ok(Query {
+            select,
+            from,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+}
+
+/// operand with the column path resolved and the literal converted to the data type
+enum Resolved {
+    /// column indexes from the table to the sub tables
+    Column(Vec<usize>),
+    Value(Value),
+}
+
+enum Condition {
+    Compare(Resolved, CompareOp, Resolved),
+    IsNull(Resolved, bool),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+/// column indexes and the data type of the column
+fn resolve_path(schema: &TableSchema, name: &str) -> Result<(Vec<usize>, DataType), Qvs20Error> {
+    if let Some(column) = schema.column_index(name) {
+        return Ok((vec![column], schema.data_types[column].clone()));
+    }
+    if let Some((name, sub_name)) = name.split_once('.') {
+        if let Some(column) = schema.column_index(name) {
+            if let Some(sub_schema) = &schema.sub_table_schemas[column] {
+                let (mut path, data_type) = resolve_path(sub_schema, sub_name)?;
+                path.insert(0, column);
+                return Ok((path, data_type));
+            }
+        }
+    }
+    Err(query_error(format!("Table {} does not have column {}.", schema.table_name, name)))
+}
+
+/// the literal as a value of the data type
+fn literal_value(literal: &Literal, data_type: Option<&DataType>) -> Result<Value, Qvs20Error> {
+    let value = match (literal, data_type) {
+        (Literal::Bool(b), _) => Value::Bool(*b),
+        (Literal::Text(t), None) | (Literal::Text(t), Some(DataType::String)) => Value::String(t.clone()),
+        (Literal::Text(t), Some(data_type)) => match TableRows::value_from_str(t, data_type) {
+            Ok(v) => v,
+            Err(_) => return Err(query_error(format!("Query literal '{}' is not {}.", t, data_type))),
+        },
+        (Literal::Number(n), Some(data_type)) if !matches!(data_type, DataType::Integer | DataType::Decimal | DataType::Float) => {
+            return Err(query_error(format!("Query literal {} is not {}.", n, data_type)))
+        }
+        (Literal::Number(n), _) => {
+            if let Ok(i) = n.parse::<i64>() {
+                Value::Integer(i)
+            } else if let Ok(Value::Decimal(d)) = TableRows::value_from_str(n, &DataType::Decimal) {
+                Value::Decimal(d)
+            } else if let Ok(f) = n.parse::<f64>() {
+                Value::Float(f)
+            } else {
+                return Err(query_error(format!("Query has wrong number {}.", n)));
+            }
+        }
+    };
+    Ok(value)
+}
+
+fn resolve_operand(schema: &TableSchema, operand: &Operand, other: &Operand) -> Result<Resolved, Qvs20Error> {
+    match operand {
+        Operand::Column(name) => Ok(Resolved::Column(resolve_path(schema, name)?.0)),
+        Operand::Literal(literal) => {
+            // the literal gets the data type of the column on the other side
+            let data_type = match other {
+                Operand::Column(name) => Some(resolve_path(schema, name)?.1),
+                Operand::Literal(_) => None,
+            };
+            Ok(Resolved::Value(literal_value(literal, data_type.as_ref())?))
+        }
+    }
+}
+
+fn compile(schema: &TableSchema, expr: &Expr) -> Result<Condition, Qvs20Error> {
+    let condition = match expr {
+        Expr::Compare(left, op, right) => Condition::Compare(resolve_operand(schema, left, right)?, *op, resolve_operand(schema, right, left)?),
+        Expr::IsNull(operand, negated) => Condition::IsNull(resolve_operand(schema, operand, operand)?, *negated),
+        Expr::And(a, b) => Condition::And(Box::new(compile(schema, a)?), Box::new(compile(schema, b)?)),
+        Expr::Or(a, b) => Condition::Or(Box::new(compile(schema, a)?), Box::new(compile(schema, b)?)),
+        Expr::Not(a) => Condition::Not(Box::new(compile(schema, a)?)),
+    };
+    Ok(condition)
+}
+
+/// all values on the path. For sub tables the values of all rows.
+fn collect_values<'v>(values: &'v [Value], path: &[usize], found: &mut Vec<&'v Value>) {
+    let value = &values[path[0]];
+    if path.len() == 1 {
+        found.push(value);
+    } else if let Value::SubTable(sub_table_rows) = value {
+        for row in sub_table_rows.rows.iter() {
+            collect_values(&row.values, &path[1..], found);
+        }
+    }
+}
+
+fn resolved_values<'v>(resolved: &'v Resolved, row: &'v Row) -> Vec<&'v Value> {
+    match resolved {
+        Resolved::Value(v) => vec![v],
+        Resolved::Column(path) => {
+            let mut found = vec![];
+            collect_values(&row.values, path, &mut found);
+            found
+        }
+    }
+}
+
+/// without WHERE all rows match
+fn matches_condition(condition: &Option<Condition>, row: &Row) -> bool {
+    match condition {
+        Some(condition) => evaluate(condition, row),
+        None => true,
+    }
+}
+
+fn evaluate(condition: &Condition, row: &Row) -> bool {
+    match condition {
+        Condition::Compare(left, op, right) => {
+            let left_values = resolved_values(left, row);
+            let right_values = resolved_values(right, row);
+            // true if any pair matches
+            left_values.iter().any(|a| {
+                right_values.iter().any(|b| {
+                    if matches!(a, Value::Null) || matches!(b, Value::Null) {
+                        return false;
+                    }
+                    match op {
+                        CompareOp::Eq => a == b,
+                        CompareOp::Ne => a != b,
+                        CompareOp::Lt => a < b,
+                        CompareOp::Le => a <= b,
+                        CompareOp::Gt => a > b,
+                        CompareOp::Ge => a >= b,
+                    }
+                })
+            })
+        }
+        Condition::IsNull(operand, negated) => {
+            let is_null = resolved_values(operand, row).iter().any(|v| matches!(v, Value::Null));
+            is_null != *negated
+        }
+        Condition::And(a, b) => evaluate(a, row) && evaluate(b, row),
+        Condition::Or(a, b) => evaluate(a, row) || evaluate(b, row),
+        Condition::Not(a) => !evaluate(a, row),
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, Qvs20Error> {
+        let mut parser = QueryParser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        parser.query()
+    }
+
+    fn has_aggregates(&self) -> bool {
+        self.select.iter().any(|s| matches!(s, SelectItem::Aggregate { .. }))
+    }
+
+    /// rows can be streamed without GROUP BY, ORDER BY and aggregates
+    pub fn is_streaming(&self) -> bool {
+        self.group_by.is_empty() && self.order_by.is_empty() && !self.has_aggregates()
+    }
+
+    fn check_from(&self, schema: &TableSchema) -> Result<(), Qvs20Error> {
+        match &self.from {
+            Some(from) if *from != schema.table_name => Err(query_error(format!(
+                "Query is FROM {}, but the table is {}.",
+                from, schema.table_name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// execute the query on the table
+    pub fn execute(&self, table: &Table) -> Result<Table, Qvs20Error> {
+        self.run(&table.schema, table.table_rows.rows.iter().cloned().map(Ok))
+    }
+
+    /// execute the query on a `[T]` string. Without GROUP BY and ORDER BY the rows are streamed.
+    pub fn execute_str(&self, input: &str) -> Result<Table, Qvs20Error> {
+        let stream = RowStream::from_qvs20_str_with_schema(input)?;
+        let schema = stream.schema.clone();
+        self.run(&schema, stream)
+    }
+
+    fn run<I>(&self, schema: &TableSchema, rows: I) -> Result<Table, Qvs20Error>
+    where
+        I: Iterator<Item = Result<Row, Qvs20Error>>,
+    {
+        self.check_from(schema)?;
+        let condition = match &self.filter {
+            Some(expr) => Some(compile(schema, expr)?),
+            None => None,
+        };
+        let mut table_rows = TableRows::default();
+        table_rows.row_delimiter = schema.row_delimiter;
+        if self.is_streaming() {
+            // projection of every row and stop at the limit
+            let names = self.selected_names(&[]);
+            let selection = match &names {
+                Some(names) => Some(parse_selection(schema, &names.iter().map(|x| x.as_str()).collect::<Vec<&str>>())?),
+                None => None,
+            };
+            let mut result_schema = match &selection {
+                Some(selection) => select_schema(schema, selection)?,
+                None => schema.clone(),
+            };
+            for row in rows {
+                if matches!(self.limit, Some(limit) if table_rows.rows.len() >= limit) {
+                    break;
+                }
+                let row = row?;
+                if matches_condition(&condition, &row) {
+                    match &selection {
+                        Some(selection) => table_rows.rows.push(select_row(&row, schema, selection)),
+                        None => table_rows.rows.push(row),
+                    }
+                }
+            }
+            self.rename_aliases(&mut result_schema, &[])?;
+            return Ok(Table {
+                schema: result_schema,
+                table_rows,
+            });
+        }
+        for row in rows {
+            let row = row?;
+            if matches_condition(&condition, &row) {
+                table_rows.rows.push(row);
+            }
+        }
+        let mut table = Table {
+            schema: schema.clone(),
+            table_rows,
+        };
+        let group_by: Vec<&str> = self.group_by.iter().map(|x| x.as_str()).collect();
+        if !group_by.is_empty() || self.has_aggregates() {
+            table = table.group_by(&group_by, GROUP_COLUMN)?;
+            if group_by.is_empty() && table.table_rows.rows.is_empty() {
+                // aggregates without GROUP BY return one row like in SQL, also without rows
+                let mut sub_table_rows = TableRows::default();
+                sub_table_rows.table_name = s!(GROUP_COLUMN);
+                sub_table_rows.row_delimiter = table.schema.sub_table_schemas[0].as_ref().map_or(0, |x| x.row_delimiter);
+                table.table_rows.rows.push(Row {
+                    values: vec![Value::SubTable(sub_table_rows)],
+                });
+            }
+            let mut aggregates = vec![];
+            for item in self.select.iter() {
+                if let SelectItem::Aggregate { function, column, .. } = item {
+                    let column = column.as_deref().unwrap_or("");
+                    let aggregate = match function.as_str() {
+                        "COUNT" => Aggregate::Count,
+                        "SUM" => Aggregate::Sum(column),
+                        "AVG" => Aggregate::Avg(column),
+                        "MIN" => Aggregate::Min(column),
+                        "MAX" => Aggregate::Max(column),
+                        "FIRST" => Aggregate::First(column),
+                        _ => Aggregate::Last(column),
+                    };
+                    aggregates.push((self.output_name(item, &group_by), aggregate));
+                }
+            }
+            let aggregates: Vec<(&str, Aggregate)> = aggregates.iter().map(|(name, a)| (name.as_str(), *a)).collect();
+            table = table.add_aggregates(GROUP_COLUMN, &aggregates)?;
+        }
+        if !self.order_by.is_empty() {
+            let order_by: Vec<(String, SortOrder)> = self
+                .order_by
+                .iter()
+                .map(|(name, order)| (self.source_name(name, &group_by), *order))
+                .collect();
+            let order_by: Vec<(&str, SortOrder)> = order_by.iter().map(|(name, order)| (name.as_str(), *order)).collect();
+            table.sort_by_columns(&order_by)?;
+        }
+        if let Some(limit) = self.limit {
+            table.table_rows.rows.truncate(limit);
+        }
+        if let Some(names) = self.selected_names(&group_by) {
+            table = table.select(&names.iter().map(|x| x.as_str()).collect::<Vec<&str>>())?;
+        }
+        self.rename_aliases(&mut table.schema, &group_by)?;
+        //return
+        Ok(table)
+    }
+
+    /// column name in the table for the selected column.
+    /// With GROUP BY the columns that are not keys are in the sub table `group`.
+    fn column_source(&self, name: &str, group_by: &[&str]) -> String {
+        if (group_by.is_empty() && !self.has_aggregates()) || group_by.contains(&name) {
+            s!(name)
+        } else {
+            format!("{}.{}", GROUP_COLUMN, name)
+        }
+    }
+
+    /// name of the result column of the select item
+    fn output_name(&self, item: &SelectItem, group_by: &[&str]) -> String {
+        match item {
+            SelectItem::All => s!("*"),
+            SelectItem::Column { name, alias } => alias.clone().unwrap_or_else(|| self.column_source(name, group_by)),
+            SelectItem::Aggregate { function, column, alias } => match (alias, column) {
+                (Some(alias), _) => alias.clone(),
+                (None, Some(column)) => format!("{}_{}", function.to_lowercase(), column),
+                (None, None) => function.to_lowercase(),
+            },
+        }
+    }
+
+    /// ORDER BY can use the aliases and the names of the columns
+    fn source_name(&self, name: &str, group_by: &[&str]) -> String {
+        for item in self.select.iter() {
+            match item {
+                SelectItem::Column { name: column, alias: Some(alias) } if alias == name => return self.column_source(column, group_by),
+                SelectItem::Aggregate { .. } if self.output_name(item, group_by) == name => return s!(name),
+                _ => (),
+            }
+        }
+        self.column_source(name, group_by)
+    }
+
+    /// the names for Table::select(). None for SELECT *.
+    fn selected_names(&self, group_by: &[&str]) -> Option<Vec<String>> {
+        if self.select.iter().any(|s| matches!(s, SelectItem::All)) {
+            return None;
+        }
+        let names = self
+            .select
+            .iter()
+            .map(|item| match item {
+                SelectItem::Column { name, .. } => self.column_source(name, group_by),
+                _ => self.output_name(item, group_by),
+            })
+            .collect();
+        Some(names)
+    }
+
+    fn rename_aliases(&self, schema: &mut TableSchema, group_by: &[&str]) -> Result<(), Qvs20Error> {
+        let mut table = Table {
+            schema: schema.clone(),
+            table_rows: TableRows::default(),
+        };
+        for item in self.select.iter() {
+            if let SelectItem::Column { name, alias: Some(alias) } = item {
+                table.rename_column(&self.column_source(name, group_by), alias)?;
+            }
+        }
+        *schema = table.schema;
+        Ok(())
+    }
+}
+
+impl Table {
+    /// parse and execute the query on this table
+    pub fn query(&self, text: &str) -> Result<Table, Qvs20Error> {
+        Query::parse(text)?.execute(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    const COUNTRIES: &str = r"[T][countries][description]
+[String][Integer][Date][SubTable]
+[][][][1[U][cities][sub table]1[String][Integer]1[][]1[][]1[city][population]1]
+[][][][]
+[country][population][founded][cities]
+[Slovenia][2000000][1991-06-25][1[Ljubljana][300000]1[Koper][30000]1]
+[Croatia][4000000][1991-06-25][]
+[Italia][60000000][1861-03-17][1[Milano][1400000]1[Roma][2800000]1]
+[Austria][9000000][][1[Wien][1900000]1]
+";
+
+    #[test]
+    pub fn t01_select_where_order_limit() {
+        let table = unwrap!(Table::from_qvs20_str_with_schema(COUNTRIES));
+        let result = unwrap!(table.query(
+            "select country AS name, cities.city from countries where cities.population > 1000000 or founded > '1990-01-01' order by name desc limit 2"
+        ));
+        assert_eq!(
+            result.write_table(),
+            "[T][countries][description]\n[String][SubTable]\n[][1[U][cities][sub table]1[String]1[]1[]1[city]1]\n[][]\n[name][cities]\n[Slovenia][1[Ljubljana]1[Koper]1]\n[Italia][1[Milano]1[Roma]1]\n"
+        );
+        let result = unwrap!(table.query("SELECT country WHERE founded IS NULL OR NOT population >= 3000000"));
+        assert_eq!(result.table_rows.rows.len(), 2);
+
+        let err = table.query("SELECT country WHERE founded > 'yesterday'").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Query literal 'yesterday' is not Date.");
+        let err = table.query("SELECT country WHERE country = 1").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Query literal 1 is not String.");
+        let err = table.query("SELECT country WHERE population-1 > 0").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Query has unexpected character -.");
+        let err = table.query("SELECT country WHERE population >").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Query expected a name but found end of query.");
+    }
+
+    #[test]
+    pub fn t02_group_by() {
+        let table = unwrap!(Table::from_qvs20_str_with_schema(COUNTRIES));
+        let result = unwrap!(table.query(
+            "SELECT founded, country, COUNT(*) AS n, SUM(population) GROUP BY founded ORDER BY n DESC, founded"
+        ));
+        assert_eq!(
+            result.write_table(),
+            "[T][countries][description]\n[Date][SubTable][Integer][Integer]\n[][1[U][group][]1[String]1[]1[]1[country]1][][]\n[][][][]\n[founded][group][n][sum_population]\n[1991-06-25][1[Slovenia]1[Croatia]1][2][6000000]\n[][1[Austria]1][1][9000000]\n[1861-03-17][1[Italia]1][1][60000000]\n"
+        );        assert!(Table::from_qvs20_str_with_schema(&result.write_table()).is_ok());
+
+        // aggregates without GROUP BY have one row also for an empty table
+        let mut empty = table.clone();
+        empty.table_rows.rows.clear();
+        let result = unwrap!(empty.query("SELECT COUNT(*) AS n, SUM(population)"));
+        assert_eq!(result.table_rows.rows.len(), 1);
+        assert_eq!(result.table_rows.rows[0].values, vec![Value::Integer(0), Value::Null]);
+    }
+
+    #[test]
+    pub fn t03_streaming_stops_at_limit() {
+        // the last row is wrong, but the reading stops before it
+        let text = format!("{}[Spain][wrong][][]\n", COUNTRIES);
+        let query = unwrap!(Query::parse("SELECT country WHERE population < 10000000 LIMIT 3"));
+        assert!(query.is_streaming());
+        let result = unwrap!(query.execute_str(&text));
+        assert_eq!(result.table_rows.rows.len(), 3);
+        assert!(Query::parse("SELECT country").unwrap().execute_str(&text).is_err());
+    }
+}
@@ -19,6 +19,13 @@ pub enum Qvs20Error {
     Error { msg: String },
 }
 
+/// the same error for all io errors of readers and writers
+pub fn io_error(e: std::io::Error) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("Cannot read or write: {}", e),
+    }
+}
+
 #[macro_export]
 macro_rules! src_loc {
     () => {
@@ -157,7 +164,7 @@ impl<'a> ReaderForQvs20<'a> {
     }
     /// peek if next character will be row_delimiter
     pub fn peek_next_is_row_delimiter(&self) -> bool {
-        if self.input[self.cursor_pos] == self.row_delimiter {
+        if self.cursor_pos < self.input.len() && self.input[self.cursor_pos] == self.row_delimiter {
             true
         } else {
             false
@@ -380,9 +387,9 @@ impl<'a> ReaderForQvs20<'a> {
     pub fn next_row_as_vec_of_string(&mut self) -> Result<Vec<String>, Qvs20Error> {
         let mut vec_of_string = vec![];
         while !self.peek_next_is_row_delimiter() {
-            vec_of_string.push(unwrap!(self.next_string()));
+            vec_of_string.push(self.next_string()?);
         }
-        unwrap!(self.next_row_delimiter());
+        self.next_row_delimiter()?;
         //return
         Ok(vec_of_string)
     }
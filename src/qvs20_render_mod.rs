@@ -0,0 +1,292 @@
+// qvs20_render_mod
+
+//! Render tables for documents and reports: HTML with nested tables and Markdown.
+//! In HTML the description is the caption and the data type is the tooltip of the column header.
+//! Markdown tables cannot be nested, so sub tables are separate sections with links
+//! or short inline summaries.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// count of sub rows shown in the inline summary
+const INLINE_SUMMARY_ROWS: usize = 3;
+
+/// how to render sub tables in Markdown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubTableDisplay {
+    /// every sub table is a section after the table, the cell is a link to it
+    Sections,
+    /// the cell has the count of rows and the first values
+    Inline,
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// escape for a Markdown table cell. All ASCII punctuation can be escaped with backslash.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("<br>"),
+            '\r' => (),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// part of the anchor id: lowercase letters, digits and dashes
+fn anchor_part(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect()
+}
+
+fn is_number(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Integer | DataType::Decimal | DataType::Float)
+}
+
+fn html_table(schema: &TableSchema, table_rows: &TableRows, html: &mut String) {
+    html.push_str("<table>\n");
+    if !schema.table_description.is_empty() {
+        html.push_str(&format!("<caption>{}</caption>\n", escape_html(&schema.table_description)));
+    }
+    html.push_str("<thead><tr>");
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        html.push_str(&format!(
+            "<th title=\"{}\">{}</th>",
+            schema.data_types[column],
+            escape_html(column_name)
+        ));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in table_rows.rows.iter() {
+        html.push_str("<tr>");
+        for (column, value) in row.values.iter().enumerate() {
+            match (value, &schema.sub_table_schemas[column]) {
+                (Value::SubTable(sub_table_rows), Some(sub_schema)) => {
+                    html.push_str("<td>\n");
+                    html_table(sub_schema, sub_table_rows, html);
+                    html.push_str("</td>");
+                }
+                (value, _) if is_number(&schema.data_types[column]) => {
+                    html.push_str(&format!("<td align=\"right\">{}</td>", escape_html(&value_to_string(value))));
+                }
+                (value, _) => html.push_str(&format!("<td>{}</td>", escape_html(&value_to_string(value)))),
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+/// the sub table that is rendered in its own section
+struct Section<'a> {
+    anchor: String,
+    title: String,
+    schema: &'a TableSchema,
+    table_rows: &'a TableRows,
+}
+
+/// the Markdown table. Sub tables for sections are added to the list.
+fn markdown_table<'a>(
+    schema: &'a TableSchema,
+    table_rows: &'a TableRows,
+    display: SubTableDisplay,
+    anchor: &str,
+    title: &str,
+    sections: &mut Vec<Section<'a>>,
+) -> String {
+    let mut md = s!();
+    md.push('|');
+    for column_name in schema.column_names.iter() {
+        md.push_str(&format!(" {} |", escape_markdown(column_name)));
+    }
+    md.push_str("\n|");
+    for data_type in schema.data_types.iter() {
+        md.push_str(if is_number(data_type) { " ---: |" } else { " --- |" });
+    }
+    md.push('\n');
+    for (row_index, row) in table_rows.rows.iter().enumerate() {
+        md.push('|');
+        for (column, value) in row.values.iter().enumerate() {
+            let cell = match (value, &schema.sub_table_schemas[column]) {
+                (Value::SubTable(sub_table_rows), _) if sub_table_rows.rows.is_empty() => s!("0 rows"),
+                (Value::SubTable(sub_table_rows), Some(sub_schema)) => match display {
+                    SubTableDisplay::Sections => {
+                        let column_name = &schema.column_names[column];
+                        let sub_anchor = format!("{}-{}-{}", anchor, anchor_part(column_name), row_index + 1);
+                        let link = format!("[{} rows](#{})", sub_table_rows.rows.len(), sub_anchor);
+                        sections.push(Section {
+                            anchor: sub_anchor,
+                            title: format!("{} row {} {}", title, row_index + 1, column_name),
+                            schema: sub_schema,
+                            table_rows: sub_table_rows,
+                        });
+                        link
+                    }
+                    SubTableDisplay::Inline => {
+                        let mut first_values: Vec<String> = sub_table_rows
+                            .rows
+                            .iter()
+                            .take(INLINE_SUMMARY_ROWS)
+                            .filter_map(|r| r.values.first().map(value_to_string))
+                            .collect();
+                        if sub_table_rows.rows.len() > INLINE_SUMMARY_ROWS {
+                            first_values.push(s!("…"));
+                        }
+                        format!("{} rows: {}", sub_table_rows.rows.len(), escape_markdown(&first_values.join(", ")))
+                    }
+                },
+                (value, _) => escape_markdown(&value_to_string(value)),
+            };
+            md.push_str(&format!(" {} |", cell));
+        }
+        md.push('\n');
+    }
+    //return
+    md
+}
+
+impl Table {
+    /// HTML table with nested tables for sub tables
+    pub fn to_html(&self) -> String {
+        let mut html = s!();
+        html_table(&self.schema, &self.table_rows, &mut html);
+        //return
+        html
+    }
+
+    /// Markdown with the table name as heading and the description
+    pub fn to_markdown(&self, display: SubTableDisplay) -> String {
+        let anchor = anchor_part(&self.schema.table_name);
+        let mut md = format!("## {}\n\n", escape_markdown(&self.schema.table_name));
+        if !self.schema.table_description.is_empty() {
+            md.push_str(&format!("{}\n\n", escape_markdown(&self.schema.table_description)));
+        }
+        let mut sections = vec![];
+        md.push_str(&markdown_table(
+            &self.schema,
+            &self.table_rows,
+            display,
+            &anchor,
+            &self.schema.table_name,
+            &mut sections,
+        ));
+        // sections can add more sections for deeper sub tables
+        let mut i = 0;
+        while i < sections.len() {
+            let section = &sections[i];
+            let (anchor, title, schema, table_rows) = (section.anchor.clone(), section.title.clone(), section.schema, section.table_rows);
+            md.push_str(&format!("\n<a id=\"{}\"></a>\n\n### {}\n\n", anchor, escape_markdown(&title)));
+            let table = markdown_table(schema, table_rows, display, &anchor, &title, &mut sections);
+            md.push_str(&table);
+            i += 1;
+        }
+        //return
+        md
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_html_and_markdown() {
+        let s = r"[T][countries][big & small]
+[String][Integer][SubTable]
+[][][1[U][cities][sub table]1[String]1[]1[]1[city]1]
+[][][]
+[country][population][cities]
+[Slovenia <SI>][2000000][1[Ljubljana]1[Koper]1[Piran]1[Bled]1]
+[Bosna | Herzegovina][][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        assert_eq!(
+            table.to_html(),
+            r#"<table>
+<caption>big &amp; small</caption>
+<thead><tr><th title="String">country</th><th title="Integer">population</th><th title="SubTable">cities</th></tr></thead>
+<tbody>
+<tr><td>Slovenia &lt;SI&gt;</td><td align="right">2000000</td><td>
+<table>
+<caption>sub table</caption>
+<thead><tr><th title="String">city</th></tr></thead>
+<tbody>
+<tr><td>Ljubljana</td></tr>
+<tr><td>Koper</td></tr>
+<tr><td>Piran</td></tr>
+<tr><td>Bled</td></tr>
+</tbody>
+</table>
+</td></tr>
+<tr><td>Bosna | Herzegovina</td><td align="right"></td><td>
+<table>
+<caption>sub table</caption>
+<thead><tr><th title="String">city</th></tr></thead>
+<tbody>
+</tbody>
+</table>
+</td></tr>
+</tbody>
+</table>
+"#
+        );
+        assert_eq!(
+            table.to_markdown(SubTableDisplay::Inline),
+            r"## countries
+
+big & small
+
+| country | population | cities |
+| --- | ---: | --- |
+| Slovenia \<SI\> | 2000000 | 4 rows: Ljubljana, Koper, Piran, … |
+| Bosna \| Herzegovina |  | 0 rows |
+"
+        );
+        assert_eq!(
+            table.to_markdown(SubTableDisplay::Sections),
+            r#"## countries
+
+big & small
+
+| country | population | cities |
+| --- | ---: | --- |
+| Slovenia \<SI\> | 2000000 | [4 rows](#countries-cities-1) |
+| Bosna \| Herzegovina |  | 0 rows |
+
+<a id="countries-cities-1"></a>
+
+### countries row 1 cities
+
+| city |
+| --- |
+| Ljubljana |
+| Koper |
+| Piran |
+| Bled |
+"#
+        );
+    }
+}
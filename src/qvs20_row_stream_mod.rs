@@ -0,0 +1,112 @@
+// qvs20_row_stream_mod
+
+//! Read the data rows one by one, without keeping all rows in memory.
+//! The constraints and unique keys are checked like in TableRows::rows_from_qvs20_str().
+//! For unique keys only the keys are kept in memory.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// Iterator over the rows of a QVS21 string.
+pub struct RowStream<'a> {
+    rdr: ReaderForQvs20<'a>,
+    pub schema: TableSchema,
+    /// holds the position for error messages, but not the rows
+    table_rows: TableRows,
+    constraints: SchemaConstraints,
+    unique_keys: UniqueKeys,
+    finished: bool,
+}
+
+impl<'a> RowStream<'a> {
+    fn new(rdr: ReaderForQvs20<'a>, schema: TableSchema, table_rows: TableRows) -> Result<RowStream<'a>, Qvs20Error> {
+        let constraints = SchemaConstraints::new(&schema)?;
+        let unique_keys = UniqueKeys::new(&schema, &constraints.columns)?;
+        //return
+        Ok(RowStream {
+            rdr,
+            schema,
+            table_rows,
+            constraints,
+            unique_keys,
+            finished: false,
+        })
+    }
+
+    /// stream from a `[T]` string with schema and rows
+    pub fn from_qvs20_str_with_schema(input: &'a str) -> Result<RowStream<'a>, Qvs20Error> {
+        let mut rdr = ReaderForQvs20::new(input.as_bytes());
+        let mut schema = TableSchema::default();
+        schema.read_schema(&mut rdr)?;
+        let mut table_rows = TableRows::default();
+        table_rows.row_delimiter = schema.row_delimiter;
+        RowStream::new(rdr, schema, table_rows)
+    }
+
+    /// stream from a `[R]` string with a separate schema
+    pub fn from_qvs20_str(input: &'a str, schema: &TableSchema) -> Result<RowStream<'a>, Qvs20Error> {
+        let mut rdr = ReaderForQvs20::new(input.as_bytes());
+        let mut table_rows = TableRows::default();
+        table_rows.row_delimiter = schema.row_delimiter;
+        table_rows.read_1st_row_file_type_and_table_name(&mut rdr)?;
+        if table_rows.table_name != schema.table_name {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "TableRows table name {} differs from TableSchema table name {}.",
+                    table_rows.table_name, schema.table_name
+                ),
+            });
+        }
+        RowStream::new(rdr, schema.clone(), table_rows)
+    }
+
+    /// count of rows read until now
+    pub fn rows_read(&self) -> usize {
+        self.table_rows.active_row
+    }
+}
+
+impl<'a> Iterator for RowStream<'a> {
+    type Item = Result<Row, Qvs20Error>;
+    /// the next row. After an error the stream ends.
+    fn next(&mut self) -> Option<Result<Row, Qvs20Error>> {
+        if self.finished {
+            return None;
+        }
+        match self
+            .table_rows
+            .next_data_row(&mut self.rdr, &self.schema, &self.constraints, &mut self.unique_keys)
+        {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_stream_rows() {
+        let s = "[T][table name][description]\n[String][Integer]\n[][]\n[][min=0]\n[name][count]\n[a][1]\n[b][2]\n[c][-3]\n";
+        let mut stream = unwrap!(RowStream::from_qvs20_str_with_schema(s));
+        assert_eq!(unwrap!(unwrap!(stream.next())).values, vec![Value::String(s!("a")), Value::Integer(1)]);
+        assert_eq!(stream.rows_read(), 1);
+        assert!(unwrap!(stream.next()).is_ok());
+        let err = unwrap!(stream.next()).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Constraint violation row 2 col 1 count: value -3 is less than min 0");
+        assert!(stream.next().is_none());
+    }
+}
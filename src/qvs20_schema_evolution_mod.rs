@@ -0,0 +1,327 @@
+// qvs20_schema_evolution_mod
+
+//! Compare two versions of a schema and migrate rows from the old to the new schema.
+//! Columns are matched by name, so the order of columns can change.
+//! Two keys in the additional properties of the new schema help the evolution:
+//!
+//! - `renamed_from=OldName` - the column was renamed
+//! - `default=value` - the value for rows written without this column
+//!
+//! Added String and SubTable columns don't need a default. They are empty.
+//! Safe widening of data types: Integer to Decimal, anything except SubTable to String.
+//! Integer to Float is not safe, because Float rounds integers bigger than 2^53.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use rust_decimal::prelude::*;
+
+/// One difference between the old and the new schema.
+#[derive(Clone, Debug)]
+pub enum SchemaChange {
+    Added { name: String, data_type: DataType },
+    Removed { name: String, data_type: DataType },
+    Renamed { from: String, to: String },
+    Retyped { name: String, from: DataType, to: DataType },
+    SubTableChanged { name: String, comparison: Box<SchemaComparison> },
+}
+
+/// Backward compatible: the new schema can read rows written with the old schema (after migration).
+/// Forward compatible: the old schema can read rows written with the new schema (after migration).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Compatibility {
+    Full,
+    Backward,
+    Forward,
+    Breaking,
+}
+
+/// Result of TableSchema::compare().
+#[derive(Clone, Debug)]
+pub struct SchemaComparison {
+    pub changes: Vec<SchemaChange>,
+    pub compatibility: Compatibility,
+}
+
+impl SchemaComparison {
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// the value can be converted from one data type to the other without loss
+pub fn is_safe_widening(from: &DataType, to: &DataType) -> bool {
+    match (from, to) {
+        (a, b) if a == b => true,
+        (DataType::Integer, DataType::Decimal) => true,
+        (DataType::SubTable, _) => false,
+        (_, DataType::String) => true,
+        _ => false,
+    }
+}
+
+/// convert the value to a wider data type
+pub fn widen_value(value: Value, to: &DataType) -> Result<Value, Qvs20Error> {
+    match (value, to) {
+        // String is not nullable
+        (Value::Null, DataType::String) => Ok(Value::String(s!())),
+        (Value::Null, _) => Ok(Value::Null),
+        (Value::Integer(i), DataType::Decimal) => Ok(Value::Decimal(Decimal::from(i))),
+        (Value::SubTable(t), DataType::SubTable) => Ok(Value::SubTable(t)),
+        (Value::SubTable(_), _) => Err(Qvs20Error::Error {
+            msg: format!("SubTable cannot be converted to {}.", to),
+        }),
+        (v, DataType::String) => Ok(Value::String(value_to_string(&v))),
+        (v, _) => Ok(v),
+    }
+}
+
+/// where the value for the new column comes from
+enum ColumnSource {
+    Old(usize),
+    Default(Value),
+}
+
+/// column in the new schema has a default or can be empty
+fn has_default(schema: &TableSchema, column: usize) -> bool {
+    property_value(&schema.additional_properties[column], "default").is_some()
+        || schema.data_types[column] == DataType::String
+        || schema.data_types[column] == DataType::SubTable
+}
+
+/// name of the column in the other schema: the same name or renamed
+fn find_old_column(old: &TableSchema, new: &TableSchema, new_column: usize) -> Option<usize> {
+    match old.column_index(&new.column_names[new_column]) {
+        Some(i) => Some(i),
+        None => match property_value(&new.additional_properties[new_column], "renamed_from") {
+            Some(from) => old.column_index(from.trim()),
+            None => None,
+        },
+    }
+}
+
+impl TableSchema {
+    /// compare the old and the new version of the schema
+    pub fn compare(old: &TableSchema, new: &TableSchema) -> SchemaComparison {
+        let mut changes = vec![];
+        let mut backward = true;
+        let mut forward = true;
+        let mut matched_old = vec![false; old.column_names.len()];
+        for new_column in 0..new.column_names.len() {
+            let name = &new.column_names[new_column];
+            let new_type = &new.data_types[new_column];
+            let old_column = match find_old_column(old, new, new_column) {
+                Some(i) => i,
+                None => {
+                    changes.push(SchemaChange::Added {
+                        name: name.clone(),
+                        data_type: new_type.clone(),
+                    });
+                    // old rows don't have this column
+                    if !has_default(new, new_column) {
+                        backward = false;
+                    }
+                    continue;
+                }
+            };
+            matched_old[old_column] = true;
+            let old_name = &old.column_names[old_column];
+            let old_type = &old.data_types[old_column];
+            if old_name != name {
+                changes.push(SchemaChange::Renamed {
+                    from: old_name.clone(),
+                    to: name.clone(),
+                });
+            }
+            if old_type != new_type {
+                changes.push(SchemaChange::Retyped {
+                    name: name.clone(),
+                    from: old_type.clone(),
+                    to: new_type.clone(),
+                });
+                if !is_safe_widening(old_type, new_type) {
+                    backward = false;
+                }
+                if !is_safe_widening(new_type, old_type) {
+                    forward = false;
+                }
+            } else if let (Some(old_sub), Some(new_sub)) = (&old.sub_table_schemas[old_column], &new.sub_table_schemas[new_column]) {
+                let comparison = TableSchema::compare(old_sub, new_sub);
+                if !comparison.is_unchanged() {
+                    match comparison.compatibility {
+                        Compatibility::Full => (),
+                        Compatibility::Backward => forward = false,
+                        Compatibility::Forward => backward = false,
+                        Compatibility::Breaking => {
+                            backward = false;
+                            forward = false;
+                        }
+                    }
+                    changes.push(SchemaChange::SubTableChanged {
+                        name: name.clone(),
+                        comparison: Box::new(comparison),
+                    });
+                }
+            }
+        }
+        for (old_column, matched) in matched_old.iter().enumerate() {
+            if !matched {
+                changes.push(SchemaChange::Removed {
+                    name: old.column_names[old_column].clone(),
+                    data_type: old.data_types[old_column].clone(),
+                });
+                // new rows don't have this column
+                if !has_default(old, old_column) {
+                    forward = false;
+                }
+            }
+        }
+        let compatibility = match (backward, forward) {
+            (true, true) => Compatibility::Full,
+            (true, false) => Compatibility::Backward,
+            (false, true) => Compatibility::Forward,
+            (false, false) => Compatibility::Breaking,
+        };
+        //return
+        SchemaComparison { changes, compatibility }
+    }
+}
+
+impl TableRows {
+    /// convert rows written with the old schema to the new schema
+    pub fn migrate(&self, old: &TableSchema, new: &TableSchema) -> Result<TableRows, Qvs20Error> {
+        // for every new column: the old column or the default value
+        let mut sources: Vec<ColumnSource> = vec![];
+        for new_column in 0..new.column_names.len() {
+            let new_type = &new.data_types[new_column];
+            match find_old_column(old, new, new_column) {
+                Some(old_column) => {
+                    if !is_safe_widening(&old.data_types[old_column], new_type) {
+                        return Err(Qvs20Error::Error {
+                            msg: format!(
+                                "Column {} cannot be migrated from {} to {}.",
+                                new.column_names[new_column], old.data_types[old_column], new_type
+                            ),
+                        });
+                    }
+                    sources.push(ColumnSource::Old(old_column));
+                }
+                None => {
+                    let default = match property_value(&new.additional_properties[new_column], "default") {
+                        Some(text) => match TableRows::value_from_str(text, new_type) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                return Err(Qvs20Error::Error {
+                                    msg: format!("Column {} default {}", new.column_names[new_column], err_trim!(e)),
+                                })
+                            }
+                        },
+                        None => match new_type {
+                            DataType::String => Value::String(s!()),
+                            DataType::SubTable => Value::SubTable(TableRows::default()),
+                            _ => {
+                                return Err(Qvs20Error::Error {
+                                    msg: format!("Added column {} needs a default value.", new.column_names[new_column]),
+                                })
+                            }
+                        },
+                    };
+                    sources.push(ColumnSource::Default(default));
+                }
+            }
+        }
+        let mut table_rows = TableRows::default();
+        table_rows.table_name = new.table_name.clone();
+        table_rows.row_delimiter = new.row_delimiter;
+        for row in self.rows.iter() {
+            let mut values = vec![];
+            for (new_column, source) in sources.iter().enumerate() {
+                let value = match source {
+                    ColumnSource::Old(old_column) => {
+                        let value = row.values[*old_column].clone();
+                        match (value, &old.sub_table_schemas[*old_column], &new.sub_table_schemas[new_column]) {
+                            (Value::SubTable(sub_rows), Some(old_sub), Some(new_sub)) => Value::SubTable(sub_rows.migrate(old_sub, new_sub)?),
+                            (value, _, _) => widen_value(value, &new.data_types[new_column])?,
+                        }
+                    }
+                    ColumnSource::Default(default) => {
+                        let mut default = default.clone();
+                        if let Value::SubTable(sub_rows) = &mut default {
+                            sub_rows.row_delimiter = new.sub_table_schemas[new_column].as_ref().map_or(0, |x| x.row_delimiter);
+                        }
+                        default
+                    }
+                };
+                values.push(value);
+            }
+            table_rows.rows.push(Row { values });
+        }
+        //return
+        Ok(table_rows)
+    }
+
+    /// read rows written with the old schema and migrate them to the new schema
+    pub fn rows_from_qvs20_str_with_migration(
+        input: &str,
+        old: &TableSchema,
+        new: &TableSchema,
+    ) -> Result<TableRows, Qvs20Error> {
+        let table_rows = TableRows::rows_from_qvs20_str(input, old)?;
+        let table_rows = table_rows.migrate(old, new)?;
+        // the new schema can have new constraints
+        table_rows.check_constraints(new)?;
+        //return
+        Ok(table_rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_compare_and_migrate() {
+        let old = unwrap!(TableSchema::schema_from_qvs20_str(
+            "[S][country][v1]\n[String][Integer][String]\n[][][]\n[][][]\n[Name][Population][Capital]\n"
+        ));
+        let new = unwrap!(TableSchema::schema_from_qvs20_str(
+            "[S][country][v2]\n[String][Decimal][Integer][String]\n[][][][]\n[renamed_from=Name][][default=0][]\n[Country][Population][Area][Capital]\n"
+        ));
+        let comparison = TableSchema::compare(&old, &new);
+        assert_eq!(
+            format!("{:?}", comparison.changes),
+            "[Renamed { from: \"Name\", to: \"Country\" }, Retyped { name: \"Population\", from: Integer, to: Decimal }, Added { name: \"Area\", data_type: Integer }]"
+        );
+        // Decimal cannot be safely narrowed to Integer
+        assert_eq!(comparison.compatibility, Compatibility::Backward);
+
+        let rows = unwrap!(TableRows::rows_from_qvs20_str_with_migration(
+            "[R][country]\n[Slovenia][2000000][Ljubljana]\n",
+            &old,
+            &new
+        ));
+        assert_eq!(rows.write_table_rows(), "[R][country]\n[Slovenia][2000000][0][Ljubljana]\n");
+
+        // the other direction
+        let comparison = TableSchema::compare(&new, &old);
+        assert_eq!(comparison.compatibility, Compatibility::Forward);
+
+        let newer = unwrap!(TableSchema::schema_from_qvs20_str(
+            "[S][country][v3]\n[String][Date]\n[][]\n[][]\n[Name][Founded]\n"
+        ));
+        assert_eq!(TableSchema::compare(&old, &newer).compatibility, Compatibility::Breaking);
+        let err = TableRows::default().migrate(&old, &newer).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Added column Founded needs a default value.");
+
+        // Integer to Float is lossy, an empty Integer becomes an empty String
+        assert!(!is_safe_widening(&DataType::Integer, &DataType::Float));
+        let as_text = unwrap!(TableSchema::schema_from_qvs20_str(
+            "[S][country][v4]\n[String][String][String]\n[][][]\n[][][]\n[Name][Population][Capital]\n"
+        ));
+        let rows = unwrap!(TableRows::rows_from_qvs20_str_with_migration("[R][country]\n[Slovenia][][Ljubljana]\n", &old, &as_text));
+        assert_eq!(rows.rows[0].values[1], Value::String(s!()));
+    }
+}
@@ -0,0 +1,146 @@
+// qvs20_schema_registry_mod
+
+//! The rows file `[R][table_name]` does not contain the schema.
+//! The SchemaRegistry holds the schemas from `[S]` files and finds the schema
+//! for a rows file by the table name in its 1st row.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Schemas by table name.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, TableSchema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> SchemaRegistry {
+        SchemaRegistry::default()
+    }
+
+    /// load all `[S]` files with the extension qvs20 or qvs21 from the directory.
+    /// Other files are ignored.
+    pub fn from_dir(path: &Path) -> Result<SchemaRegistry, Qvs20Error> {
+        let mut registry = SchemaRegistry::new();
+        let entries = match fs::read_dir(path) {
+            Ok(e) => e,
+            Err(e) => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Cannot read directory {}: {}", path.display(), e),
+                })
+            }
+        };
+        // sorted for the same order of errors on every system
+        let mut file_paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        file_paths.sort();
+        for file_path in file_paths.iter() {
+            let extension = file_path.extension().and_then(|x| x.to_str()).unwrap_or("").to_lowercase();
+            if extension != "qvs20" && extension != "qvs21" {
+                continue;
+            }
+            let text = match fs::read_to_string(file_path) {
+                Ok(t) => t,
+                Err(e) => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Cannot read file {}: {}", file_path.display(), e),
+                    })
+                }
+            };
+            if !text.starts_with("[S]") {
+                continue;
+            }
+            if let Err(e) = registry.add_schema_from_qvs20_str(&text) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("{} {}", file_path.display(), err_trim!(e)),
+                });
+            }
+        }
+        //return
+        Ok(registry)
+    }
+
+    /// add the schema. The table name must be unique.
+    pub fn add_schema(&mut self, schema: TableSchema) -> Result<(), Qvs20Error> {
+        if self.schemas.contains_key(&schema.table_name) {
+            return Err(Qvs20Error::Error {
+                msg: format!("SchemaRegistry already has table {}.", schema.table_name),
+            });
+        }
+        self.schemas.insert(schema.table_name.clone(), schema);
+        //return
+        Ok(())
+    }
+
+    /// add the schema from a `[S]` string
+    pub fn add_schema_from_qvs20_str(&mut self, input: &str) -> Result<(), Qvs20Error> {
+        let schema = TableSchema::schema_from_qvs20_str(input)?;
+        self.add_schema(schema)
+    }
+
+    pub fn get(&self, table_name: &str) -> Option<&TableSchema> {
+        self.schemas.get(table_name)
+    }
+
+    /// names of all tables in the registry
+    pub fn table_names(&self) -> Vec<&str> {
+        self.schemas.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// schema for the rows file, found by the table name in the 1st row
+    pub fn schema_for_rows(&self, input: &str) -> Result<&TableSchema, Qvs20Error> {
+        let table_name = TableRows::table_name_from_qvs20_str(input)?;
+        match self.get(&table_name) {
+            Some(s) => Ok(s),
+            None => Err(Qvs20Error::Error {
+                msg: format!(
+                    "SchemaRegistry has no schema for table {}. Known tables: {}",
+                    table_name,
+                    self.table_names().join(", ")
+                ),
+            }),
+        }
+    }
+
+    /// read the rows file `[R]` with the schema from the registry
+    pub fn table_from_rows_str(&self, input: &str) -> Result<Table, Qvs20Error> {
+        let schema = self.schema_for_rows(input)?;
+        let table_rows = TableRows::rows_from_qvs20_str(input, schema)?;
+        //return
+        Ok(Table {
+            schema: schema.clone(),
+            table_rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_registry_from_dir() {
+        let registry = unwrap!(SchemaRegistry::from_dir(Path::new("sample_data/registry")));
+        assert_eq!(registry.table_names(), vec!["city", "country"]);
+
+        let table = unwrap!(registry.table_from_rows_str("[R][country]\n[Slovenia][2000000]\n[Italia][60000000]\n"));
+        assert_eq!(table.schema.table_description, "countries of the world");
+        assert_eq!(table.table_rows.rows.len(), 2);
+
+        let err = registry.table_from_rows_str("[R][town]\n[Koper][30000]\n").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: SchemaRegistry has no schema for table town. Known tables: city, country");
+
+        // constraints from the schema are checked
+        let err = registry.table_from_rows_str("[R][country]\n[Slovenia][-1]\n").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Constraint violation row 0 col 1 Population: value -1 is less than min 0");
+
+        let err = TableRows::rows_from_qvs20_str("[R][city]\n[Koper][Slovenia][30000]\n", unwrap!(registry.get("country"))).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: TableRows table name city differs from TableSchema table name country.");
+    }
+}
@@ -0,0 +1,158 @@
+// qvs20_sniff_mod
+
+//! The 1st row of a file tells what is inside: `[T][table name][description]` for schema and rows,
+//! `[S][table name][description]` for only the schema and `[R][table name]` for only the rows.
+//! `[U]` is only for the schema of a sub table inside the 3rd schema row and never starts a file.
+//!
+//! The sniff reads only the 1st row. The open reads the whole file accordingly.
+//! The schema for a rows file comes from the SchemaRegistry.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_schema_registry_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use std::fs;
+use std::path::Path;
+
+/// what is in the file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileType {
+    /// `[T]` schema and rows
+    Table,
+    /// `[S]` only the schema
+    Schema,
+    /// `[R]` only the rows
+    Rows,
+}
+
+/// the 1st row of the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileInfo {
+    pub file_type: FileType,
+    pub table_name: String,
+    /// empty for rows files
+    pub description: String,
+}
+
+/// content of the file by file type
+#[derive(Clone, Debug)]
+pub enum QvsFile {
+    Table(Table),
+    Schema(TableSchema),
+    /// the rows with the schema from the registry
+    Rows(Table),
+}
+
+/// read only the 1st row of the file. The bytes can be only the beginning of the file.
+pub fn sniff(bytes: &[u8]) -> Result<FileInfo, Qvs20Error> {
+    // the 1st row cannot have a sub table, so the first LF is the end of the row
+    let mut first_row = match bytes.iter().position(|b| *b == b'\n') {
+        Some(pos) => bytes[..pos].to_vec(),
+        None => bytes.to_vec(),
+    };
+    first_row.push(b'\n');
+    let mut rdr = ReaderForQvs20::new(&first_row);
+    let fields = rdr.next_row_as_vec_of_string()?;
+    let file_type = match fields.first().map(|f| f.as_str()) {
+        Some("T") => FileType::Table,
+        Some("S") => FileType::Schema,
+        Some("R") => FileType::Rows,
+        Some("U") => {
+            return Err(Qvs20Error::Error {
+                msg: s!("File type U is only for sub table schemas inside a schema. A file must start with T, S or R."),
+            })
+        }
+        Some(other) => {
+            return Err(Qvs20Error::Error {
+                msg: format!("Unknown file type {}. A file must start with T, S or R.", other),
+            })
+        }
+        None => {
+            return Err(Qvs20Error::Error {
+                msg: s!("The 1st row has no file type."),
+            })
+        }
+    };
+    let table_name = match fields.get(1) {
+        Some(name) => name.clone(),
+        None => {
+            return Err(Qvs20Error::Error {
+                msg: s!("The 1st row has no table name."),
+            })
+        }
+    };
+    //return
+    Ok(FileInfo {
+        file_type,
+        table_name,
+        description: fields.get(2).cloned().unwrap_or_default(),
+    })
+}
+
+/// read the file by the file type in the 1st row
+pub fn open(path: &Path, registry: &SchemaRegistry) -> Result<QvsFile, Qvs20Error> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(Qvs20Error::Error {
+                msg: format!("Cannot read file {}: {}", path.display(), e),
+            })
+        }
+    };
+    let file_info = sniff(text.as_bytes()).map_err(|e| Qvs20Error::Error {
+        msg: format!("{} {}", path.display(), err_trim!(e)),
+    })?;
+    //return
+    match file_info.file_type {
+        FileType::Table => Ok(QvsFile::Table(Table::from_qvs20_str_with_schema(&text)?)),
+        FileType::Schema => Ok(QvsFile::Schema(TableSchema::schema_from_qvs20_str(&text)?)),
+        FileType::Rows => Ok(QvsFile::Rows(registry.table_from_rows_str(&text)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_sniff_and_open() {
+        assert_eq!(
+            unwrap!(sniff(b"[S][city][big \\[cities\\]]\n[String]")),
+            FileInfo {
+                file_type: FileType::Schema,
+                table_name: s!("city"),
+                description: s!("big [cities]"),
+            }
+        );
+        // only the beginning of a file
+        assert_eq!(unwrap!(sniff(b"[R][city]")).file_type, FileType::Rows);
+        assert_eq!(
+            remove_src_loc(sniff(b"[U][cities][]\n").unwrap_err()),
+            "Error: File type U is only for sub table schemas inside a schema. A file must start with T, S or R."
+        );
+
+        let registry = unwrap!(SchemaRegistry::from_dir(Path::new("sample_data/registry")));
+        // a directory for every test process, the runs don't collide
+        let dir = std::env::temp_dir().join(format!("qvs21_t01_sniff_and_open_{}", std::process::id()));
+        unwrap!(fs::create_dir_all(&dir));
+        let path = dir.join("table.qvs21");
+        unwrap!(fs::write(&path, "[T][country][]\n[String][Integer]\n[][]\n[][]\n[Country][Population]\n[Slovenia][2000000]\n"));
+        match unwrap!(open(&path, &registry)) {
+            QvsFile::Table(table) => assert_eq!(table.schema.table_name, "country"),
+            other => panic!("expected Table, got {:?}", other),
+        }
+        match unwrap!(open(Path::new("sample_data/registry/city.qvs20"), &registry)) {
+            QvsFile::Schema(schema) => assert_eq!(schema.column_names.len(), 3),
+            other => panic!("expected Schema, got {:?}", other),
+        }
+        let path = dir.join("rows.qvs21");
+        unwrap!(fs::write(&path, "[R][city]\n[Ljubljana][Slovenia][300000]\n"));
+        match unwrap!(open(&path, &registry)) {
+            QvsFile::Rows(table) => assert_eq!(table.table_rows.rows.len(), 1),
+            other => panic!("expected Rows, got {:?}", other),
+        }
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
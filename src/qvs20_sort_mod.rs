@@ -0,0 +1,132 @@
+// qvs20_sort_mod
+
+//! Stable sorting of rows by columns.
+//! The order of values is the `Ord` for Value in qvs20_table_rows_mod.
+//!
+//! Column names with a dot like `Cities.Population` sort the sub tables in the column `Cities`.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl TableRows {
+    /// stable sort by the columns.
+    /// Column names with a dot like `Cities.Population` sort the sub tables in the column Cities.
+    pub fn sort_by_columns(&mut self, schema: &TableSchema, columns: &[(&str, SortOrder)]) -> Result<(), Qvs20Error> {
+        let mut keys: Vec<(usize, SortOrder)> = vec![];
+        let mut sub_table_keys: Vec<(usize, Vec<(&str, SortOrder)>)> = vec![];
+        for (column_name, order) in columns.iter() {
+            if let Some(column) = schema.column_index(column_name) {
+                keys.push((column, *order));
+                continue;
+            }
+            let sub_table_column = column_name
+                .split_once('.')
+                .and_then(|(name, sub_name)| schema.column_index(name).map(|i| (i, sub_name)))
+                .filter(|(i, _)| schema.sub_table_schemas[*i].is_some());
+            match sub_table_column {
+                Some((column, sub_name)) => match sub_table_keys.iter_mut().find(|x| x.0 == column) {
+                    Some(x) => x.1.push((sub_name, *order)),
+                    None => sub_table_keys.push((column, vec![(sub_name, *order)])),
+                },
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Table {} does not have column {}.", schema.table_name, column_name),
+                    })
+                }
+            }
+        }
+        self.rows.sort_by(|a, b| {
+            for (column, order) in keys.iter() {
+                let ordering = a.values[*column].cmp(&b.values[*column]);
+                let ordering = match order {
+                    SortOrder::Asc => ordering,
+                    SortOrder::Desc => ordering.reverse(),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        for (column, sub_columns) in sub_table_keys.iter() {
+            if let Some(sub_schema) = &schema.sub_table_schemas[*column] {
+                for row in self.rows.iter_mut() {
+                    if let Value::SubTable(sub_table_rows) = &mut row.values[*column] {
+                        sub_table_rows.sort_by_columns(sub_schema, sub_columns)?;
+                    }
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+}
+
+impl Table {
+    /// stable sort by the columns: `&[("Country", SortOrder::Asc), ("Cities.Population", SortOrder::Desc)]`
+    pub fn sort_by_columns(&mut self, columns: &[(&str, SortOrder)]) -> Result<(), Qvs20Error> {
+        self.table_rows.sort_by_columns(&self.schema, columns)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_value_order() {
+        let d = |x: &str| Value::Decimal(unwrap!(TableRows::from_u8_to_decimal(x.as_bytes())));
+        assert!(Value::Null < Value::Integer(i64::MIN));
+        assert!(Value::Integer(1) < d("1.5"));
+        assert!(d("1.5") < Value::Float(1.6));
+        assert!(Value::Integer(2) > Value::Float(1.9));
+        // exact, not rounded to f64
+        assert!(Value::Integer(9007199254740993) > Value::Float(9007199254740992.0));
+        assert!(d("0.1") < Value::Float(0.1));
+        // the same value in different data types
+        assert!(Value::Integer(1) < d("1.0"));
+        assert!(d("1.0") < Value::Float(1.0));
+        assert_eq!(d("1.0"), d("1.00"));
+        assert_eq!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+        assert!(Value::Float(f64::NAN) > Value::Float(f64::INFINITY));
+        assert_eq!(Value::Float(-0.0), Value::Float(0.0));
+        assert!(Value::Bool(true) < Value::Integer(0));
+        assert_ne!(Value::String(s!("a")), Value::String(s!("b")));
+    }
+
+    #[test]
+    pub fn t02_sort_by_columns() {
+        let s = r"[T][cities][description]
+[String][Integer][SubTable]
+[][][1[U][streets][sub table]1[String][Integer]1[][]1[][]1[street][length]1]
+[][][]
+[country][population][streets]
+[Slovenia][30000][1[Obala][3]1[Pristan][1]1]
+[Italia][1400000][]
+[Slovenia][300000][1[Slovenska][2]1[Celovska][5]1]
+[Italia][2800000][]
+";
+        let mut table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        unwrap!(table.sort_by_columns(&[
+            ("country", SortOrder::Asc),
+            ("population", SortOrder::Desc),
+            ("streets.street", SortOrder::Asc)
+        ]));
+        assert!(table.write_table().ends_with(
+            "[street][length]1]\n[][][]\n[country][population][streets]\n[Italia][2800000][]\n[Italia][1400000][]\n[Slovenia][300000][1[Celovska][5]1[Slovenska][2]1]\n[Slovenia][30000][1[Obala][3]1[Pristan][1]1]\n"
+        ));
+        let err = table.sort_by_columns(&[("streets.name", SortOrder::Asc)]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Table streets does not have column name.");
+    }
+}
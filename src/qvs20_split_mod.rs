@@ -0,0 +1,120 @@
+// qvs20_split_mod
+
+//! Write the schema and the rows as a matched pair of separate files:
+//! one `[S][table name][description]` schema file and one or many `[R][table name]` rows files.
+//! The rows files always have the table name of the schema, so the SchemaRegistry
+//! finds the schema for every rows file.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+use crate::qvs20_writer_mod::*;
+
+use std::io::Write;
+
+/// writes the schema file once and then any count of rows files for the same schema
+pub struct SplitWriter {
+    schema: TableSchema,
+}
+
+impl SplitWriter {
+    /// write the `[S]` schema file
+    pub fn new<W: Write>(schema: &TableSchema, mut schema_writer: W) -> Result<SplitWriter, Qvs20Error> {
+        schema_writer.write_all(schema.write_schema().as_bytes()).map_err(io_error)?;
+        //return
+        Ok(SplitWriter { schema: schema.clone() })
+    }
+
+    pub fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    /// write a `[R]` rows file with the table name of the schema.
+    /// The rows must have the columns of the schema.
+    /// TableRows with another table name are an error, TableRows without a name get the name of the schema.
+    pub fn write_rows<W: Write>(&self, table_rows: &TableRows, mut rows_writer: W) -> Result<(), Qvs20Error> {
+        if !table_rows.table_name.is_empty() && table_rows.table_name != self.schema.table_name {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "TableRows table name {} differs from TableSchema table name {}.",
+                    table_rows.table_name, self.schema.table_name
+                ),
+            });
+        }
+        let count_of_columns = self.schema.column_names.len();
+        if let Some(row) = table_rows.rows.iter().position(|r| r.values.len() != count_of_columns) {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "Row {} has {} columns, but the schema of {} has {} columns.",
+                    row,
+                    table_rows.rows[row].values.len(),
+                    self.schema.table_name,
+                    count_of_columns
+                ),
+            });
+        }
+        self.write_rows_header(&mut rows_writer)?;
+        let mut wrt = WriterForQvs20::new();
+        table_rows.write_data_rows_to_writer(&mut wrt);
+        rows_writer.write_all(wrt.return_and_finish().as_bytes()).map_err(io_error)?;
+        //return
+        Ok(())
+    }
+
+    /// write only the 1st row `[R][table name]` of a rows file. Returns the count of bytes.
+    /// For writers that add the data rows one by one.
+    pub fn write_rows_header<W: Write>(&self, mut rows_writer: W) -> Result<usize, Qvs20Error> {
+        let mut wrt = WriterForQvs20::new();
+        wrt.write_string("R");
+        wrt.write_string(&self.schema.table_name);
+        wrt.write_delimiter();
+        let header = wrt.return_and_finish();
+        rows_writer.write_all(header.as_bytes()).map_err(io_error)?;
+        //return
+        Ok(header.len())
+    }
+}
+
+impl Table {
+    /// write the schema to the `[S]` schema file and the rows to the `[R]` rows file
+    pub fn write_split<S: Write, R: Write>(&self, schema_writer: S, rows_writer: R) -> Result<(), Qvs20Error> {
+        SplitWriter::new(&self.schema, schema_writer)?.write_rows(&self.table_rows, rows_writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qvs20_schema_registry_mod::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_write_split_and_many_rows_files() {
+        let s = "[T][city][big cities]\n[String][Integer]\n[][]\n[][min=0]\n[City][Population]\n[Ljubljana][300000]\n[Koper][]\n";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(s));
+        let (mut schema_file, mut rows_file) = (vec![], vec![]);
+        unwrap!(table.write_split(&mut schema_file, &mut rows_file));
+        let schema_text = unwrap!(String::from_utf8(schema_file));
+        assert_eq!(schema_text, "[S][city][big cities]\n[String][Integer]\n[][]\n[][min=0]\n[City][Population]\n");
+        let rows_text = unwrap!(String::from_utf8(rows_file));
+        assert_eq!(rows_text, "[R][city]\n[Ljubljana][300000]\n[Koper][]\n");
+
+        // many rows files for one schema
+        let split_writer = unwrap!(SplitWriter::new(&table.schema, vec![]));
+        let mut part = unwrap!(TableRows::new("", b'\n'));
+        part.rows = vec![table.table_rows.rows[1].clone()];
+        let mut rows_file_2 = vec![];
+        unwrap!(split_writer.write_rows(&part, &mut rows_file_2));
+        let rows_text_2 = unwrap!(String::from_utf8(rows_file_2));
+        assert_eq!(rows_text_2, "[R][city]\n[Koper][]\n");
+        part.table_name = s!("town");
+        let err = split_writer.write_rows(&part, vec![]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: TableRows table name town differs from TableSchema table name city.");
+
+        let mut registry = SchemaRegistry::new();
+        unwrap!(registry.add_schema_from_qvs20_str(&schema_text));
+        assert_eq!(unwrap!(registry.table_from_rows_str(&rows_text)).table_rows.rows.len(), 2);
+        assert_eq!(unwrap!(registry.table_from_rows_str(&rows_text_2)).table_rows.rows.len(), 1);
+    }
+}
@@ -0,0 +1,476 @@
+// qvs20_sql_mod
+
+//! SQL scripts for PostgreSQL, MySQL and SQLite: CREATE TABLE from the schema
+//! and INSERT or COPY from the rows.
+//!
+//! SQL has no sub tables. Every SubTable column becomes a child table `{table}_{column}`
+//! with a foreign key to the parent table. The child table has the key columns of the parent
+//! with the prefix of the parent: `countries_country`.
+//! A table without a primary key gets the column `_row` with the 1-based row number
+//! (inside the sub table for child tables).
+//!
+//! | QVS21               | PostgreSQL       | MySQL          | SQLite  |
+//! |---------------------|------------------|----------------|---------|
+//! | String              | TEXT             | TEXT           | TEXT    |
+//! | Integer             | BIGINT           | BIGINT         | INTEGER |
+//! | Decimal             | NUMERIC(p,s)     | DECIMAL(p,s)   | NUMERIC |
+//! | Float               | DOUBLE PRECISION | DOUBLE         | REAL    |
+//! | Bool                | BOOLEAN          | BOOLEAN        | INTEGER |
+//! | DateTimeFixedOffset | TIMESTAMPTZ      | DATETIME(6)    | TEXT    |
+//! | Date                | DATE             | DATE           | TEXT    |
+//! | Time                | TIME             | TIME(6)        | TEXT    |
+//!
+//! String with `max_length` is VARCHAR(n). MySQL cannot index TEXT, so String key columns are VARCHAR(255).
+//! Decimal precision and scale come from the constraints `precision` and `scale`.
+//! MySQL DATETIME has no offset: the values are converted to UTC.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// name of the row number column for tables without a primary key
+const ROW_COLUMN: &str = "_row";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SqlDialect {
+    PostgreSql,
+    MySql,
+    Sqlite,
+}
+
+/// key column inherited from the parent table
+#[derive(Clone)]
+struct KeyColumn {
+    name: String,
+    data_type: DataType,
+    additional_property: String,
+    /// the column name in the parent table
+    referenced: String,
+}
+
+/// one SQL table for the schema or a sub table schema
+struct SqlTable<'a> {
+    name: String,
+    schema: &'a TableSchema,
+    parent_key: Vec<KeyColumn>,
+    parent_name: Option<String>,
+    /// `_row` column when the schema has no primary key
+    row_column: bool,
+    /// values for INSERT: parent key, `_row`, columns that are not SubTable
+    rows: Vec<Vec<Value>>,
+}
+
+fn quote_name(name: &str, dialect: SqlDialect) -> String {
+    match dialect {
+        SqlDialect::MySql => format!("`{}`", name.replace('`', "``")),
+        _ => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+fn sql_type(data_type: &DataType, property: &str, is_key: bool, dialect: SqlDialect) -> Result<String, Qvs20Error> {
+    let constraints = ColumnConstraints::parse(property, data_type)?.unwrap_or_default();
+    let sql_type = match (data_type, dialect) {
+        (DataType::String, SqlDialect::Sqlite) => s!("TEXT"),
+        (DataType::String, _) => match constraints.max_length {
+            Some(n) => format!("VARCHAR({})", n),
+            None if is_key && dialect == SqlDialect::MySql => s!("VARCHAR(255)"),
+            None => s!("TEXT"),
+        },
+        (DataType::Integer, SqlDialect::Sqlite) => s!("INTEGER"),
+        (DataType::Integer, _) => s!("BIGINT"),
+        (DataType::Decimal, SqlDialect::Sqlite) => s!("NUMERIC"),
+        (DataType::Decimal, _) => {
+            let name = if dialect == SqlDialect::MySql { "DECIMAL" } else { "NUMERIC" };
+            match (constraints.precision, constraints.scale) {
+                (Some(p), Some(s)) => format!("{}({},{})", name, p, s),
+                (Some(p), None) => format!("{}({})", name, p),
+                // MySQL default is DECIMAL(10,0)
+                _ if dialect == SqlDialect::MySql => s!("DECIMAL(65,30)"),
+                _ => s!("NUMERIC"),
+            }
+        }
+        (DataType::Float, SqlDialect::PostgreSql) => s!("DOUBLE PRECISION"),
+        (DataType::Float, SqlDialect::MySql) => s!("DOUBLE"),
+        (DataType::Float, SqlDialect::Sqlite) => s!("REAL"),
+        (DataType::Bool, SqlDialect::Sqlite) => s!("INTEGER"),
+        (DataType::Bool, _) => s!("BOOLEAN"),
+        (DataType::DateTimeFixedOffset, SqlDialect::PostgreSql) => s!("TIMESTAMPTZ"),
+        (DataType::DateTimeFixedOffset, SqlDialect::MySql) => s!("DATETIME(6)"),
+        (DataType::Date, SqlDialect::Sqlite) | (DataType::Time, SqlDialect::Sqlite) | (DataType::DateTimeFixedOffset, SqlDialect::Sqlite) => {
+            s!("TEXT")
+        }
+        (DataType::Date, _) => s!("DATE"),
+        (DataType::Time, SqlDialect::PostgreSql) => s!("TIME"),
+        (DataType::Time, SqlDialect::MySql) => s!("TIME(6)"),
+        (DataType::SubTable, _) => {
+            return Err(Qvs20Error::Error {
+                msg: s!("SubTable has no SQL type."),
+            })
+        }
+    };
+    //return
+    Ok(sql_type)
+}
+
+fn sql_literal(value: &Value, dialect: SqlDialect) -> Result<String, Qvs20Error> {
+    let literal = match value {
+        Value::String(s) => {
+            let s = s.replace('\'', "''");
+            // MySQL uses backslash as escape character in strings
+            let s = if dialect == SqlDialect::MySql { s.replace('\\', "\\\\") } else { s };
+            format!("'{}'", s)
+        }
+        Value::Integer(i) => i.to_string(),
+        Value::Decimal(d) => d.to_string(),
+        Value::Float(f) if f.is_finite() => format!("{:?}", f),
+        Value::Float(f) => {
+            if dialect != SqlDialect::PostgreSql {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Float {} cannot be written to {:?}.", f, dialect),
+                });
+            }
+            let text = if f.is_nan() {
+                "NaN"
+            } else if *f > 0.0 {
+                "Infinity"
+            } else {
+                "-Infinity"
+            };
+            format!("'{}'", text)
+        }
+        Value::Bool(b) => match (dialect, b) {
+            (SqlDialect::Sqlite, true) => s!("1"),
+            (SqlDialect::Sqlite, false) => s!("0"),
+            (_, true) => s!("TRUE"),
+            (_, false) => s!("FALSE"),
+        },
+        Value::DateTimeFixedOffset(d) if dialect == SqlDialect::MySql => format!("'{}'", d.naive_utc().format("%Y-%m-%d %H:%M:%S%.6f")),
+        Value::DateTimeFixedOffset(d) => format!("'{}'", d.to_rfc3339()),
+        Value::Date(d) => format!("'{}'", d),
+        Value::Time(t) => format!("'{}'", t),
+        Value::Null => s!("NULL"),
+        Value::SubTable(_) => {
+            return Err(Qvs20Error::Error {
+                msg: s!("SubTable cannot be a SQL literal."),
+            })
+        }
+    };
+    //return
+    Ok(literal)
+}
+
+/// text for PostgreSQL COPY: backslash escapes and \N for Null
+fn copy_text(value: &Value) -> Result<String, Qvs20Error> {
+    let text = match value {
+        Value::Null => s!("\\N"),
+        Value::Bool(b) => s!(if *b { "t" } else { "f" }),
+        Value::Float(f) if f.is_nan() => s!("NaN"),
+        Value::Float(f) if f.is_infinite() => s!(if *f > 0.0 { "Infinity" } else { "-Infinity" }),
+        Value::Float(f) => format!("{:?}", f),
+        Value::DateTimeFixedOffset(d) => d.to_rfc3339(),
+        Value::SubTable(_) => {
+            return Err(Qvs20Error::Error {
+                msg: s!("SubTable cannot be a COPY value."),
+            })
+        }
+        value => value_to_string(value),
+    };
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' if !matches!(value, Value::Null) => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    //return
+    Ok(escaped)
+}
+
+/// the table and the child tables, parent before the children.
+/// The groups are the key values of the parent row and the rows of its sub table.
+fn plan_tables<'a>(
+    name: &str,
+    prefix: &str,
+    schema: &'a TableSchema,
+    parent_name: Option<String>,
+    parent_key: Vec<KeyColumn>,
+    groups: Vec<(Vec<Value>, Vec<&'a Row>)>,
+    tables: &mut Vec<SqlTable<'a>>,
+) -> Result<(), Qvs20Error> {
+    let primary_key = schema.primary_key_columns()?;
+    let row_column = primary_key.is_empty();
+    // the key for the child tables: inherited columns keep the name, own columns get the prefix
+    let mut child_key: Vec<KeyColumn> = parent_key
+        .iter()
+        .map(|k| KeyColumn {
+            referenced: k.name.clone(),
+            ..k.clone()
+        })
+        .collect();
+    if row_column {
+        child_key.push(KeyColumn {
+            name: format!("{}{}", prefix, ROW_COLUMN),
+            data_type: DataType::Integer,
+            additional_property: s!(),
+            referenced: s!(ROW_COLUMN),
+        });
+    }
+    for column in primary_key.iter() {
+        let column_name = &schema.column_names[*column];
+        child_key.push(KeyColumn {
+            name: format!("{}_{}", prefix, column_name),
+            data_type: schema.data_types[*column].clone(),
+            additional_property: remove_property_key(&schema.additional_properties[*column], "primary_key"),
+            referenced: column_name.clone(),
+        });
+    }
+    let mut rows = vec![];
+    // for every SubTable column the groups of the child table
+    let mut child_groups: Vec<Vec<(Vec<Value>, Vec<&'a Row>)>> = vec![vec![]; schema.column_names.len()];
+    for (key_values, group_rows) in groups.iter() {
+        for (row_index, row) in group_rows.iter().enumerate() {
+            let mut values = key_values.clone();
+            if row_column {
+                values.push(Value::Integer(row_index as i64 + 1));
+            }
+            let mut row_key = values.clone();
+            row_key.extend(primary_key.iter().map(|c| row.values[*c].clone()));
+            for (column, value) in row.values.iter().enumerate() {
+                match value {
+                    Value::SubTable(sub_table_rows) => child_groups[column].push((row_key.clone(), sub_table_rows.rows.iter().collect())),
+                    _ if schema.data_types[column] == DataType::SubTable => child_groups[column].push((row_key.clone(), vec![])),
+                    value => values.push(value.clone()),
+                }
+            }
+            rows.push(values);
+        }
+    }
+    tables.push(SqlTable {
+        name: s!(name),
+        schema,
+        parent_key,
+        parent_name,
+        row_column,
+        rows,
+    });
+    for (column, groups) in child_groups.into_iter().enumerate() {
+        if let Some(sub_schema) = &schema.sub_table_schemas[column] {
+            let column_name = &schema.column_names[column];
+            plan_tables(
+                &format!("{}_{}", name, column_name),
+                column_name,
+                sub_schema,
+                Some(s!(name)),
+                child_key.clone(),
+                groups,
+                tables,
+            )?;
+        }
+    }
+    //return
+    Ok(())
+}
+
+fn plan_table(table: &Table) -> Result<Vec<SqlTable<'_>>, Qvs20Error> {
+    let mut tables = vec![];
+    let rows = table.table_rows.rows.iter().collect();
+    let name = &table.schema.table_name;
+    plan_tables(name, name, &table.schema, None, vec![], vec![(vec![], rows)], &mut tables)?;
+    //return
+    Ok(tables)
+}
+
+impl<'a> SqlTable<'a> {
+    /// quoted names of the columns in the order of the row values
+    fn column_names(&self, dialect: SqlDialect) -> Vec<String> {
+        let mut names: Vec<String> = self.parent_key.iter().map(|k| quote_name(&k.name, dialect)).collect();
+        if self.row_column {
+            names.push(quote_name(ROW_COLUMN, dialect));
+        }
+        for (column, column_name) in self.schema.column_names.iter().enumerate() {
+            if self.schema.data_types[column] != DataType::SubTable {
+                names.push(quote_name(column_name, dialect));
+            }
+        }
+        names
+    }
+
+    fn create_table(&self, dialect: SqlDialect) -> Result<String, Qvs20Error> {
+        let schema = self.schema;
+        let primary_key = schema.primary_key_columns()?;
+        let constraints = schema.column_constraints()?;
+        let parent_columns: Vec<String> = self.parent_key.iter().map(|k| quote_name(&k.name, dialect)).collect();
+        let mut lines = vec![];
+        for k in self.parent_key.iter() {
+            let sql_type = sql_type(&k.data_type, &k.additional_property, true, dialect)?;
+            lines.push(format!("{} {} NOT NULL", quote_name(&k.name, dialect), sql_type));
+        }
+        let mut key_columns = parent_columns.clone();
+        if self.row_column {
+            let sql_type = sql_type(&DataType::Integer, "", true, dialect)?;
+            lines.push(format!("{} {} NOT NULL", quote_name(ROW_COLUMN, dialect), sql_type));
+            key_columns.push(quote_name(ROW_COLUMN, dialect));
+        }
+        let mut unique_columns = vec![];
+        for (column, column_name) in schema.column_names.iter().enumerate() {
+            let data_type = &schema.data_types[column];
+            if *data_type == DataType::SubTable {
+                continue;
+            }
+            let is_unique = matches!(&constraints[column], Some(c) if c.unique);
+            let is_key = primary_key.contains(&column) || is_unique;
+            let not_null = if is_nullable(data_type) && !primary_key.contains(&column) {
+                ""
+            } else {
+                " NOT NULL"
+            };
+            let sql_type = sql_type(data_type, &schema.additional_properties[column], is_key, dialect)?;
+            lines.push(format!("{} {}{}", quote_name(column_name, dialect), sql_type, not_null));
+            if is_unique {
+                unique_columns.push(quote_name(column_name, dialect));
+            }
+        }
+        key_columns.extend(primary_key.iter().map(|c| quote_name(&schema.column_names[*c], dialect)));
+        lines.push(format!("PRIMARY KEY ({})", key_columns.join(", ")));
+        // unique in the sub table of one parent row
+        for unique_column in unique_columns {
+            let mut columns = parent_columns.clone();
+            columns.push(unique_column);
+            lines.push(format!("UNIQUE ({})", columns.join(", ")));
+        }
+        if let Some(parent_name) = &self.parent_name {
+            let referenced: Vec<String> = self.parent_key.iter().map(|k| quote_name(&k.referenced, dialect)).collect();
+            lines.push(format!(
+                "FOREIGN KEY ({}) REFERENCES {} ({})",
+                parent_columns.join(", "),
+                quote_name(parent_name, dialect),
+                referenced.join(", ")
+            ));
+        }
+        //return
+        Ok(format!("CREATE TABLE {} (\n    {}\n);\n", quote_name(&self.name, dialect), lines.join(",\n    ")))
+    }
+}
+
+impl TableSchema {
+    /// CREATE TABLE statements for the table and the child tables for SubTable columns
+    pub fn to_sql_ddl(&self, dialect: SqlDialect) -> Result<String, Qvs20Error> {
+        let table = Table {
+            schema: self.clone(),
+            table_rows: TableRows::default(),
+        };
+        let mut ddl = vec![];
+        for sql_table in plan_table(&table)?.iter() {
+            ddl.push(sql_table.create_table(dialect)?);
+        }
+        //return
+        Ok(ddl.join("\n"))
+    }
+}
+
+impl Table {
+    /// INSERT statements with max batch_rows rows each, parent tables before child tables
+    pub fn to_sql_inserts(&self, dialect: SqlDialect, batch_rows: usize) -> Result<String, Qvs20Error> {
+        let mut script = s!();
+        for sql_table in plan_table(self)?.iter() {
+            let insert = format!(
+                "INSERT INTO {} ({}) VALUES\n",
+                quote_name(&sql_table.name, dialect),
+                sql_table.column_names(dialect).join(", ")
+            );
+            for batch in sql_table.rows.chunks(batch_rows.max(1)) {
+                let mut values = vec![];
+                for row in batch.iter() {
+                    let literals: Result<Vec<String>, Qvs20Error> = row.iter().map(|v| sql_literal(v, dialect)).collect();
+                    values.push(format!("({})", literals?.join(", ")));
+                }
+                script.push_str(&insert);
+                script.push_str(&values.join(",\n"));
+                script.push_str(";\n");
+            }
+        }
+        //return
+        Ok(script)
+    }
+
+    /// PostgreSQL COPY FROM STDIN script, parent tables before child tables
+    pub fn to_sql_copy(&self) -> Result<String, Qvs20Error> {
+        let dialect = SqlDialect::PostgreSql;
+        let mut script = s!();
+        for sql_table in plan_table(self)?.iter() {
+            script.push_str(&format!(
+                "COPY {} ({}) FROM STDIN;\n",
+                quote_name(&sql_table.name, dialect),
+                sql_table.column_names(dialect).join(", ")
+            ));
+            for row in sql_table.rows.iter() {
+                let texts: Result<Vec<String>, Qvs20Error> = row.iter().map(copy_text).collect();
+                script.push_str(&texts?.join("\t"));
+                script.push('\n');
+            }
+            script.push_str("\\.\n");
+        }
+        //return
+        Ok(script)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_ddl_inserts_copy() {
+        let s = r"[T][countries][description]
+[String][Decimal][Bool][SubTable]
+[][][][1[U][cities][sub table]1[String][Integer]1[][]1[unique=T][min=0]1[city][population]1]
+[primary_key=1;max_length=40][precision=10;scale=2][][]
+[country][area][eu][cities]
+[Slovenia][20271.5][T][1[Ljubljana][300000]1[Ko'per\\a][]1]
+[Switzerland][41285][][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        assert_eq!(
+            unwrap!(table.schema.to_sql_ddl(SqlDialect::PostgreSql)),
+            r#"CREATE TABLE "countries" (
+    "country" VARCHAR(40) NOT NULL,
+    "area" NUMERIC(10,2),
+    "eu" BOOLEAN,
+    PRIMARY KEY ("country")
+);
+
+CREATE TABLE "countries_cities" (
+    "countries_country" VARCHAR(40) NOT NULL,
+    "_row" BIGINT NOT NULL,
+    "city" TEXT NOT NULL,
+    "population" BIGINT,
+    PRIMARY KEY ("countries_country", "_row"),
+    UNIQUE ("countries_country", "city"),
+    FOREIGN KEY ("countries_country") REFERENCES "countries" ("country")
+);
+"#
+        );
+        assert_eq!(
+            unwrap!(table.to_sql_inserts(SqlDialect::MySql, 1)),
+            r"INSERT INTO `countries` (`country`, `area`, `eu`) VALUES
+('Slovenia', 20271.5, TRUE);
+INSERT INTO `countries` (`country`, `area`, `eu`) VALUES
+('Switzerland', 41285, NULL);
+INSERT INTO `countries_cities` (`countries_country`, `_row`, `city`, `population`) VALUES
+('Slovenia', 1, 'Ljubljana', 300000);
+INSERT INTO `countries_cities` (`countries_country`, `_row`, `city`, `population`) VALUES
+('Slovenia', 2, 'Ko''per\\a', NULL);
+"
+        );
+        assert_eq!(
+            unwrap!(table.to_sql_copy()),
+            "COPY \"countries\" (\"country\", \"area\", \"eu\") FROM STDIN;\nSlovenia\t20271.5\tt\nSwitzerland\t41285\t\\N\n\\.\nCOPY \"countries_cities\" (\"countries_country\", \"_row\", \"city\", \"population\") FROM STDIN;\nSlovenia\t1\tLjubljana\t300000\nSlovenia\t2\tKo'per\\\\a\t\\N\n\\.\n"
+        );
+    }
+}
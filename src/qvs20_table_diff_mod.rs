@@ -0,0 +1,586 @@
+// qvs20_table_diff_mod
+
+//! Compare two snapshots of a table by key columns and apply the changes.
+//! The diff has inserted, deleted and updated rows. Updated rows have the changed cells.
+//! Sub tables are compared row by row with the primary key of the sub table schema
+//! or with all non SubTable columns, if there is no primary key.
+//! Without primary key the same key can be in more rows: these rows are matched by position
+//! and if they differ, all the rows with this key are deleted and inserted again.
+//!
+//! The patch table is the diff written as a QVS21 table with 2 more columns in front:
+//! `op` is I, D or U and `changed` has the names of the changed columns separated by `|`.
+//! The key columns are in the additional property of the `op` column: `key=Country|City`.
+//! Because of this, the column names of a patch table cannot contain `|` or `;`.
+//! Inserted rows are appended at the end of the table.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use std::collections::HashMap;
+
+/// count of the op and changed columns in front of the patch table
+const PATCH_COLUMNS: usize = 2;
+
+/// Change of one cell in an updated row.
+#[derive(Clone, Debug)]
+pub enum CellChange {
+    /// old is Null when the diff is read from a patch table
+    Value { column: usize, old: Value, new: Value },
+    SubTable { column: usize, diff: TableDiff },
+}
+
+#[derive(Clone, Debug)]
+pub struct RowUpdate {
+    /// values of the key columns
+    pub key: Vec<Value>,
+    pub changes: Vec<CellChange>,
+}
+
+/// Result of Table::diff().
+#[derive(Clone, Debug)]
+pub struct TableDiff {
+    pub schema: TableSchema,
+    pub key_columns: Vec<usize>,
+    pub inserted: Vec<Row>,
+    /// values of the key columns of the deleted rows
+    pub deleted: Vec<Vec<Value>>,
+    pub updated: Vec<RowUpdate>,
+}
+
+/// key columns of a sub table: the primary key or all non SubTable columns
+fn sub_table_key_columns(schema: &TableSchema) -> Result<Vec<usize>, Qvs20Error> {
+    let key_columns = schema.primary_key_columns()?;
+    if !key_columns.is_empty() {
+        return Ok(key_columns);
+    }
+    let key_columns: Vec<usize> = (0..schema.data_types.len())
+        .filter(|i| schema.data_types[*i] != DataType::SubTable)
+        .collect();
+    if key_columns.is_empty() {
+        return Err(Qvs20Error::Error {
+            msg: format!("Sub table {} has no columns for the key.", schema.table_name),
+        });
+    }
+    //return
+    Ok(key_columns)
+}
+
+/// same column names and data types, also in sub tables
+fn same_columns(a: &TableSchema, b: &TableSchema) -> bool {
+    a.column_names == b.column_names
+        && a.data_types == b.data_types
+        && a.sub_table_schemas.iter().zip(b.sub_table_schemas.iter()).all(|x| match x {
+            (Some(a), Some(b)) => same_columns(a, b),
+            (None, None) => true,
+            _ => false,
+        })
+}
+
+fn key_values(values: &[Value], key_columns: &[usize]) -> Vec<Value> {
+    key_columns.iter().map(|i| values[*i].clone()).collect()
+}
+
+fn key_text(key: &[Value]) -> String {
+    key_to_string(&key.iter().map(value_to_key).collect::<RowKey>())
+}
+
+fn empty_table_rows(schema: &TableSchema) -> TableRows {
+    let mut table_rows = TableRows::default();
+    table_rows.table_name = schema.table_name.clone();
+    table_rows.row_delimiter = schema.row_delimiter;
+    //return
+    table_rows
+}
+
+/// value for the cells that are not in the patch
+fn empty_value(schema: &TableSchema, column: usize) -> Value {
+    match (&schema.data_types[column], &schema.sub_table_schemas[column]) {
+        (DataType::String, _) => Value::String(s!()),
+        (DataType::SubTable, Some(sub_schema)) => Value::SubTable(empty_table_rows(sub_schema)),
+        (DataType::SubTable, None) => Value::SubTable(TableRows::default()),
+        _ => Value::Null,
+    }
+}
+
+/// row indexes for every key, in the order of the rows
+fn key_groups(table_rows: &TableRows, key_columns: &[usize]) -> HashMap<RowKey, Vec<usize>> {
+    let mut groups: HashMap<RowKey, Vec<usize>> = HashMap::new();
+    for (row_index, row) in table_rows.rows.iter().enumerate() {
+        groups.entry(row_key(row, key_columns)).or_default().push(row_index);
+    }
+    groups
+}
+
+/// the rows with the same key are equal by position
+fn same_group(old: &TableRows, new: &TableRows, old_group: &[usize], new_group: &[usize]) -> bool {
+    old_group.len() == new_group.len()
+        && old_group
+            .iter()
+            .zip(new_group.iter())
+            .all(|(o, n)| old.rows[*o].values == new.rows[*n].values)
+}
+
+/// unique_key is false for sub tables without primary key
+fn diff_rows(
+    old: &TableRows,
+    new: &TableRows,
+    schema: &TableSchema,
+    key_columns: Vec<usize>,
+    unique_key: bool,
+) -> Result<TableDiff, Qvs20Error> {
+    if unique_key {
+        for table_rows in [old, new].iter() {
+            if let Err(e) = TableIndex::build(table_rows, key_columns.clone()) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Table {} {}", schema.table_name, err_trim!(e)),
+                });
+            }
+        }
+    }
+    let old_groups = key_groups(old, &key_columns);
+    let new_groups = key_groups(new, &key_columns);
+    let mut inserted = vec![];
+    let mut deleted = vec![];
+    let mut updated = vec![];
+    for new_row in new.rows.iter() {
+        let new_group = &new_groups[&row_key(new_row, &key_columns)];
+        match old_groups.get(&row_key(new_row, &key_columns)) {
+            None => inserted.push(new_row.clone()),
+            Some(old_group) if old_group.len() == 1 && new_group.len() == 1 => {
+                let changes = diff_cells(&old.rows[old_group[0]], new_row, schema, &key_columns)?;
+                if !changes.is_empty() {
+                    updated.push(RowUpdate {
+                        key: key_values(&new_row.values, &key_columns),
+                        changes,
+                    });
+                }
+            }
+            Some(old_group) => {
+                // the same key in more rows: unchanged or all inserted again
+                if !same_group(old, new, old_group, new_group) {
+                    inserted.push(new_row.clone());
+                }
+            }
+        }
+    }
+    for old_row in old.rows.iter() {
+        let old_group = &old_groups[&row_key(old_row, &key_columns)];
+        match new_groups.get(&row_key(old_row, &key_columns)) {
+            None => deleted.push(key_values(&old_row.values, &key_columns)),
+            Some(new_group) if old_group.len() == 1 && new_group.len() == 1 => (),
+            Some(new_group) => {
+                if !same_group(old, new, old_group, new_group) {
+                    deleted.push(key_values(&old_row.values, &key_columns));
+                }
+            }
+        }
+    }
+    //return
+    Ok(TableDiff {
+        schema: schema.clone(),
+        key_columns,
+        inserted,
+        deleted,
+        updated,
+    })
+}
+
+fn diff_cells(old_row: &Row, new_row: &Row, schema: &TableSchema, key_columns: &[usize]) -> Result<Vec<CellChange>, Qvs20Error> {
+    let mut changes = vec![];
+    for column in 0..schema.column_names.len() {
+        if key_columns.contains(&column) {
+            continue;
+        }
+        match (&old_row.values[column], &new_row.values[column], &schema.sub_table_schemas[column]) {
+            (Value::SubTable(old_sub), Value::SubTable(new_sub), Some(sub_schema)) => {
+                let unique_key = !sub_schema.primary_key_columns()?.is_empty();
+                let diff = diff_rows(old_sub, new_sub, sub_schema, sub_table_key_columns(sub_schema)?, unique_key)?;
+                if !diff.is_empty() {
+                    changes.push(CellChange::SubTable { column, diff });
+                }
+            }
+            (old, new, _) => {
+                if old != new {
+                    changes.push(CellChange::Value {
+                        column,
+                        old: old.clone(),
+                        new: new.clone(),
+                    });
+                }
+            }
+        }
+    }
+    //return
+    Ok(changes)
+}
+
+fn apply_diff_to_rows(table_rows: &mut TableRows, schema: &TableSchema, diff: &TableDiff) -> Result<(), Qvs20Error> {
+    // more rows with the same key only in sub tables without primary key
+    let mut index = key_groups(table_rows, &diff.key_columns);
+    let not_exist = |key: &[Value], op: &str| Qvs20Error::Error {
+        msg: format!("Patch {} row {} that does not exist in table {}.", op, key_text(key), schema.table_name),
+    };
+    for update in diff.updated.iter() {
+        let row_index = match index.get(&update.key.iter().map(value_to_key).collect::<RowKey>()) {
+            Some(group) if group.len() == 1 => group[0],
+            Some(_) => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Patch updates row {} that is not unique in table {}.", key_text(&update.key), schema.table_name),
+                })
+            }
+            None => return Err(not_exist(&update.key, "updates")),
+        };
+        let row = &mut table_rows.rows[row_index];
+        for change in update.changes.iter() {
+            match change {
+                CellChange::Value { column, new, .. } => row.values[*column] = new.clone(),
+                CellChange::SubTable { column, diff } => match (&mut row.values[*column], &schema.sub_table_schemas[*column]) {
+                    (Value::SubTable(sub_table_rows), Some(sub_schema)) => apply_diff_to_rows(sub_table_rows, sub_schema, diff)?,
+                    _ => {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Column {} is not a SubTable.", schema.column_names[*column]),
+                        })
+                    }
+                },
+            }
+        }
+    }
+    // every deleted key deletes one row with this key
+    let mut deleted = vec![];
+    for key in diff.deleted.iter() {
+        match index.get_mut(&key.iter().map(value_to_key).collect::<RowKey>()).and_then(|group| group.pop()) {
+            Some(row_index) => deleted.push(row_index),
+            None => return Err(not_exist(key, "deletes")),
+        }
+    }
+    deleted.sort_unstable();
+    for row in diff.inserted.iter() {
+        // a deleted key can be inserted again
+        if let Some(group) = index.get(&row_key(row, &diff.key_columns)) {
+            if !group.is_empty() {
+                let key = key_values(&row.values, &diff.key_columns);
+                return Err(Qvs20Error::Error {
+                    msg: format!("Patch inserts row {} that already exists in table {}.", key_text(&key), schema.table_name),
+                });
+            }
+        }
+    }
+    for row_index in deleted.iter().rev() {
+        table_rows.rows.remove(*row_index);
+    }
+    table_rows.rows.extend(diff.inserted.iter().cloned());
+    //return
+    Ok(())
+}
+
+/// schema of the patch table: op, changed and the columns of the table
+fn patch_schema(schema: &TableSchema, key_columns: &[usize]) -> Result<TableSchema, Qvs20Error> {
+    // the names are separated by | in the key property and in the changed column
+    if let Some(name) = schema.column_names.iter().find(|x| x.contains('|') || x.contains(';')) {
+        return Err(Qvs20Error::Error {
+            msg: format!("Table {} column name {} cannot contain | or ; in a patch table.", schema.table_name, name),
+        });
+    }
+    let key_names: Vec<&str> = key_columns.iter().map(|i| schema.column_names[*i].as_str()).collect();
+    let mut patch = TableSchema::new_simple_strings(0);
+    patch.table_name = schema.table_name.clone();
+    patch.table_description = s!("patch");
+    patch.row_delimiter = schema.row_delimiter;
+    patch.push_column("op", DataType::String, None, &format!("key={}", key_names.join("|")));
+    patch.push_column("changed", DataType::String, None, "");
+    for column in 0..schema.column_names.len() {
+        let sub_patch_schema = match &schema.sub_table_schemas[column] {
+            Some(sub_schema) => Some(patch_schema(sub_schema, &sub_table_key_columns(sub_schema)?)?),
+            None => None,
+        };
+        patch.push_column(&schema.column_names[column], schema.data_types[column].clone(), sub_patch_schema, "");
+    }
+    //return
+    Ok(patch)
+}
+
+/// the table schema from the patch table schema
+fn schema_from_patch_schema(patch: &TableSchema) -> TableSchema {
+    let mut schema = patch.clone();
+    for _ in 0..PATCH_COLUMNS {
+        schema.remove_column(0);
+    }
+    for sub_schema in schema.sub_table_schemas.iter_mut().flatten() {
+        *sub_schema = schema_from_patch_schema(sub_schema);
+    }
+    //return
+    schema
+}
+
+/// sub tables of inserted rows are written as inserted patch rows
+fn insert_patch_value(value: &Value, sub_table_schema: &Option<TableSchema>) -> Value {
+    match (value, sub_table_schema) {
+        (Value::SubTable(sub_table_rows), Some(sub_schema)) => {
+            let diff = TableDiff {
+                schema: sub_schema.clone(),
+                key_columns: vec![],
+                inserted: sub_table_rows.rows.clone(),
+                deleted: vec![],
+                updated: vec![],
+            };
+            Value::SubTable(patch_rows(&diff))
+        }
+        _ => value.clone(),
+    }
+}
+
+fn patch_rows(diff: &TableDiff) -> TableRows {
+    let schema = &diff.schema;
+    let mut table_rows = empty_table_rows(schema);
+    // the key columns and empty values for the deleted and updated rows
+    let key_row = |op: &str, changed: String, key: &[Value]| {
+        let mut values = vec![Value::String(s!(op)), Value::String(changed)];
+        for column in 0..schema.column_names.len() {
+            match diff.key_columns.iter().position(|x| *x == column) {
+                Some(i) => values.push(key[i].clone()),
+                None => values.push(empty_value(schema, column)),
+            }
+        }
+        values
+    };
+    for row in diff.inserted.iter() {
+        let mut values = vec![Value::String(s!("I")), Value::String(s!())];
+        for (column, value) in row.values.iter().enumerate() {
+            values.push(insert_patch_value(value, &schema.sub_table_schemas[column]));
+        }
+        table_rows.rows.push(Row { values });
+    }
+    for key in diff.deleted.iter() {
+        table_rows.rows.push(Row {
+            values: key_row("D", s!(), key),
+        });
+    }
+    for update in diff.updated.iter() {
+        let changed: Vec<&str> = update
+            .changes
+            .iter()
+            .map(|change| match change {
+                CellChange::Value { column, .. } | CellChange::SubTable { column, .. } => schema.column_names[*column].as_str(),
+            })
+            .collect();
+        let mut values = key_row("U", changed.join("|"), &update.key);
+        for change in update.changes.iter() {
+            match change {
+                CellChange::Value { column, new, .. } => values[PATCH_COLUMNS + column] = new.clone(),
+                CellChange::SubTable { column, diff } => values[PATCH_COLUMNS + column] = Value::SubTable(patch_rows(diff)),
+            }
+        }
+        table_rows.rows.push(Row { values });
+    }
+    //return
+    table_rows
+}
+
+fn diff_from_patch_rows(patch_rows: &TableRows, patch: &TableSchema) -> Result<TableDiff, Qvs20Error> {
+    if patch.column_names.len() < PATCH_COLUMNS || patch.column_names[0] != "op" || patch.column_names[1] != "changed" {
+        return Err(Qvs20Error::Error {
+            msg: format!("Table {} is not a patch table.", patch.table_name),
+        });
+    }
+    let schema = schema_from_patch_schema(patch);
+    let mut key_columns = vec![];
+    let key_names = property_value(&patch.additional_properties[0], "key").unwrap_or("");
+    for name in key_names.split('|').filter(|x| !x.is_empty()) {
+        match schema.column_index(name) {
+            Some(i) => key_columns.push(i),
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Patch table {} key column {} does not exist.", patch.table_name, name),
+                })
+            }
+        }
+    }
+    let mut inserted = vec![];
+    let mut deleted = vec![];
+    let mut updated = vec![];
+    for (row_index, row) in patch_rows.rows.iter().enumerate() {
+        let values = &row.values[PATCH_COLUMNS..];
+        match value_to_string(&row.values[0]).as_str() {
+            "I" => {
+                let mut new_values = vec![];
+                for (column, value) in values.iter().enumerate() {
+                    match (value, &patch.sub_table_schemas[PATCH_COLUMNS + column]) {
+                        (Value::SubTable(sub_patch_rows), Some(sub_patch)) => {
+                            let sub_diff = diff_from_patch_rows(sub_patch_rows, sub_patch)?;
+                            if !sub_diff.deleted.is_empty() || !sub_diff.updated.is_empty() {
+                                return Err(Qvs20Error::Error {
+                                    msg: format!("Patch table {} row {} inserts a sub table with D or U rows.", patch.table_name, row_index),
+                                });
+                            }
+                            let mut sub_table_rows = empty_table_rows(&sub_diff.schema);
+                            sub_table_rows.rows = sub_diff.inserted;
+                            new_values.push(Value::SubTable(sub_table_rows));
+                        }
+                        (value, _) => new_values.push(value.clone()),
+                    }
+                }
+                inserted.push(Row { values: new_values });
+            }
+            "D" => deleted.push(key_values(values, &key_columns)),
+            "U" => {
+                let mut changes = vec![];
+                for name in value_to_string(&row.values[1]).split('|').filter(|x| !x.is_empty()) {
+                    let column = match schema.column_index(name) {
+                        Some(i) => i,
+                        None => {
+                            return Err(Qvs20Error::Error {
+                                msg: format!("Patch table {} row {} changed column {} does not exist.", patch.table_name, row_index, name),
+                            })
+                        }
+                    };
+                    match (&values[column], &patch.sub_table_schemas[PATCH_COLUMNS + column]) {
+                        (Value::SubTable(sub_patch_rows), Some(sub_patch)) => changes.push(CellChange::SubTable {
+                            column,
+                            diff: diff_from_patch_rows(sub_patch_rows, sub_patch)?,
+                        }),
+                        (value, _) => changes.push(CellChange::Value {
+                            column,
+                            old: Value::Null,
+                            new: value.clone(),
+                        }),
+                    }
+                }
+                updated.push(RowUpdate {
+                    key: key_values(values, &key_columns),
+                    changes,
+                });
+            }
+            op => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Patch table {} row {} has unknown op {}. Expected I, D or U.", patch.table_name, row_index, op),
+                })
+            }
+        }
+    }
+    //return
+    Ok(TableDiff {
+        schema,
+        key_columns,
+        inserted,
+        deleted,
+        updated,
+    })
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty() && self.updated.is_empty()
+    }
+
+    /// the diff as a table that can be written to a QVS21 file
+    pub fn to_patch_table(&self) -> Result<Table, Qvs20Error> {
+        //return
+        Ok(Table {
+            schema: patch_schema(&self.schema, &self.key_columns)?,
+            table_rows: patch_rows(self),
+        })
+    }
+
+    /// read the diff from a patch table
+    pub fn from_patch_table(patch: &Table) -> Result<TableDiff, Qvs20Error> {
+        diff_from_patch_rows(&patch.table_rows, &patch.schema)
+    }
+}
+
+impl Table {
+    /// changes from this table to the new table. The rows are matched by the key columns.
+    /// Without key columns the primary key is used.
+    pub fn diff(&self, new: &Table, key_columns: &[&str]) -> Result<TableDiff, Qvs20Error> {
+        if !same_columns(&self.schema, &new.schema) {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} and table {} have different columns.", self.schema.table_name, new.schema.table_name),
+            });
+        }
+        let key_columns = if key_columns.is_empty() {
+            self.schema.primary_key_columns()?
+        } else {
+            self.column_indexes(key_columns)?
+        };
+        if key_columns.is_empty() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} diff needs key columns or a primary key.", self.schema.table_name),
+            });
+        }
+        diff_rows(&self.table_rows, &new.table_rows, &new.schema, key_columns, true)
+    }
+
+    /// apply the changes from diff(). On error the table is unchanged.
+    pub fn apply_diff(&mut self, diff: &TableDiff) -> Result<(), Qvs20Error> {
+        if !same_columns(&self.schema, &diff.schema) {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} and the diff have different columns.", self.schema.table_name),
+            });
+        }
+        let mut table_rows = self.table_rows.clone();
+        apply_diff_to_rows(&mut table_rows, &self.schema, diff)?;
+        self.table_rows = table_rows;
+        //return
+        Ok(())
+    }
+
+    /// apply the changes from a patch table
+    pub fn apply_patch(&mut self, patch: &Table) -> Result<(), Qvs20Error> {
+        let diff = TableDiff::from_patch_table(patch)?;
+        self.apply_diff(&diff)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_diff_and_patch() {
+        let pre_string = "[T][countries][description]\n[String][Integer][SubTable]\n[][][1[U][cities][sub table]1[String][Integer]1[][]1[primary_key=1][]1[city][population]1]\n[primary_key=1][][]\n[country][population][cities]\n";
+        let old_data = "[Slovenia][2000000][1[Ljubljana][300000]1[Koper][30000]1]\n[Croatia][4000000][]\n[Italia][60000000][1[Milano][1400000]1]\n";
+        let new_data = "[Slovenia][2100000][1[Ljubljana][290000]1[Koper][30000]1[Maribor][95000]1]\n[Italia][60000000][1[Milano][1400000]1]\n[Austria][9000000][1[Wien][1900000]1]\n";
+        let mut old = unwrap!(Table::from_qvs20_str_with_schema(&format!("{}{}", pre_string, old_data)));
+        let new = unwrap!(Table::from_qvs20_str_with_schema(&format!("{}{}", pre_string, new_data)));
+
+        let diff = unwrap!(old.diff(&new, &[]));
+        assert_eq!(diff.inserted.len(), 1);
+        assert_eq!(format!("{:?}", diff.deleted), "[[String(\"Croatia\")]]");
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0].changes.len(), 2);
+
+        let patch = unwrap!(diff.to_patch_table());
+        let text = patch.write_table();
+        assert_eq!(
+            text,
+            "[T][countries][patch]\n[String][String][String][Integer][SubTable]\n[][][][][1[U][cities][patch]1[String][String][String][Integer]1[][][][]1[key=city][][][]1[op][changed][city][population]1]\n[key=country][][][][]\n[op][changed][country][population][cities]\n[I][][Austria][9000000][1[I][][Wien][1900000]1]\n[D][][Croatia][][]\n[U][population|cities][Slovenia][2100000][1[I][][Maribor][95000]1[U][population][Ljubljana][290000]1]\n"
+        );
+
+        let patch = unwrap!(Table::from_qvs20_str_with_schema(&text));
+        unwrap!(old.apply_patch(&patch));
+        assert_eq!(old.write_table(), new.write_table());
+        assert!(unwrap!(old.diff(&new, &["country"])).is_empty());
+
+        let err = old.apply_patch(&patch).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Patch inserts row Maribor that already exists in table cities.");
+        assert_eq!(old.write_table(), new.write_table());
+        // sub table without primary key with the same row twice
+        let pre_string = "[T][countries][description]\n[String][SubTable]\n[][1[U][cities][sub table]1[String]1[]1[]1[city]1]\n[primary_key=1][]\n[country][cities]\n";
+        let mut old = unwrap!(Table::from_qvs20_str_with_schema(&format!("{}{}", pre_string, "[Slovenia][1[Koper]1[Koper]1[Piran]1]\n")));
+        let new = unwrap!(Table::from_qvs20_str_with_schema(&format!("{}{}", pre_string, "[Slovenia][1[Koper]1[Piran]1[Piran]1]\n")));
+        let diff = unwrap!(old.diff(&new, &[]));
+        let patch = unwrap!(Table::from_qvs20_str_with_schema(&unwrap!(diff.to_patch_table()).write_table()));
+        unwrap!(old.apply_patch(&patch));
+        assert_eq!(old.write_table(), new.write_table());
+
+        let mut new = new;
+        new.schema.column_names[0] = s!("country|state");
+        old.schema.column_names[0] = s!("country|state");
+        let err = unwrap!(old.diff(&new, &[])).to_patch_table().unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Table countries column name country|state cannot contain | or ; in a patch table.");
+    }
+}
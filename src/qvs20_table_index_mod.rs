@@ -0,0 +1,215 @@
+// qvs20_table_index_mod
+
+//! Primary key, unique columns and the hash index to find rows by key.
+//! The Value type is not hashable (Float, SubTable), so the key is made of strings.
+//! Decimals are normalized, because 1.0 and 1.00 are the same key. The same for the Float -0.0 and 0.0.
+//! Null is a separate key part and not the same as the empty String.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// one key column of the key
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KeyPart {
+    Null,
+    Text(String),
+}
+
+impl fmt::Display for KeyPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyPart::Null => write!(f, "Null"),
+            KeyPart::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// the key of one row, one part for every key column
+pub type RowKey = Vec<KeyPart>;
+
+/// key part for the value. Not for display.
+pub fn value_to_key(value: &Value) -> KeyPart {
+    match value {
+        Value::Null => KeyPart::Null,
+        Value::Decimal(d) => KeyPart::Text(d.normalize().to_string()),
+        // -0.0 == 0.0
+        Value::Float(f) if *f == 0.0 => KeyPart::Text(value_to_string(&Value::Float(0.0))),
+        _ => KeyPart::Text(value_to_string(value)),
+    }
+}
+
+/// key of the row from the key columns
+pub fn row_key(row: &Row, key_columns: &[usize]) -> RowKey {
+    key_columns.iter().map(|i| value_to_key(&row.values[*i])).collect()
+}
+
+/// the key for error messages
+pub fn key_to_string(key: &[KeyPart]) -> String {
+    key.iter().map(|k| k.to_string()).collect::<Vec<String>>().join(",")
+}
+
+/// key columns and already found keys
+struct KeyCheck {
+    key_columns: Vec<usize>,
+    primary_key: bool,
+    keys: HashSet<RowKey>,
+}
+
+/// Checks the primary key and the unique columns while reading rows.
+/// Every sub table has its own scope, so every TableRows makes its own UniqueKeys.
+pub struct UniqueKeys {
+    checks: Vec<KeyCheck>,
+}
+
+impl UniqueKeys {
+    pub fn new(schema: &TableSchema, constraints: &[Option<ColumnConstraints>]) -> Result<UniqueKeys, Qvs20Error> {
+        let mut checks = vec![];
+        let primary_key = schema.primary_key_from_constraints(constraints)?;
+        if !primary_key.is_empty() {
+            checks.push(KeyCheck {
+                key_columns: primary_key,
+                primary_key: true,
+                keys: HashSet::new(),
+            });
+        }
+        for (i, c) in constraints.iter().enumerate() {
+            if let Some(c) = c {
+                if c.unique {
+                    checks.push(KeyCheck {
+                        key_columns: vec![i],
+                        primary_key: false,
+                        keys: HashSet::new(),
+                    });
+                }
+            }
+        }
+        //return
+        Ok(UniqueKeys { checks })
+    }
+    /// remember the key of the row. Error if it is a duplicate or the primary key has a Null.
+    pub fn check_row(&mut self, row: &Row, row_index: usize, schema: &TableSchema) -> Result<(), Qvs20Error> {
+        for check in self.checks.iter_mut() {
+            let key = row_key(row, &check.key_columns);
+            let names: Vec<&str> = check.key_columns.iter().map(|i| schema.column_names[*i].as_str()).collect();
+            if check.primary_key && key.contains(&KeyPart::Null) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Null in primary key row {} columns {}: {}", row_index, names.join(","), key_to_string(&key)),
+                });
+            }
+            if check.keys.contains(&key) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Duplicate key row {} columns {}: {}", row_index, names.join(","), key_to_string(&key)),
+                });
+            }
+            check.keys.insert(key);
+        }
+        //return
+        Ok(())
+    }
+}
+
+/// Hash index on the key columns of a Table.
+/// The index is not updated when the rows change. Build it again.
+#[derive(Clone, Debug, Default)]
+pub struct TableIndex {
+    /// indexes of the key columns
+    pub key_columns: Vec<usize>,
+    /// key to row index
+    map: HashMap<RowKey, usize>,
+}
+
+impl TableIndex {
+    /// build the index. The key must be unique.
+    pub fn build(table_rows: &TableRows, key_columns: Vec<usize>) -> Result<TableIndex, Qvs20Error> {
+        let mut map = HashMap::with_capacity(table_rows.rows.len());
+        for (row_index, row) in table_rows.rows.iter().enumerate() {
+            let key = row_key(row, &key_columns);
+            if let Some(first) = map.insert(key.clone(), row_index) {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Duplicate key row {} and row {}: {}", first, row_index, key_to_string(&key)),
+                });
+            }
+        }
+        //return
+        Ok(TableIndex { key_columns, map })
+    }
+    /// row index for the key values in the order of the key columns
+    pub fn get(&self, key: &[Value]) -> Option<usize> {
+        let key: RowKey = key.iter().map(value_to_key).collect();
+        self.map.get(&key).copied()
+    }
+    /// count of keys
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_duplicate_primary_key() {
+        let pre_string = "[T][table name][description]\n[String][Integer][String]\n[][][]\n[primary_key=2][primary_key=1][unique=T]\n[name1][name2][name3]\n";
+        let data = "[a][1][x]\n[b][1][y]\n[a][2][z]\n";
+        let s = format!("{}{}", pre_string, data);
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        assert_eq!(unwrap!(table.schema.primary_key_columns()), vec![1, 0]);
+
+        let data = "[a][1][x]\n[b][1][y]\n[a][1][z]\n";
+        let s = format!("{}{}", pre_string, data);
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Duplicate key row 2 columns name2,name1: 1,a");
+
+        let data = "[a][1][x]\n[b][1][x]\n";
+        let s = format!("{}{}", pre_string, data);
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Duplicate key row 1 columns name3: x");
+
+        // -0.0 is 0.0 and Null is not allowed in the primary key
+        let pre_string = "[T][table name][description]\n[String][Integer][Float]\n[][][]\n[primary_key=1][unique=T][unique=T]\n[name1][name2][name3]\n";
+        let s = format!("{}{}", pre_string, "[b][1][0]\n[a][][-0]\n");
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Duplicate key row 1 columns name3: 0");
+        let s = pre_string.replace("[primary_key=1][unique=T]", "[unique=T][primary_key=1]") + "[a][][0]\n";
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Null in primary key row 0 columns name2: Null");
+    }
+    #[test]
+    pub fn t02_sub_table_scope() {
+        // the same key in different sub tables is not a duplicate
+        let s = r"[T][table_name][description]
+[String][SubTable]
+[][1[U][cities][sub table]1[String]1[]1[primary_key=1]1[city]1]
+[primary_key=1][]
+[country][cities]
+[Slovenia][1[Koper]1[Piran]1]
+[Italia][1[Koper]1[Milano]1]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let index = unwrap!(table.primary_key_index());
+        let row = unwrap!(table.find_row(&index, &[Value::String(s!("Italia"))]));
+        match &row.values[1] {
+            Value::SubTable(t) => assert_eq!(t.rows.len(), 2),
+            _ => panic!("expected SubTable"),
+        }
+        assert!(table.find_row(&index, &[Value::String(s!("Croatia"))]).is_none());
+
+        let s = s.replace("[Piran]", "[Koper]");
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: start sub table rows Duplicate key row 1 columns city: Koper"
+        );
+    }
+}
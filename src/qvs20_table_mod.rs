@@ -5,7 +5,9 @@
 //! and don't need a fixed Rust struct in compile time.
 //! It means that sometimes a change in the table does not dictate change in source code and compiling.
 
+use crate::qvs20_constraints_mod::*;
 use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
 use crate::qvs20_table_rows_mod::*;
 use crate::qvs20_table_schema_mod::*;
 use crate::qvs20_writer_mod::*;
@@ -57,14 +59,242 @@ impl Table {
         Ok(table)
     }
 
-    /// write to String
+    /// write to String without checking the constraints, use write_table_checked() for files to partners
     pub fn write_table(&self) -> String {
         let mut wrt = WriterForQvs20::new();
         self.schema.write_schema_to_writer(&mut wrt, false);
-        self.table_rows.write_table_rows_to_writer(&mut wrt);
+        self.table_rows.write_data_rows_to_writer(&mut wrt);
         //return
         wrt.return_and_finish()
     }
+
+    /// write to String, but first check the constraints declared in the schema
+    pub fn write_table_checked(&self) -> Result<String, Qvs20Error> {
+        self.table_rows.check_constraints(&self.schema)?;
+        //return
+        Ok(self.write_table())
+    }
+
+    /// hash index on the primary key declared in the schema
+    pub fn primary_key_index(&self) -> Result<TableIndex, Qvs20Error> {
+        let key_columns = self.schema.primary_key_columns()?;
+        if key_columns.is_empty() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} has no primary key.", self.schema.table_name),
+            });
+        }
+        TableIndex::build(&self.table_rows, key_columns)
+    }
+
+    /// hash index on any columns. The values must be unique.
+    pub fn index_on_columns(&self, column_names: &[&str]) -> Result<TableIndex, Qvs20Error> {
+        let key_columns = self.column_indexes(column_names)?;
+        TableIndex::build(&self.table_rows, key_columns)
+    }
+
+    /// find the row by key values using the index
+    pub fn find_row(&self, index: &TableIndex, key: &[Value]) -> Option<&Row> {
+        match index.get(key) {
+            Some(row_index) => self.table_rows.rows.get(row_index),
+            None => None,
+        }
+    }
+
+    /// indexes of the columns by name
+    pub fn column_indexes(&self, column_names: &[&str]) -> Result<Vec<usize>, Qvs20Error> {
+        let mut vec = vec![];
+        for name in column_names.iter() {
+            match self.schema.column_index(name) {
+                Some(i) => vec.push(i),
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Table {} does not have column {}.", self.schema.table_name, name),
+                    })
+                }
+            }
+        }
+        //return
+        Ok(vec)
+    }
+
+    /// Extract the SubTable column into a separate child table with the name of the column.
+    /// The child table starts with the parent key columns, then the sub table columns.
+    /// The primary key of the parent is used as parent key. If there is no primary key,
+    /// a generated Integer column `table_name_id` with the row number is added to the parent.
+    /// If the sub table has a primary key, it is unique only inside one parent row,
+    /// so the child primary key is the parent key columns and then the sub table key columns.
+    /// The column is removed from the parent.
+    pub fn flatten_sub_table(&mut self, column_name: &str) -> Result<Table, Qvs20Error> {
+        let column = self.column_indexes(&[column_name])?[0];
+        let sub_schema = match &self.schema.sub_table_schemas[column] {
+            Some(s) => s.clone(),
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Column {} is not a SubTable.", column_name),
+                })
+            }
+        };
+        let mut key_columns = self.schema.primary_key_columns()?;
+        // validate all before any change of self
+        let key_names: Vec<String> = if key_columns.is_empty() {
+            // generated parent key
+            let key_name = format!("{}_id", self.schema.table_name);
+            if self.schema.column_index(&key_name).is_some() {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Table {} already has column {}.", self.schema.table_name, key_name),
+                });
+            }
+            vec![key_name]
+        } else {
+            key_columns.iter().map(|i| self.schema.column_names[*i].clone()).collect()
+        };
+        let sub_key_columns = sub_schema.primary_key_columns()?;
+        for name in key_names.iter() {
+            if sub_schema.column_index(name).is_some() {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Sub table {} already has column {}.", column_name, name),
+                });
+            }
+        }
+        if key_columns.is_empty() {
+            self.schema.push_column(&key_names[0], DataType::Integer, None, "primary_key=1");
+            for (row_index, row) in self.table_rows.rows.iter_mut().enumerate() {
+                row.values.push(Value::Integer(row_index as i64 + 1));
+            }
+            key_columns.push(self.schema.column_names.len() - 1);
+        }
+        // the child schema starts with the parent key columns
+        let mut child_schema = TableSchema {
+            table_name: s!(column_name),
+            table_description: sub_schema.table_description.clone(),
+            ..Default::default()
+        };
+        for (position, i) in key_columns.iter().enumerate() {
+            let property = if sub_key_columns.is_empty() {
+                s!()
+            } else {
+                with_primary_key("", position + 1)
+            };
+            child_schema.push_column(&self.schema.column_names[*i], self.schema.data_types[*i].clone(), None, &property);
+        }
+        for i in 0..sub_schema.column_names.len() {
+            let property = match sub_key_columns.iter().position(|c| *c == i) {
+                Some(position) => with_primary_key(&sub_schema.additional_properties[i], key_columns.len() + position + 1),
+                None => sub_schema.additional_properties[i].clone(),
+            };
+            child_schema.push_column(
+                &sub_schema.column_names[i],
+                sub_schema.data_types[i].clone(),
+                sub_schema.sub_table_schemas[i].clone(),
+                &property,
+            );
+        }
+        child_schema.set_depth(0)?;
+
+        let mut child_rows = TableRows::default();
+        for row in self.table_rows.rows.iter_mut() {
+            // key values before the column is removed, because the indexes change
+            let key_values: Vec<Value> = key_columns.iter().map(|i| row.values[*i].clone()).collect();
+            if let Value::SubTable(sub_table_rows) = row.values.remove(column) {
+                for sub_row in sub_table_rows.rows.into_iter() {
+                    let mut values = key_values.clone();
+                    values.extend(sub_row.values);
+                    child_rows.rows.push(Row { values });
+                }
+            }
+        }
+        child_rows.set_depth(0)?;
+        self.schema.remove_column(column);
+        //return
+        Ok(Table {
+            schema: child_schema,
+            table_rows: child_rows,
+        })
+    }
+
+    /// Group the rows of the child table by the parent key and add them as a new SubTable column.
+    /// The child key columns are not repeated in the sub table.
+    /// Every child row must have a parent row.
+    pub fn nest(&mut self, child: &Table, parent_key: &[&str], child_key: &[&str], column_name: &str) -> Result<(), Qvs20Error> {
+        if parent_key.len() != child_key.len() {
+            return Err(Qvs20Error::Error {
+                msg: s!("Parent key and child key must have the same count of columns."),
+            });
+        }
+        if self.schema.column_index(column_name).is_some() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} already has column {}.", self.schema.table_name, column_name),
+            });
+        }
+        let parent_columns = self.column_indexes(parent_key)?;
+        let child_columns = child.column_indexes(child_key)?;
+        let index = TableIndex::build(&self.table_rows, parent_columns)?;
+
+        // rows of the sub tables grouped by the parent row
+        let mut groups: Vec<Vec<Row>> = vec![vec![]; self.table_rows.rows.len()];
+        for (row_index, row) in child.table_rows.rows.iter().enumerate() {
+            let key: Vec<Value> = child_columns.iter().map(|i| row.values[*i].clone()).collect();
+            let parent_row = match index.get(&key) {
+                Some(p) => p,
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!(
+                            "Table {} row {} has no parent in table {}: {}",
+                            child.schema.table_name,
+                            row_index,
+                            self.schema.table_name,
+                            key_to_string(&row_key(row, &child_columns))
+                        ),
+                    })
+                }
+            };
+            let values = row
+                .values
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !child_columns.contains(i))
+                .map(|(_, v)| v.clone())
+                .collect();
+            groups[parent_row].push(Row { values });
+        }
+        let mut sub_schema = child.schema.clone();
+        let mut sorted_columns = child_columns.clone();
+        sorted_columns.sort();
+        for i in sorted_columns.iter().rev() {
+            sub_schema.remove_column(*i);
+        }
+        // the remaining primary key columns are renumbered from 1
+        let mut key_columns: Vec<(u32, usize)> = vec![];
+        for (i, constraints) in sub_schema.column_constraints()?.iter().enumerate() {
+            if let Some(position) = constraints.as_ref().and_then(|c| c.primary_key) {
+                key_columns.push((position, i));
+            }
+        }
+        key_columns.sort();
+        for (new_position, (_, i)) in key_columns.iter().enumerate() {
+            sub_schema.additional_properties[*i] = with_primary_key(&sub_schema.additional_properties[*i], new_position + 1);
+        }
+        sub_schema.set_depth(1)?;
+        self.schema.push_column(column_name, DataType::SubTable, Some(sub_schema), "");
+        for (row, group) in self.table_rows.rows.iter_mut().zip(groups) {
+            let mut sub_table_rows = TableRows::default();
+            sub_table_rows.rows = group;
+            sub_table_rows.set_depth(1)?;
+            row.values.push(Value::SubTable(sub_table_rows));
+        }
+        // return
+        Ok(())
+    }
+}
+
+/// the additional property with the new position in the primary key
+fn with_primary_key(property: &str, position: usize) -> String {
+    let property = remove_property_key(property, "primary_key");
+    if property.is_empty() {
+        format!("primary_key={}", position)
+    } else {
+        format!("{};primary_key={}", property, position)
+    }
 }
 
 #[cfg(test)]
@@ -404,7 +634,105 @@ mod test {
             "Error: Failed conversion to time. input contains invalid characters. row 0 col 2"
         );
     }
+    #[test]
+    pub fn t10_constraints() {
+        let pre_string = "[T][table name][description]\n[String][Integer][Date]\n[][][]\n[enum=a|b][min=0;max=100][min=2020-01-01]\n[name1][name2][name3]\n";
+        let data = "[a][100][2020-01-01]\n[b][0][2020-12-31]\n";
+        let s = format!("{}{}", pre_string, data);
+        let mut table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        assert!(table.write_table_checked().is_ok());
+
+        let data = "[a][100][2020-01-01]\n[b][101][2020-12-31]\n";
+        let s = format!("{}{}", pre_string, data);
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Constraint violation row 1 col 1 name2: value 101 is greater than max 100"
+        );
+
+        let data = "[a][100][2019-12-31]\n";
+        let s = format!("{}{}", pre_string, data);
+        let err = Table::from_qvs20_str_with_schema(&s).unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Constraint violation row 0 col 2 name3: value 2019-12-31 is less than min 2020-01-01"
+        );
+
+        // the writer checks the changes in memory
+        table.table_rows.rows[0].values[0] = Value::String(s!("c"));
+        let err = table.write_table_checked().unwrap_err();
+        assert_eq!(
+            remove_src_loc(err),
+            "Error: Constraint violation row 0 col 0 name1: value c is not in enum"
+        );
+    }
     
+    #[test]
+    pub fn t11_flatten_and_nest() {
+        let s = r"[T][countries][description]
+[String][SubTable]
+[][1[U][cities][sub table]1[String][Integer]1[][]1[][]1[city][population]1]
+[][]
+[country][cities]
+[Slovenia][1[Ljubljana][300000]1[Koper][30000]1]
+[Croatia][]
+[Italia][1[Milano][1400000]1]
+";
+        let mut table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let child = unwrap!(table.flatten_sub_table("cities"));
+        // without primary key the parent gets a generated key
+        assert_eq!(
+            table.write_table(),
+            "[T][countries][description]\n[String][Integer]\n[][]\n[][primary_key=1]\n[country][countries_id]\n[Slovenia][1]\n[Croatia][2]\n[Italia][3]\n"
+        );
+        assert_eq!(
+            child.write_table(),
+            "[T][cities][sub table]\n[Integer][String][Integer]\n[][][]\n[][][]\n[countries_id][city][population]\n[1][Ljubljana][300000]\n[1][Koper][30000]\n[3][Milano][1400000]\n"
+        );
+
+        // the child key columns are removed from the primary key of the sub table
+        let mut keyed_child = child.clone();
+        keyed_child.schema.additional_properties = vec![s!("primary_key=1"), s!("primary_key=2"), s!("")];
+        let mut parent = table.clone();
+        unwrap!(parent.nest(&keyed_child, &["countries_id"], &["countries_id"], "cities"));
+        assert_eq!(unwrap!(parent.schema.sub_table_schemas[2].as_ref()).additional_properties, vec![s!("primary_key=1"), s!("")]);
+
+        unwrap!(table.nest(&child, &["countries_id"], &["countries_id"], "cities"));
+        table.schema.remove_column(1);
+        for row in table.table_rows.rows.iter_mut() {
+            row.values.remove(1);
+        }
+        assert_eq!(table.write_table(), s);
+
+        // no change after an error
+        let mut bad = unwrap!(Table::from_qvs20_str_with_schema(&s.replace("[city][population]", "[countries_id][population]")));
+        let err = bad.flatten_sub_table("cities").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Sub table cities already has column countries_id.");
+        assert_eq!(bad.schema.column_names.len(), 2);
+
+        let mut child = child;
+        child.table_rows.rows[2].values[0] = Value::Integer(4);
+        let err = table.nest(&child, &["country"], &["countries_id"], "cities2").unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Table cities row 0 has no parent in table countries: 1");
+
+        // the sub table key is unique only together with the parent key
+        let s = r"[T][countries][description]
+[String][SubTable]
+[][1[U][cities][sub table]1[String][Integer]1[][]1[primary_key=1][min=0]1[city][population]1]
+[primary_key=1][]
+[country][cities]
+[Slovenia][1[Koper][30000]1]
+[Italia][1[Koper][1000]1]
+";
+        let mut table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let child = unwrap!(table.flatten_sub_table("cities"));
+        let child_text = unwrap!(child.write_table_checked());
+        assert_eq!(
+            child_text,
+            "[T][cities][sub table]\n[String][String][Integer]\n[][][]\n[primary_key=1][primary_key=2][min=0]\n[country][city][population]\n[Slovenia][Koper][30000]\n[Italia][Koper][1000]\n"
+        );
+        assert!(Table::from_qvs20_str_with_schema(&child_text).is_ok());
+    }
     #[test]
     pub fn t03_write_schema_and_data() {
         let schema = TableSchema::new_simple_strings(3);
@@ -0,0 +1,287 @@
+// qvs20_table_ops_mod
+
+//! Small dataframe-like operations: select, filter, with_column and rename_column.
+//! The result is a new Table with consistent schema and rows, ready for write_table().
+//! Column names with a dot like `Cities.City` are columns of the sub table in the column `Cities`.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// Read-only view of one row for predicates and computed columns.
+pub struct RowView<'a> {
+    pub schema: &'a TableSchema,
+    pub row: &'a Row,
+    pub row_index: usize,
+}
+
+impl<'a> RowView<'a> {
+    /// value by column name. None if the column does not exist.
+    pub fn get(&self, column_name: &str) -> Option<&'a Value> {
+        self.schema.column_index(column_name).map(|i| &self.row.values[i])
+    }
+    /// value by column index
+    pub fn value(&self, column: usize) -> &'a Value {
+        &self.row.values[column]
+    }
+}
+
+/// selected column, with selected columns of the sub table or None for all columns
+pub struct ColumnSelection {
+    column: usize,
+    sub_columns: Option<Vec<ColumnSelection>>,
+}
+
+pub fn parse_selection(schema: &TableSchema, column_names: &[&str]) -> Result<Vec<ColumnSelection>, Qvs20Error> {
+    // None is the whole column
+    let mut columns: Vec<(usize, Option<Vec<&str>>)> = vec![];
+    for column_name in column_names.iter() {
+        if let Some(column) = schema.column_index(column_name) {
+            match columns.iter_mut().find(|x| x.0 == column) {
+                Some(x) => x.1 = None,
+                None => columns.push((column, None)),
+            }
+            continue;
+        }
+        let sub_table_column = column_name
+            .split_once('.')
+            .and_then(|(name, sub_name)| schema.column_index(name).map(|i| (i, sub_name)))
+            .filter(|(i, _)| schema.sub_table_schemas[*i].is_some());
+        match sub_table_column {
+            Some((column, sub_name)) => match columns.iter_mut().find(|x| x.0 == column) {
+                Some((_, Some(sub_names))) => sub_names.push(sub_name),
+                Some((_, None)) => (),
+                None => columns.push((column, Some(vec![sub_name]))),
+            },
+            None => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Table {} does not have column {}.", schema.table_name, column_name),
+                })
+            }
+        }
+    }
+    let mut selection = vec![];
+    for (column, sub_names) in columns {
+        let sub_columns = match (sub_names, &schema.sub_table_schemas[column]) {
+            (Some(sub_names), Some(sub_schema)) => Some(parse_selection(sub_schema, &sub_names)?),
+            _ => None,
+        };
+        selection.push(ColumnSelection { column, sub_columns });
+    }
+    //return
+    Ok(selection)
+}
+
+pub fn select_schema(schema: &TableSchema, selection: &[ColumnSelection]) -> Result<TableSchema, Qvs20Error> {
+    let mut selected = schema.clone();
+    selected.data_types.clear();
+    selected.sub_table_schemas.clear();
+    selected.additional_properties.clear();
+    selected.column_names.clear();
+    // the primary key is valid only with all key columns
+    let primary_key = schema.primary_key_columns()?;
+    let whole_key = primary_key.iter().all(|k| selection.iter().any(|s| s.column == *k));
+    for s in selection.iter() {
+        let sub_table_schema = match (&s.sub_columns, &schema.sub_table_schemas[s.column]) {
+            (Some(sub_columns), Some(sub_schema)) => Some(select_schema(sub_schema, sub_columns)?),
+            (_, sub_schema) => sub_schema.clone(),
+        };
+        let mut additional_property = schema.additional_properties[s.column].clone();
+        if !whole_key {
+            additional_property = remove_property_key(&additional_property, "primary_key");
+        }
+        selected.push_column(
+            &schema.column_names[s.column],
+            schema.data_types[s.column].clone(),
+            sub_table_schema,
+            &additional_property,
+        );
+    }
+    //return
+    Ok(selected)
+}
+
+/// the selected values of one row
+pub fn select_row(row: &Row, schema: &TableSchema, selection: &[ColumnSelection]) -> Row {
+    let mut values = vec![];
+    for s in selection.iter() {
+        let value = match (&row.values[s.column], &s.sub_columns, &schema.sub_table_schemas[s.column]) {
+            (Value::SubTable(sub_table_rows), Some(sub_columns), Some(sub_schema)) => {
+                Value::SubTable(select_rows(sub_table_rows, sub_schema, sub_columns))
+            }
+            (value, _, _) => value.clone(),
+        };
+        values.push(value);
+    }
+    //return
+    Row { values }
+}
+
+fn select_rows(table_rows: &TableRows, schema: &TableSchema, selection: &[ColumnSelection]) -> TableRows {
+    let mut selected = TableRows::default();
+    selected.table_name = table_rows.table_name.clone();
+    selected.row_delimiter = table_rows.row_delimiter;
+    for row in table_rows.rows.iter() {
+        selected.rows.push(select_row(row, schema, selection));
+    }
+    //return
+    selected
+}
+
+fn rename_in_schema(schema: &mut TableSchema, column_name: &str, new_name: &str) -> Result<(), Qvs20Error> {
+    if let Some(column) = schema.column_index(column_name) {
+        if schema.column_index(new_name).is_some() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} already has column {}.", schema.table_name, new_name),
+            });
+        }
+        schema.column_names[column] = s!(new_name);
+        return Ok(());
+    }
+    if let Some((name, sub_name)) = column_name.split_once('.') {
+        if let Some(column) = schema.column_index(name) {
+            if let Some(sub_schema) = &mut schema.sub_table_schemas[column] {
+                return rename_in_schema(sub_schema, sub_name, new_name);
+            }
+        }
+    }
+    //return
+    Err(Qvs20Error::Error {
+        msg: format!("Table {} does not have column {}.", schema.table_name, column_name),
+    })
+}
+
+impl Table {
+    /// new table with only the columns in this order.
+    /// `Cities.City` selects the column City in the sub table Cities.
+    /// The primary key is removed if not all key columns are selected.
+    pub fn select(&self, column_names: &[&str]) -> Result<Table, Qvs20Error> {
+        let selection = parse_selection(&self.schema, column_names)?;
+        //return
+        Ok(Table {
+            schema: select_schema(&self.schema, &selection)?,
+            table_rows: select_rows(&self.table_rows, &self.schema, &selection),
+        })
+    }
+
+    /// new table with only the rows where the predicate is true
+    pub fn filter<F>(&self, predicate: F) -> Table
+    where
+        F: Fn(&RowView) -> bool,
+    {
+        let mut table_rows = TableRows::default();
+        table_rows.table_name = self.table_rows.table_name.clone();
+        table_rows.row_delimiter = self.table_rows.row_delimiter;
+        for (row_index, row) in self.table_rows.rows.iter().enumerate() {
+            let row_view = RowView {
+                schema: &self.schema,
+                row,
+                row_index,
+            };
+            if predicate(&row_view) {
+                table_rows.rows.push(row.clone());
+            }
+        }
+        //return
+        Table {
+            schema: self.schema.clone(),
+            table_rows,
+        }
+    }
+
+    /// new table with an added column computed from every row.
+    /// The values must be of the data type or Null.
+    pub fn with_column<F>(&self, column_name: &str, data_type: DataType, f: F) -> Result<Table, Qvs20Error>
+    where
+        F: Fn(&RowView) -> Value,
+    {
+        if self.schema.column_index(column_name).is_some() {
+            return Err(Qvs20Error::Error {
+                msg: format!("Table {} already has column {}.", self.schema.table_name, column_name),
+            });
+        }
+        if data_type == DataType::SubTable {
+            return Err(Qvs20Error::Error {
+                msg: format!("Computed column {} cannot be a SubTable.", column_name),
+            });
+        }
+        let mut values = vec![];
+        for (row_index, row) in self.table_rows.rows.iter().enumerate() {
+            let row_view = RowView {
+                schema: &self.schema,
+                row,
+                row_index,
+            };
+            let value = f(&row_view);
+            if let Some(value_data_type) = value.data_type() {
+                if value_data_type != data_type {
+                    return Err(Qvs20Error::Error {
+                        msg: format!(
+                            "Computed column {} row {}: value {} is {} and not {}.",
+                            column_name,
+                            row_index,
+                            value_to_string(&value),
+                            value_data_type,
+                            data_type
+                        ),
+                    });
+                }
+            }
+            values.push(value);
+        }
+        let mut table = self.clone();
+        table.schema.push_column(column_name, data_type, None, "");
+        for (row, value) in table.table_rows.rows.iter_mut().zip(values) {
+            row.values.push(value);
+        }
+        //return
+        Ok(table)
+    }
+
+    /// rename the column. `Cities.City` renames the column City in the sub table Cities.
+    pub fn rename_column(&mut self, column_name: &str, new_name: &str) -> Result<(), Qvs20Error> {
+        rename_in_schema(&mut self.schema, column_name, new_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_select_filter_with_column() {
+        let s = r"[T][countries][description]
+[String][Integer][String][SubTable]
+[][][][1[U][cities][sub table]1[String][Integer]1[][]1[primary_key=1][]1[city][population]1]
+[primary_key=1][min=0][][]
+[country][population][capital][cities]
+[Slovenia][2000000][Ljubljana][1[Ljubljana][300000]1[Koper][30000]1]
+[Croatia][4000000][Zagreb][]
+[Italia][60000000][Roma][1[Milano][1400000]1]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let mut table = unwrap!(table
+            .filter(|row| row.get("population") < Some(&Value::Integer(10_000_000)))
+            .select(&["population", "cities.city", "country"]));
+        table = unwrap!(table.with_column("big", DataType::Bool, |row| match row.value(0) {
+            Value::Integer(i) => Value::Bool(*i > 3_000_000),
+            _ => Value::Null,
+        }));
+        unwrap!(table.rename_column("cities.city", "name"));
+        assert_eq!(
+            table.write_table(),
+            "[T][countries][description]\n[Integer][SubTable][String][Bool]\n[][1[U][cities][sub table]1[String]1[]1[primary_key=1]1[name]1][][]\n[min=0][][primary_key=1][]\n[population][cities][country][big]\n[2000000][1[Ljubljana]1[Koper]1][Slovenia][F]\n[4000000][][Croatia][T]\n"
+        );
+
+        let err = table.with_column("small", DataType::Integer, |_| Value::Bool(true)).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Computed column small row 0: value T is Bool and not Integer.");
+        let err = table.select(&["cities.population"]).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Table cities does not have column population.");
+        // without the primary key column the primary key is removed
+        let selected = unwrap!(table.select(&["population"]));
+        assert_eq!(selected.schema.additional_properties, vec!["min=0"]);
+    }
+}
@@ -5,13 +5,16 @@
 //! and don't need a fixed Rust struct in compile time.
 //! It means that sometimes a change in the table does not dictate change in source code and compiling.
 
+use crate::qvs20_constraints_mod::*;
 use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_index_mod::*;
 use crate::qvs20_table_schema_mod::*;
 use crate::qvs20_writer_mod::*;
 use crate::src_loc;
 
 use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
 use rust_decimal::prelude::*;
+use std::cmp::Ordering;
 use std::str::FromStr;
 
 #[derive(Clone, Debug, Default)]
@@ -47,6 +50,8 @@ pub enum Value {
     Date(NaiveDate),
     Time(NaiveTime),
     SubTable(TableRows),
+    /// empty field [] in a column that is not String or SubTable
+    Null,
 }
 
 impl Default for Value {
@@ -55,6 +60,153 @@ impl Default for Value {
     }
 }
 
+impl Value {
+    /// data type of the value. None for Null.
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::String(_) => Some(DataType::String),
+            Value::Integer(_) => Some(DataType::Integer),
+            Value::Decimal(_) => Some(DataType::Decimal),
+            Value::Float(_) => Some(DataType::Float),
+            Value::Bool(_) => Some(DataType::Bool),
+            Value::DateTimeFixedOffset(_) => Some(DataType::DateTimeFixedOffset),
+            Value::Date(_) => Some(DataType::Date),
+            Value::Time(_) => Some(DataType::Time),
+            Value::SubTable(_) => Some(DataType::SubTable),
+            Value::Null => None,
+        }
+    }
+}
+
+/// empty field is Null for all data types except String and SubTable
+pub fn is_nullable(data_type: &DataType) -> bool {
+    !matches!(data_type, DataType::String | DataType::SubTable)
+}
+
+/// position of the data type in the order of values with different data types
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Integer(_) | Value::Decimal(_) | Value::Float(_) => 2,
+        Value::String(_) => 3,
+        Value::Date(_) => 4,
+        Value::Time(_) => 5,
+        Value::DateTimeFixedOffset(_) => 6,
+        Value::SubTable(_) => 7,
+    }
+}
+
+/// order of numbers with the same value in different data types
+fn number_rank(value: &Value) -> u8 {
+    match value {
+        Value::Integer(_) => 0,
+        Value::Decimal(_) => 1,
+        _ => 2,
+    }
+}
+
+/// NaN is greater than all numbers and equal to NaN
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+fn to_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+        Value::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+/// exact Decimal for Integer and Decimal, for Float only if it is finite and in the range of Decimal
+fn to_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Integer(i) => Some(Decimal::from(*i)),
+        Value::Decimal(d) => Some(*d),
+        Value::Float(f) if *f == 0.0 => Some(Decimal::ZERO),
+        Value::Float(f) if f.is_finite() && f.abs() >= 1e-28 && f.abs() < 7.9e28 => Decimal::from_f64_retain(*f),
+        _ => None,
+    }
+}
+
+fn cmp_numbers(a: &Value, b: &Value) -> Ordering {
+    let ordering = match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+        (Value::Decimal(a), Value::Decimal(b)) => a.cmp(b),
+        (Value::Integer(a), Value::Decimal(b)) => Decimal::from(*a).cmp(b),
+        (Value::Decimal(a), Value::Integer(b)) => a.cmp(&Decimal::from(*b)),
+        (Value::Float(a), Value::Float(b)) => cmp_f64(*a, *b),
+        (a, b) => match (to_decimal(a), to_decimal(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => cmp_f64(to_f64(a), to_f64(b)),
+        },
+    };
+    //return
+    ordering.then_with(|| number_rank(a).cmp(&number_rank(b)))
+}
+
+fn cmp_table_rows(a: &TableRows, b: &TableRows) -> Ordering {
+    for (row_a, row_b) in a.rows.iter().zip(b.rows.iter()) {
+        let ordering = row_a.values.cmp(&row_b.values);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    //return
+    a.rows.len().cmp(&b.rows.len())
+}
+
+/// The order of values:
+///
+/// - Null is the smallest value. It comes first in Asc order.
+/// - Integer, Decimal and Float are compared by the numeric value.
+///   Integer and Decimal are compared exactly, also with a finite Float in the range of Decimal.
+///   Other Floats are compared as f64.
+///   If a number has the same value in different data types, Integer < Decimal < Float.
+///   So `Integer(1)` is not equal to `Decimal(1.0)`, but they sort together.
+/// - NaN is equal to NaN and greater than all other numbers. -0.0 is equal to 0.0.
+/// - Decimal is equal for the same value with a different scale: 1.0 == 1.00
+/// - DateTimeFixedOffset is compared by the instant in time.
+/// - SubTable is compared row by row and value by value.
+/// - Values of different data types: Null < Bool < numbers < String < Date < Time < DateTimeFixedOffset < SubTable.
+///   In one column all values have the same data type, so this is rarely needed.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Date(a), Value::Date(b)) => a.cmp(b),
+            (Value::Time(a), Value::Time(b)) => a.cmp(b),
+            (Value::DateTimeFixedOffset(a), Value::DateTimeFixedOffset(b)) => a.cmp(b),
+            (Value::SubTable(a), Value::SubTable(b)) => cmp_table_rows(a, b),
+            (a, b) if type_rank(a) == 2 && type_rank(b) == 2 => cmp_numbers(a, b),
+            (a, b) => type_rank(a).cmp(&type_rank(b)),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
 impl TableRows {
     pub fn new(table_name: &str, row_delimiter: u8) -> Result<TableRows, Qvs20Error> {
         let mut table_rows = TableRows::default();
@@ -64,6 +216,20 @@ impl TableRows {
         Ok(table_rows)
     }
 
+    /// set the row delimiters of the rows and all sub tables for the depth
+    pub fn set_depth(&mut self, depth: usize) -> Result<(), Qvs20Error> {
+        self.row_delimiter = row_delimiter_for_depth(depth)?;
+        for row in self.rows.iter_mut() {
+            for value in row.values.iter_mut() {
+                if let Value::SubTable(sub_table_rows) = value {
+                    sub_table_rows.set_depth(depth + 1)?;
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+
     /// rows from separate file than schema
     pub fn rows_from_qvs20_str(input: &str, schema: &TableSchema) -> Result<TableRows, Qvs20Error> {
         // the input is String to ensure it is well-formed utf8
@@ -74,12 +240,29 @@ impl TableRows {
         table_rows.row_delimiter = schema.row_delimiter;
         // first row is table_name and must be equal to schema
         table_rows.read_1st_row_file_type_and_table_name(&mut rdr)?;
+        if table_rows.table_name != schema.table_name {
+            return Err(Qvs20Error::Error {
+                msg: format!(
+                    "TableRows table name {} differs from TableSchema table name {}.",
+                    table_rows.table_name, schema.table_name
+                ),
+            });
+        }
         table_rows.append_data_rows(&mut rdr, schema)?;
         //return
         Ok(table_rows)
     }
+    /// read only the 1st row of a separate rows file: the table name
+    /// to find the schema before reading the rows
+    pub fn table_name_from_qvs20_str(input: &str) -> Result<String, Qvs20Error> {
+        let mut rdr = ReaderForQvs20::new(input.as_bytes());
+        let mut table_rows = TableRows::default();
+        table_rows.read_1st_row_file_type_and_table_name(&mut rdr)?;
+        //return
+        Ok(table_rows.table_name)
+    }
     /// 1st row: file_type, table name, row_delimiter
-    fn read_1st_row_file_type_and_table_name(
+    pub fn read_1st_row_file_type_and_table_name(
         &mut self,
         rdr: &mut ReaderForQvs20,
     ) -> Result<(), Qvs20Error> {
@@ -106,13 +289,116 @@ impl TableRows {
         rdr: &mut ReaderForQvs20,
         schema: &TableSchema,
     ) -> Result<(), Qvs20Error> {
-        while !rdr.peek_next_is_eof() && !rdr.peek_next_is_end_of_sub_table() {
-            // data row
-            while let Some(result) = self.while_append_one_data_row(rdr, schema) {
-                // if Err then propagate
-                result?;
+        // constraints are parsed once for all rows and sub tables
+        let constraints = SchemaConstraints::new(schema)?;
+        self.append_data_rows_with_constraints(rdr, schema, &constraints)
+    }
+
+    fn append_data_rows_with_constraints(
+        &mut self,
+        rdr: &mut ReaderForQvs20,
+        schema: &TableSchema,
+        constraints: &SchemaConstraints,
+    ) -> Result<(), Qvs20Error> {
+        let mut unique_keys = UniqueKeys::new(schema, &constraints.columns)?;
+        while let Some(row) = self.next_data_row(rdr, schema, constraints, &mut unique_keys)? {
+            self.rows.push(row);
+        }
+        //return
+        Ok(())
+    }
+
+    /// read one data row and check the constraints.
+    /// The row is returned and not appended to self.rows, for streaming.
+    /// None at the end of rows.
+    pub fn next_data_row(
+        &mut self,
+        rdr: &mut ReaderForQvs20,
+        schema: &TableSchema,
+        constraints: &SchemaConstraints,
+        unique_keys: &mut UniqueKeys,
+    ) -> Result<Option<Row>, Qvs20Error> {
+        if rdr.peek_next_is_eof() || rdr.peek_next_is_end_of_sub_table() {
+            return Ok(None);
+        }
+        let mut row = Row::default();
+        while let Some(result) = self.while_append_one_data_row(rdr, schema, constraints, &mut row) {
+            // if Err then propagate
+            result?;
+        }
+        self.check_row_constraints(&row, self.active_row, schema, &constraints.columns)?;
+        unique_keys.check_row(&row, self.active_row, schema)?;
+        self.active_row += 1;
+        //return
+        Ok(Some(row))
+    }
+
+    /// check the constraints of one row, the error has row and column position
+    fn check_row_constraints(
+        &self,
+        row: &Row,
+        row_index: usize,
+        schema: &TableSchema,
+        constraints: &[Option<ColumnConstraints>],
+    ) -> Result<(), Qvs20Error> {
+        for (column, value) in row.values.iter().enumerate() {
+            if let Some(Some(c)) = constraints.get(column) {
+                if let Err(e) = c.check(value) {
+                    return Err(Qvs20Error::Error {
+                        msg: format!(
+                            "Constraint violation row {} col {} {}: {}",
+                            row_index,
+                            column,
+                            schema.column_names.get(column).unwrap_or(&s!()),
+                            err_trim!(e)
+                        ),
+                    });
+                }
+            }
+        }
+        //return
+        Ok(())
+    }
+
+    /// check the constraints and unique keys of all rows and sub tables, before write or after changes in memory
+    pub fn check_constraints(&self, schema: &TableSchema) -> Result<(), Qvs20Error> {
+        let constraints = SchemaConstraints::new(schema)?;
+        self.check_constraints_with(schema, &constraints)
+    }
+
+    fn check_constraints_with(&self, schema: &TableSchema, constraints: &SchemaConstraints) -> Result<(), Qvs20Error> {
+        let mut unique_keys = UniqueKeys::new(schema, &constraints.columns)?;
+        for row_index in 0..self.rows.len() {
+            self.check_one_row(&self.rows[row_index], row_index, schema, constraints, &mut unique_keys)?;
+        }
+        //return
+        Ok(())
+    }
+
+    /// check the constraints, the unique keys and the sub tables of one row,
+    /// for rows that are streamed one at a time
+    pub(crate) fn check_one_row(
+        &self,
+        row: &Row,
+        row_index: usize,
+        schema: &TableSchema,
+        constraints: &SchemaConstraints,
+        unique_keys: &mut UniqueKeys,
+    ) -> Result<(), Qvs20Error> {
+        self.check_row_constraints(row, row_index, schema, &constraints.columns)?;
+        unique_keys.check_row(row, row_index, schema)?;
+        for (column, value) in row.values.iter().enumerate() {
+            if let Value::SubTable(sub_table_rows) = value {
+                if let (Some(Some(sub_schema)), Some(Some(sub_constraints))) =
+                    (schema.sub_table_schemas.get(column), constraints.sub_tables.get(column))
+                {
+                    if let Err(e) = sub_table_rows.check_constraints_with(sub_schema, sub_constraints) {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("row {} col {} sub table {}", row_index, column, err_trim!(e)),
+                        });
+                    }
+                }
             }
-            self.active_row += 1;
         }
         //return
         Ok(())
@@ -124,6 +410,8 @@ impl TableRows {
         &mut self,
         rdr: &mut ReaderForQvs20,
         schema: &TableSchema,
+        constraints: &SchemaConstraints,
+        row: &mut Row,
     ) -> Option<Result<(), Qvs20Error>> {
         let result = match rdr.next() {
             Some(p) => p,
@@ -145,10 +433,6 @@ impl TableRows {
                 }))
             }
         };
-        // create new row if needed
-        if self.rows.len() <= self.active_row {
-            self.rows.push(Row { values: vec![] });
-        }
         match token {
             Token::Field(u) => {
                 let value = match self.from_utf8_to_value(u, schema) {
@@ -159,7 +443,7 @@ impl TableRows {
                         }))
                     }
                 };
-                self.rows[self.active_row].values.push(value);
+                row.values.push(value);
                 self.active_column += 1;
                 // return
                 Some(Ok(()))
@@ -218,7 +502,18 @@ impl TableRows {
                         ),
                     }));
                 }
-                match sub_table_rows.append_data_rows(rdr, sub_schema) {
+                let sub_constraints = match constraints.sub_tables.get(self.active_column) {
+                    Some(Some(c)) => c,
+                    _ => {
+                        return Some(Err(Qvs20Error::Error {
+                            msg: format!(
+                                "start sub table data row {} column {} missing sub table constraints {}",
+                                self.active_row, self.active_column, src_loc!()
+                            ),
+                        }))
+                    }
+                };
+                match sub_table_rows.append_data_rows_with_constraints(rdr, sub_schema, sub_constraints) {
                     Ok(()) => (),
                     Err(e) => {
                         return Some(Err(Qvs20Error::Error {
@@ -226,9 +521,7 @@ impl TableRows {
                         }))
                     }
                 }
-                self.rows[self.active_row]
-                    .values
-                    .push(Value::SubTable(sub_table_rows));
+                row.values.push(Value::SubTable(sub_table_rows));
                 // return
                 Some(Ok(()))
             }
@@ -263,6 +556,9 @@ impl TableRows {
     }
     /// for all types except sub_table
     fn from_utf8_to_value(&self, value: &[u8], schema: &TableSchema) -> Result<Value, Qvs20Error> {
+        if value.is_empty() && is_nullable(&schema.data_types[self.active_column]) {
+            return Ok(Value::Null);
+        }
         // various data types from utf8
         match schema.data_types[self.active_column] {
             DataType::String => match Self::from_u8_to_string(value) {
@@ -377,11 +673,49 @@ impl TableRows {
                     })
                 }
             },
-            // TODO: conversions
-            DataType::SubTable => return Ok(Value::Integer(0)),
+            DataType::SubTable => {
+                // a sub table without rows is written as empty field []
+                if !value.is_empty() {
+                    return Err(Qvs20Error::Error {
+                        msg: s!(
+                            "{}Expected SubTable found field. row {} col {}",
+                            src_loc!(),
+                            self.active_row,
+                            self.active_column
+                        ),
+                    });
+                }
+                let mut sub_table_rows = TableRows::default();
+                if let Some(Some(sub_schema)) = schema.sub_table_schemas.get(self.active_column) {
+                    sub_table_rows.row_delimiter = sub_schema.row_delimiter;
+                }
+                return Ok(Value::SubTable(sub_table_rows));
+            }
         };
     }
 
+    /// value of the data type from text that is not escaped, like default values in properties.
+    /// SubTable has no text value.
+    pub fn value_from_str(text: &str, data_type: &DataType) -> Result<Value, Qvs20Error> {
+        let field_value = text.as_bytes();
+        if field_value.is_empty() && is_nullable(data_type) {
+            return Ok(Value::Null);
+        }
+        match data_type {
+            DataType::String => Ok(Value::String(s!(text))),
+            DataType::Integer => Self::from_u8_to_i64(field_value).map(Value::Integer),
+            DataType::Decimal => Self::from_u8_to_decimal(field_value).map(Value::Decimal),
+            DataType::Float => Self::from_u8_to_f64(field_value).map(Value::Float),
+            DataType::Bool => Self::from_u8_to_bool(field_value).map(Value::Bool),
+            DataType::DateTimeFixedOffset => Self::from_u8_to_datetime(field_value).map(Value::DateTimeFixedOffset),
+            DataType::Date => Self::from_u8_to_date(field_value).map(Value::Date),
+            DataType::Time => Self::from_u8_to_time(field_value).map(Value::Time),
+            DataType::SubTable => Err(Qvs20Error::Error {
+                msg: s!("SubTable does not have values"),
+            }),
+        }
+    }
+
     pub fn from_u8_to_string(field_value: &[u8]) -> Result<String, Qvs20Error> {
         let str_value = match ReaderForQvs20::unescape(field_value) {
             Ok(s) => s,
@@ -552,15 +886,17 @@ impl TableRows {
         //return
         wrt.return_and_finish()
     }
-    /// write rows to writer
+    /// write rows to writer as a separate rows file
     pub fn write_table_rows_to_writer(&self, wrt: &mut WriterForQvs20) {
-        if wrt.output_is_empty() {
-            // when TableRows are in separate file from Schema
-            // the 1st row has 2 fields: file type and TableName
-            wrt.write_string("R");
-            wrt.write_string(&self.table_name);
-            wrt.write_delimiter();
-        }
+        // the TableRows are in separate file from Schema
+        // the 1st row has 2 fields: file type and TableName
+        wrt.write_string("R");
+        wrt.write_string(&self.table_name);
+        wrt.write_delimiter();
+        self.write_data_rows_to_writer(wrt);
+    }
+    /// write only the data rows to writer, without the 1st row
+    pub fn write_data_rows_to_writer(&self, wrt: &mut WriterForQvs20) {
         for row in self.rows.iter() {
             for value in row.values.iter() {
                 match value {
@@ -568,14 +904,12 @@ impl TableRows {
                     Value::Integer(i) => wrt.write_integer(*i),
                     Value::Decimal(d) => wrt.write_decimal(*d),
                     Value::Float(f) => wrt.write_float(*f),
-                    /*
-                    Bool(bool),
-                    DateTimeFixedOffset(DateTime<FixedOffset>),
-                    Date(NaiveDate),
-                    Time(NaiveTime),
-                    SubTable(TableRows),
-                    */
-                    _ => {}
+                    Value::Bool(b) => wrt.write_bool(*b),
+                    Value::DateTimeFixedOffset(d) => wrt.write_datetime(d),
+                    Value::Date(d) => wrt.write_date(d),
+                    Value::Time(t) => wrt.write_time(t),
+                    Value::SubTable(sub_table_rows) => wrt.write_sub_table_rows(sub_table_rows),
+                    Value::Null => wrt.write_string(""),
                 }
             }
             wrt.write_delimiter();
@@ -5,6 +5,7 @@
 //! and don't need a fixed Rust struct in compile time.
 //! It means that sometimes a change in the table does not dictate change in source code and compiling.
 
+use crate::qvs20_constraints_mod::*;
 use crate::qvs20_reader_mod::*;
 use crate::*;
 
@@ -49,6 +50,25 @@ pub enum DataType {
     SubTable,
 }
 
+/// row delimiter for the depth: LF for the table, 1-9 for sub tables
+pub fn row_delimiter_for_depth(depth: usize) -> Result<u8, Qvs20Error> {
+    match depth {
+        0 => Ok(b'\n'),
+        1..=9 => Ok(b'0' + depth as u8),
+        _ => Err(Qvs20Error::Error {
+            msg: format!("Sub table cannot be nested deeper than 9 levels: {}", depth),
+        }),
+    }
+}
+
+/// depth of the row delimiter: 0 for LF, 1-9 for sub tables
+pub fn depth_for_row_delimiter(row_delimiter: u8) -> usize {
+    match row_delimiter {
+        b'1'..=b'9' => (row_delimiter - b'0') as usize,
+        _ => 0,
+    }
+}
+
 // the read_field can return this variants for the schema fields
 enum ValueForSchema {
     String(String),
@@ -103,6 +123,87 @@ impl TableSchema {
         //return
         Ok(())
     }
+    /// constraints for every column parsed from the additional properties
+    pub fn column_constraints(&self) -> Result<Vec<Option<ColumnConstraints>>, Qvs20Error> {
+        let mut vec_of_constraints = vec![];
+        for (i, property) in self.additional_properties.iter().enumerate() {
+            let constraints = match ColumnConstraints::parse(property, &self.data_types[i]) {
+                Ok(c) => c,
+                Err(e) => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Schema 4th row column {} {}", self.column_names.get(i).unwrap_or(&s!()), err_trim!(e)),
+                    })
+                }
+            };
+            vec_of_constraints.push(constraints);
+        }
+        //return
+        Ok(vec_of_constraints)
+    }
+    /// set the row delimiters of the schema and all sub table schemas for the depth
+    pub fn set_depth(&mut self, depth: usize) -> Result<(), Qvs20Error> {
+        self.row_delimiter = row_delimiter_for_depth(depth)?;
+        for sub_schema in self.sub_table_schemas.iter_mut().flatten() {
+            sub_schema.set_depth(depth + 1)?;
+        }
+        //return
+        Ok(())
+    }
+    /// add a column at the end
+    pub fn push_column(
+        &mut self,
+        column_name: &str,
+        data_type: DataType,
+        sub_table_schema: Option<TableSchema>,
+        additional_property: &str,
+    ) {
+        self.data_types.push(data_type);
+        self.sub_table_schemas.push(sub_table_schema);
+        self.additional_properties.push(s!(additional_property));
+        self.column_names.push(s!(column_name));
+    }
+    /// remove the column from all schema rows
+    pub fn remove_column(&mut self, column: usize) {
+        self.data_types.remove(column);
+        self.sub_table_schemas.remove(column);
+        self.additional_properties.remove(column);
+        self.column_names.remove(column);
+    }
+    /// index of the column with this name
+    pub fn column_index(&self, column_name: &str) -> Option<usize> {
+        self.column_names.iter().position(|x| x == column_name)
+    }
+    /// indexes of the primary key columns in the order of the key.
+    /// Empty if there is no primary key.
+    pub fn primary_key_columns(&self) -> Result<Vec<usize>, Qvs20Error> {
+        self.primary_key_from_constraints(&self.column_constraints()?)
+    }
+    /// primary key columns from already parsed constraints
+    pub fn primary_key_from_constraints(&self, constraints: &[Option<ColumnConstraints>]) -> Result<Vec<usize>, Qvs20Error> {
+        let mut key_columns = vec![];
+        for (i, constraints) in constraints.iter().enumerate() {
+            if let Some(c) = constraints {
+                if let Some(position) = c.primary_key {
+                    if self.data_types[i] == DataType::SubTable {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Primary key column {} cannot be a SubTable.", self.column_names[i]),
+                        });
+                    }
+                    key_columns.push((position, i));
+                }
+            }
+        }
+        key_columns.sort();
+        for (i, (position, _)) in key_columns.iter().enumerate() {
+            if *position as usize != i + 1 {
+                return Err(Qvs20Error::Error {
+                    msg: format!("Primary key positions must be 1, 2, 3,... Found: {}", position),
+                });
+            }
+        }
+        //return
+        Ok(key_columns.into_iter().map(|(_, i)| i).collect())
+    }
     fn active_row_str(&self) -> String {
         // humans count from 1, machines count from 0
         let a = match self.active_row {
@@ -428,7 +529,10 @@ impl TableSchema {
 
     /// write to writer
     pub fn write_schema_to_writer(&self, wrt: &mut WriterForQvs20, schema_only:bool) {
-        if schema_only==true{
+        if self.row_delimiter != b'\n' && self.row_delimiter != 0 {
+            // sub table schema has row delimiter 1-9
+            wrt.write_string("U");
+        } else if schema_only==true{
             wrt.write_string("S");
         }else{
             wrt.write_string("T");
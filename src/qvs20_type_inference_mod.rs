@@ -0,0 +1,321 @@
+// qvs20_type_inference_mod
+
+//! Legacy sources give tables with only String columns like `TableSchema::new_simple_strings()`.
+//! The type inference scans the values and proposes the narrowest data type for every column:
+//! Bool `T`/`F`, Integer, Decimal, Float, Date, Time, DateTimeFixedOffset or else String.
+//! Empty fields are ignored for inference and become Null after conversion.
+//! Numbers with leading zeros like `007` stay String, because they are usually codes.
+//! Cells that look like a sub table `1[a][b]1[c][d]1` make the column a SubTable.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+/// One cell that did not fit the new data type.
+/// The value is converted to Null or to an empty sub table.
+#[derive(Clone, Debug)]
+pub struct ConversionIssue {
+    pub row: usize,
+    pub column: usize,
+    pub column_name: String,
+    pub text: String,
+    pub msg: String,
+}
+
+/// narrowest data type for one non-empty text
+pub fn infer_text(text: &str) -> DataType {
+    if text == "T" || text == "F" {
+        return DataType::Bool;
+    }
+    let unsigned = text.trim_start_matches(['-', '+']);
+    let leading_zero = unsigned.len() > 1 && unsigned.starts_with('0') && !unsigned.starts_with("0.");
+    if !leading_zero {
+        if TableRows::from_u8_to_i64(text.as_bytes()).is_ok() {
+            return DataType::Integer;
+        }
+        // the exponent is for Float
+        let exponent = text.contains(['e', 'E']);
+        if !exponent && TableRows::from_u8_to_decimal(text.as_bytes()).is_ok() {
+            return DataType::Decimal;
+        }
+        // f64 parses also words like inf and NaN
+        if text.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | '+' | '-' | 'e' | 'E'))
+            && TableRows::from_u8_to_f64(text.as_bytes()).is_ok()
+        {
+            return DataType::Float;
+        }
+    }
+    if TableRows::from_u8_to_date(text.as_bytes()).is_ok() {
+        return DataType::Date;
+    }
+    if TableRows::from_u8_to_time(text.as_bytes()).is_ok() {
+        return DataType::Time;
+    }
+    if TableRows::from_u8_to_datetime(text.as_bytes()).is_ok() {
+        return DataType::DateTimeFixedOffset;
+    }
+    //return
+    DataType::String
+}
+
+/// data type that can hold the values of both data types
+pub fn wider_data_type(a: &DataType, b: &DataType) -> DataType {
+    match (a, b) {
+        (a, b) if a == b => a.clone(),
+        (DataType::Integer, DataType::Decimal) | (DataType::Decimal, DataType::Integer) => DataType::Decimal,
+        (DataType::Integer | DataType::Decimal, DataType::Float) | (DataType::Float, DataType::Integer | DataType::Decimal) => {
+            DataType::Float
+        }
+        _ => DataType::String,
+    }
+}
+
+/// rows of a cell that looks like a sub table `1[a][b]1[c][d]1`.
+/// All rows must have the same count of columns. Nested sub tables are not recognized.
+fn parse_sub_table_text(text: &str) -> Option<Vec<Vec<String>>> {
+    let bytes = text.as_bytes();
+    if bytes.len() < 4 || !matches!(bytes[0], b'1'..=b'9') || bytes[1] != b'[' || bytes[bytes.len() - 1] != bytes[0] {
+        return None;
+    }
+    let delimiter = bytes[0];
+    // the reader needs the sub table inside a field of a row
+    let input = format!("[{}]\n", text);
+    let mut rdr = ReaderForQvs20::new(input.as_bytes());
+    if !matches!(rdr.next(), Some(Ok(Token::StartSubTable(d))) if d == delimiter) {
+        return None;
+    }
+    let mut rows: Vec<Vec<String>> = vec![];
+    let mut row = vec![];
+    loop {
+        match rdr.next() {
+            Some(Ok(Token::Field(field))) => row.push(TableRows::from_u8_to_string(field).ok()?),
+            Some(Ok(Token::RowDelimiter(d))) if d == delimiter && !row.is_empty() => rows.push(std::mem::take(&mut row)),
+            Some(Ok(Token::EndSubTable(d))) if d == delimiter && row.is_empty() => break,
+            _ => return None,
+        }
+    }
+    if !matches!(rdr.next(), Some(Ok(Token::RowDelimiter(b'\n')))) || rdr.next().is_some() {
+        return None;
+    }
+    if rows.is_empty() || rows.iter().any(|r| r.len() != rows[0].len()) {
+        return None;
+    }
+    //return
+    Some(rows)
+}
+
+/// all rows of all cells, when every cell looks like a sub table with the same count of columns
+fn parse_sub_table_column(texts: &[&str]) -> Option<Vec<Vec<String>>> {
+    let mut all_rows: Vec<Vec<String>> = vec![];
+    for text in texts.iter() {
+        let rows = parse_sub_table_text(text)?;
+        if !all_rows.is_empty() && rows[0].len() != all_rows[0].len() {
+            return None;
+        }
+        all_rows.extend(rows);
+    }
+    //return
+    Some(all_rows)
+}
+
+/// TableRows with String values
+fn table_rows_from_strings(rows: Vec<Vec<String>>) -> TableRows {
+    let mut table_rows = TableRows::default();
+    for row in rows {
+        table_rows.rows.push(Row {
+            values: row.into_iter().map(Value::String).collect(),
+        });
+    }
+    //return
+    table_rows
+}
+
+/// non-empty String values of the column
+fn column_texts(table_rows: &TableRows, column: usize) -> Vec<&str> {
+    table_rows
+        .rows
+        .iter()
+        .filter_map(|row| match row.values.get(column) {
+            Some(Value::String(s)) if !s.is_empty() => Some(s.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// convert one text to the data type of the column.
+/// The error has the replacement value and the message.
+fn convert_text(text: &str, schema: &TableSchema, column: usize) -> Result<Value, (Value, String)> {
+    match &schema.data_types[column] {
+        DataType::SubTable => {
+            let sub_schema = match &schema.sub_table_schemas[column] {
+                Some(s) => s,
+                None => return Err((Value::SubTable(TableRows::default()), s!("SubTable column without sub table schema"))),
+            };
+            let mut empty = TableRows::default();
+            empty.table_name = sub_schema.table_name.clone();
+            empty.row_delimiter = sub_schema.row_delimiter;
+            if text.is_empty() {
+                return Ok(Value::SubTable(empty));
+            }
+            match parse_sub_table_text(text) {
+                Some(rows) if rows[0].len() == sub_schema.column_names.len() => {
+                    let (sub_table_rows, sub_issues) = table_rows_from_strings(rows).convert_to_schema(sub_schema);
+                    match sub_issues.first() {
+                        Some(issue) => Err((
+                            Value::SubTable(sub_table_rows),
+                            format!(
+                                "sub table row {} col {} {}: {}",
+                                issue.row, issue.column, issue.column_name, issue.msg
+                            ),
+                        )),
+                        None => Ok(Value::SubTable(sub_table_rows)),
+                    }
+                }
+                _ => Err((
+                    Value::SubTable(empty),
+                    format!("not a sub table with {} columns", sub_schema.column_names.len()),
+                )),
+            }
+        }
+        data_type => match TableRows::value_from_str(text, data_type) {
+            Ok(v) => Ok(v),
+            Err(e) => Err((Value::Null, s!(remove_src_loc(e).trim_start_matches("Error: ")))),
+        },
+    }
+}
+
+impl TableSchema {
+    /// propose a schema for all-String rows. The column names are 1, 2, 3,...
+    pub fn infer_from_rows(table_rows: &TableRows) -> TableSchema {
+        let count_of_column = table_rows.rows.first().map_or(0, |row| row.values.len());
+        let mut schema = TableSchema::new_simple_strings(count_of_column);
+        schema.table_name = table_rows.table_name.clone();
+        //return
+        schema.infer_data_types(table_rows)
+    }
+
+    /// copy of the schema with inferred data types for the String columns.
+    /// Names and additional properties stay the same.
+    pub fn infer_data_types(&self, table_rows: &TableRows) -> TableSchema {
+        let mut schema = self.clone();
+        let depth = depth_for_row_delimiter(self.row_delimiter);
+        for column in 0..schema.data_types.len() {
+            if schema.data_types[column] != DataType::String {
+                continue;
+            }
+            let texts = column_texts(table_rows, column);
+            if texts.is_empty() {
+                continue;
+            }
+            if let (Some(sub_rows), Ok(sub_row_delimiter)) = (parse_sub_table_column(&texts), row_delimiter_for_depth(depth + 1)) {
+                let mut sub_schema = TableSchema::new_simple_strings(sub_rows[0].len());
+                sub_schema.table_name = schema.column_names[column].clone();
+                sub_schema.table_description = s!();
+                sub_schema.row_delimiter = sub_row_delimiter;
+                let sub_schema = sub_schema.infer_data_types(&table_rows_from_strings(sub_rows));
+                schema.data_types[column] = DataType::SubTable;
+                schema.sub_table_schemas[column] = Some(sub_schema);
+                continue;
+            }
+            let mut data_type = infer_text(texts[0]);
+            for text in texts[1..].iter() {
+                if data_type == DataType::String {
+                    break;
+                }
+                data_type = wider_data_type(&data_type, &infer_text(text));
+            }
+            schema.data_types[column] = data_type;
+        }
+        //return
+        schema
+    }
+}
+
+impl TableRows {
+    /// convert String values to the data types of the schema, usually from infer_data_types().
+    /// Values that are not String are copied. Cells that don't fit are reported.
+    pub fn convert_to_schema(&self, schema: &TableSchema) -> (TableRows, Vec<ConversionIssue>) {
+        let mut issues = vec![];
+        let mut table_rows = TableRows::default();
+        table_rows.table_name = schema.table_name.clone();
+        table_rows.row_delimiter = schema.row_delimiter;
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let mut values = vec![];
+            for (column, value) in row.values.iter().enumerate() {
+                let value = match (value, schema.data_types.get(column)) {
+                    (Value::String(text), Some(data_type)) if *data_type != DataType::String => {
+                        match convert_text(text, schema, column) {
+                            Ok(v) => v,
+                            Err((v, msg)) => {
+                                issues.push(ConversionIssue {
+                                    row: row_index,
+                                    column,
+                                    column_name: schema.column_names[column].clone(),
+                                    text: text.clone(),
+                                    msg,
+                                });
+                                v
+                            }
+                        }
+                    }
+                    _ => value.clone(),
+                };
+                values.push(value);
+            }
+            table_rows.rows.push(Row { values });
+        }
+        //return
+        (table_rows, issues)
+    }
+}
+
+impl Table {
+    /// infer the data types and convert the rows
+    pub fn infer_types(&self) -> (Table, Vec<ConversionIssue>) {
+        let schema = self.schema.infer_data_types(&self.table_rows);
+        let (table_rows, issues) = self.table_rows.convert_to_schema(&schema);
+        //return
+        (Table { schema, table_rows }, issues)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_infer_and_convert() {
+        let s = "[S][legacy][from csv]\n[String][String][String][String][String][String][String][String]\n[][][][][][][][]\n[][][][][][][][]\n[Name][Zip][Count][Price][Ratio][Active][Founded][Cities]\n";
+        let r = "[R][legacy]\n[Slovenia][01000][2][1.5][1e3][T][1991-06-25][1\\[Ljubljana\\]\\[300000\\]1\\[Koper\\]\\[30000\\]1]\n[Italia][00100][][2][0.5][F][1861-03-17][]\n";
+        let schema = unwrap!(TableSchema::schema_from_qvs20_str(s));
+        let table_rows = unwrap!(TableRows::rows_from_qvs20_str(r, &schema));
+        let table = Table { schema, table_rows };
+        let (typed, issues) = table.infer_types();
+        assert!(issues.is_empty());
+        assert_eq!(
+            format!("{:?}", typed.schema.data_types),
+            "[String, String, Integer, Decimal, Float, Bool, Date, SubTable]"
+        );
+        let sub_schema = unwrap!(typed.schema.sub_table_schemas[7].as_ref());
+        assert_eq!(format!("{:?}", sub_schema.data_types), "[String, Integer]");
+        assert_eq!(
+            typed.table_rows.write_table_rows(),
+            "[R][legacy]\n[Slovenia][01000][2][1.5][1000.0][T][1991-06-25][1[Ljubljana][300000]1[Koper][30000]1]\n[Italia][00100][][2][0.5][F][1861-03-17][]\n"
+        );
+
+        // schema without column names
+        let proposed = TableSchema::infer_from_rows(&table.table_rows);
+        assert_eq!(proposed.column_names[0], "1");
+        assert_eq!(proposed.data_types[2], DataType::Integer);
+
+        // cells that don't fit the requested schema
+        let mut schema = typed.schema.clone();
+        schema.data_types[4] = DataType::Integer;
+        let (_rows, issues) = table.table_rows.convert_to_schema(&schema);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].column_name, "Ratio");
+        assert_eq!(issues[0].text, "1e3");
+    }
+}
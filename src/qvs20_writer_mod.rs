@@ -1,7 +1,9 @@
 // qvs20_writer_mod
 
+use crate::qvs20_table_rows_mod::*;
 use crate::qvs20_table_schema_mod::*;
 
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
 use rust_decimal::prelude::*;
 //use unwrap::unwrap;
 
@@ -71,6 +73,52 @@ impl WriterForQvs20 {
         self.output.push(']');
         self.column += 1;
     }
+    /// write a field of type bool
+    pub fn write_bool(&mut self, data: bool) {
+        if data {
+            self.output.push_str("[T]");
+        } else {
+            self.output.push_str("[F]");
+        }
+        self.column += 1;
+    }
+    /// write a field of type datetime rfc3339: 2014-11-28T21:00:09.123456+09:00
+    pub fn write_datetime(&mut self, data: &DateTime<FixedOffset>) {
+        self.output.push('[');
+        self.output.push_str(&data.to_rfc3339());
+        self.output.push(']');
+        self.column += 1;
+    }
+    /// write a field of type date ISO 8601: 2014-11-28
+    pub fn write_date(&mut self, data: &NaiveDate) {
+        self.output.push('[');
+        self.output.push_str(&data.to_string());
+        self.output.push(']');
+        self.column += 1;
+    }
+    /// write a field of type time ISO 8601: 23:59:59.123456
+    pub fn write_time(&mut self, data: &NaiveTime) {
+        self.output.push('[');
+        self.output.push_str(&data.to_string());
+        self.output.push(']');
+        self.column += 1;
+    }
+    /// write a field of type SubTable
+    /// the row delimiter of the sub table is the depth 1-9
+    /// a sub table without rows is written as empty field []
+    pub fn write_sub_table_rows(&mut self, table_rows: &TableRows) {
+        self.output.push('[');
+        if !table_rows.rows.is_empty() {
+            let mut wrt = WriterForQvs20::new_with_delimiter(table_rows.row_delimiter as char);
+            //sub table start with delimiter
+            wrt.write_delimiter();
+            table_rows.write_data_rows_to_writer(&mut wrt);
+            let output_sub_table = wrt.return_and_finish();
+            self.output.push_str(&output_sub_table);
+        }
+        self.output.push(']');
+        self.column += 1;
+    }
     /// write a sub table schema
     /// write a field of type String
     pub fn write_sub_table_schema(&mut self, schema: &TableSchema) {
@@ -0,0 +1,299 @@
+// qvs20_xlsx_mod
+
+//! XLSX spreadsheet export. Only with the cargo feature `xlsx`.
+//!
+//! The table is on the first sheet with the column names in the first row.
+//! Integer, Float, Bool, Date and Time are typed cells. DateTimeFixedOffset is a text cell,
+//! because Excel dates do not have an offset. Decimal is a text cell,
+//! because the Excel number is f64 and loses digits and the scale.
+//! Every SubTable column has its own sheet with all the sub rows.
+//! The first column `parent_row` of the sub sheet links back to the row of the parent sheet
+//! and the SubTable cell links to the first sub row.
+//!
+//! The hidden sheet `qvs21_metadata` has one row for every column of every sheet:
+//! table name, description, data type, additional properties and the sheet of the sub table.
+//! This is enough to convert the workbook back to QVS21.
+
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::path::Path;
+
+const METADATA_SHEET: &str = "qvs21_metadata";
+/// the rows of a sheet, including the header row
+const MAX_SHEET_ROWS: usize = 1_048_576;
+/// bigger integers lose precision as Excel numbers and are written as text
+const MAX_EXACT_INTEGER: i64 = 9_007_199_254_740_992;
+
+fn xlsx_error(e: XlsxError) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("XLSX error: {}", e),
+    }
+}
+
+/// rows of one sheet and where the sub rows are on the sub sheets
+struct SheetPlan<'a> {
+    name: String,
+    schema: &'a TableSchema,
+    /// parent row on the parent sheet (1-based like in Excel) and the row
+    rows: Vec<(Option<u32>, &'a Row)>,
+    parent_sheet: Option<String>,
+    /// for SubTable columns: the sub sheet index and the first Excel row of the sub rows for every row
+    sub_sheets: Vec<Option<(usize, Vec<u32>)>>,
+}
+
+/// valid and unique sheet name: max 31 characters without []:*?/\'
+fn sheet_name(name: &str, used_names: &[String]) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\'".contains(c) { '_' } else { c })
+        .take(31)
+        .collect();
+    let clean = if clean.is_empty() { s!("table") } else { clean };
+    let mut unique = clean.clone();
+    let mut i = 2;
+    while unique.eq_ignore_ascii_case(METADATA_SHEET) || used_names.iter().any(|n| n.eq_ignore_ascii_case(&unique)) {
+        let suffix = format!("_{}", i);
+        unique = format!("{}{}", clean.chars().take(31 - suffix.len()).collect::<String>(), suffix);
+        i += 1;
+    }
+    unique
+}
+
+/// plan the sheet and all sub sheets. Returns the index of the sheet.
+fn plan_sheets<'a>(
+    name: &str,
+    schema: &'a TableSchema,
+    rows: Vec<(Option<u32>, &'a Row)>,
+    parent_sheet: Option<String>,
+    sheets: &mut Vec<SheetPlan<'a>>,
+) -> Result<usize, Qvs20Error> {
+    let used_names: Vec<String> = sheets.iter().map(|s| s.name.clone()).collect();
+    let name = sheet_name(name, &used_names);
+    if rows.len() + 1 > MAX_SHEET_ROWS {
+        return Err(Qvs20Error::Error {
+            msg: format!("Sheet {} has {} rows, more than the XLSX limit.", name, rows.len()),
+        });
+    }
+    let index = sheets.len();
+    sheets.push(SheetPlan {
+        name: name.clone(),
+        schema,
+        rows: vec![],
+        parent_sheet,
+        sub_sheets: vec![],
+    });
+    let mut sub_sheets = vec![];
+    for (column, sub_schema) in schema.sub_table_schemas.iter().enumerate() {
+        let sub_schema = match sub_schema {
+            Some(s) => s,
+            None => {
+                sub_sheets.push(None);
+                continue;
+            }
+        };
+        let mut sub_rows = vec![];
+        let mut first_rows = vec![];
+        for (row_index, (_, row)) in rows.iter().enumerate() {
+            // after the header row
+            first_rows.push(sub_rows.len() as u32 + 2);
+            if let Value::SubTable(sub_table_rows) = &row.values[column] {
+                for sub_row in sub_table_rows.rows.iter() {
+                    sub_rows.push((Some(row_index as u32 + 2), sub_row));
+                }
+            }
+        }
+        let sub_name = format!("{}.{}", name, schema.column_names[column]);
+        let sub_index = plan_sheets(&sub_name, sub_schema, sub_rows, Some(name.clone()), sheets)?;
+        sub_sheets.push(Some((sub_index, first_rows)));
+    }
+    sheets[index].rows = rows;
+    sheets[index].sub_sheets = sub_sheets;
+    //return
+    Ok(index)
+}
+
+fn write_value(worksheet: &mut Worksheet, row: u32, col: u16, value: &Value, formats: &Formats) -> Result<(), XlsxError> {
+    match value {
+        Value::String(s) => {
+            worksheet.write_string(row, col, s)?;
+        }
+        Value::Integer(i) if (-MAX_EXACT_INTEGER..=MAX_EXACT_INTEGER).contains(i) => {
+            worksheet.write_number(row, col, *i as f64)?;
+        }
+        Value::Integer(i) => {
+            worksheet.write_string(row, col, i.to_string())?;
+        }
+        Value::Decimal(d) => {
+            worksheet.write_string(row, col, d.to_string())?;
+        }
+        Value::Float(f) => {
+            worksheet.write_number(row, col, *f)?;
+        }
+        Value::Bool(b) => {
+            worksheet.write_boolean(row, col, *b)?;
+        }
+        Value::DateTimeFixedOffset(d) => {
+            worksheet.write_string(row, col, d.to_rfc3339())?;
+        }
+        Value::Date(d) => {
+            worksheet.write_date_with_format(row, col, d, &formats.date)?;
+        }
+        Value::Time(t) => {
+            worksheet.write_time_with_format(row, col, t, &formats.time)?;
+        }
+        // SubTable cells are written with the link to the sub sheet
+        Value::SubTable(_) | Value::Null => (),
+    }
+    Ok(())
+}
+
+struct Formats {
+    header: Format,
+    date: Format,
+    time: Format,
+}
+
+fn write_sheet(worksheet: &mut Worksheet, sheet: &SheetPlan, sheets: &[SheetPlan], formats: &Formats) -> Result<(), XlsxError> {
+    worksheet.set_name(&sheet.name)?;
+    // the sub sheet has the first column parent_row
+    let first_col: u16 = if sheet.parent_sheet.is_some() { 1 } else { 0 };
+    if first_col == 1 {
+        worksheet.write_string_with_format(0, 0, "parent_row", &formats.header)?;
+    }
+    for (column, column_name) in sheet.schema.column_names.iter().enumerate() {
+        worksheet.write_string_with_format(0, first_col + column as u16, column_name, &formats.header)?;
+    }
+    for (row_index, (parent_row, row)) in sheet.rows.iter().enumerate() {
+        let excel_row = row_index as u32 + 1;
+        if let (Some(parent_row), Some(parent_sheet)) = (parent_row, &sheet.parent_sheet) {
+            let link = format!("internal:'{}'!A{}", parent_sheet, parent_row);
+            worksheet.write_url_with_text(excel_row, 0, link.as_str(), parent_row.to_string())?;
+        }
+        for (column, value) in row.values.iter().enumerate() {
+            let col = first_col + column as u16;
+            match (value, &sheet.sub_sheets[column]) {
+                (Value::SubTable(sub_table_rows), Some((sub_index, first_rows))) if !sub_table_rows.rows.is_empty() => {
+                    let link = format!("internal:'{}'!A{}", sheets[*sub_index].name, first_rows[row_index]);
+                    worksheet.write_url_with_text(excel_row, col, link.as_str(), format!("{} rows", sub_table_rows.rows.len()))?;
+                }
+                (Value::SubTable(_), _) => {
+                    worksheet.write_string(excel_row, col, "0 rows")?;
+                }
+                (value, _) => write_value(worksheet, excel_row, col, value, formats)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_metadata(worksheet: &mut Worksheet, sheets: &[SheetPlan], formats: &Formats) -> Result<(), XlsxError> {
+    worksheet.set_name(METADATA_SHEET)?;
+    worksheet.set_hidden(true);
+    let header = ["sheet", "table_name", "description", "column", "data_type", "additional_properties", "sub_sheet"];
+    for (col, name) in header.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *name, &formats.header)?;
+    }
+    let mut excel_row = 1;
+    for sheet in sheets.iter() {
+        let schema = sheet.schema;
+        for column in 0..schema.column_names.len() {
+            worksheet.write_string(excel_row, 0, &sheet.name)?;
+            worksheet.write_string(excel_row, 1, &schema.table_name)?;
+            worksheet.write_string(excel_row, 2, &schema.table_description)?;
+            worksheet.write_string(excel_row, 3, &schema.column_names[column])?;
+            worksheet.write_string(excel_row, 4, schema.data_types[column].to_string())?;
+            worksheet.write_string(excel_row, 5, &schema.additional_properties[column])?;
+            if let Some((sub_index, _)) = &sheet.sub_sheets[column] {
+                worksheet.write_string(excel_row, 6, &sheets[*sub_index].name)?;
+            }
+            excel_row += 1;
+        }
+    }
+    Ok(())
+}
+
+impl Table {
+    /// the workbook with all sheets
+    fn xlsx_workbook(&self) -> Result<Workbook, Qvs20Error> {
+        let rows = self.table_rows.rows.iter().map(|row| (None, row)).collect();
+        let mut sheets = vec![];
+        plan_sheets(&self.schema.table_name, &self.schema, rows, None, &mut sheets)?;
+        let formats = Formats {
+            header: Format::new().set_bold(),
+            date: Format::new().set_num_format("yyyy-mm-dd"),
+            time: Format::new().set_num_format("hh:mm:ss"),
+        };
+        let mut workbook = Workbook::new();
+        for sheet in sheets.iter() {
+            let mut worksheet = Worksheet::new();
+            write_sheet(&mut worksheet, sheet, &sheets, &formats).map_err(xlsx_error)?;
+            workbook.push_worksheet(worksheet);
+        }
+        let mut worksheet = Worksheet::new();
+        write_metadata(&mut worksheet, &sheets, &formats).map_err(xlsx_error)?;
+        workbook.push_worksheet(worksheet);
+        //return
+        Ok(workbook)
+    }
+
+    /// write the table to an XLSX file. Every SubTable column is on its own sheet.
+    pub fn write_xlsx(&self, path: &Path) -> Result<(), Qvs20Error> {
+        self.xlsx_workbook()?.save(path).map_err(xlsx_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    /// the xml file from the xlsx zip
+    fn xlsx_part(buffer: &[u8], name: &str) -> String {
+        use std::io::Read;
+        let mut archive = unwrap!(zip::ZipArchive::new(std::io::Cursor::new(buffer)));
+        let mut text = s!();
+        unwrap!(unwrap!(archive.by_name(name)).read_to_string(&mut text));
+        text
+    }
+
+    #[test]
+    pub fn t01_workbook_sheets() {
+        let s = r"[T][countries/europe][description]
+[String][Integer][Decimal][Date][SubTable]
+[][][][][1[U][cities][sub table]1[String][SubTable]1[][2[U][streets][]2[String]2[]2[]2[street]2]1[][]1[city][streets]1]
+[primary_key=1][][][][]
+[country][population][area][founded][cities]
+[Slovenia][2000000][20271.50][1991-06-25][1[Ljubljana][2[Čopova]2[Trubarjeva]2]1[Koper][]1]
+[Croatia][-9223372036854775808][][][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let mut workbook = unwrap!(table.xlsx_workbook());
+        let names: Vec<String> = workbook.worksheets().iter().map(|w| w.name()).collect();
+        assert_eq!(
+            names,
+            vec!["countries_europe", "countries_europe.cities", "countries_europe.cities.streets", "qvs21_metadata"]
+        );
+        let buffer = unwrap!(workbook.save_to_buffer());
+        let sheet = xlsx_part(&buffer, "xl/worksheets/sheet1.xml");
+        let strings = xlsx_part(&buffer, "xl/sharedStrings.xml");
+        // typed cells: number and date, the big integer and the decimal are text
+        assert!(sheet.contains(r#"<c r="B2"><v>2000000</v></c>"#));
+        assert!(sheet.contains(r#"<c r="D2" s="3"><v>33414</v></c>"#));
+        assert!(sheet.contains(r#"<c r="B3" t="s">"#));
+        assert!(strings.contains("<t>-9223372036854775808</t>"));
+        assert!(strings.contains("<t>20271.50</t>"));
+        // the link to the sub rows and back to the parent row
+        assert!(sheet.contains(r#"<hyperlink ref="E2" location="'countries_europe.cities'!A2""#));
+        let sub_sheet = xlsx_part(&buffer, "xl/worksheets/sheet2.xml");
+        assert!(sub_sheet.contains(r#"<hyperlink ref="A3" location="'countries_europe'!A2""#));
+        // metadata rows: header and one row for every column of every sheet
+        let metadata = xlsx_part(&buffer, "xl/worksheets/sheet4.xml");
+        assert_eq!(metadata.matches("<row ").count(), 1 + 5 + 2 + 1);
+        assert!(strings.contains("<t>primary_key=1</t>"));
+        assert!(strings.contains("<t>countries_europe.cities.streets</t>"));
+    }
+}
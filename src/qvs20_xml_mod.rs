@@ -0,0 +1,524 @@
+// qvs20_xml_mod
+
+//! XML export and import. Only with the cargo feature `xml`.
+//!
+//! The export has one element for every row and one child element for every column.
+//! Sub tables are nested `row` elements inside the column element.
+//! The header `schema` has the data types and additional properties, so the import
+//! of the same layout gives the same typed table:
+//!
+//! ```xml
+//! <table name="countries" description="European countries">
+//!   <schema>
+//!     <column name="country" data_type="String" additional_properties="primary_key=1"/>
+//!     <column name="cities" data_type="SubTable">
+//!       <table name="cities">
+//!         <schema>
+//!           <column name="city" data_type="String"/>
+//!         </schema>
+//!       </table>
+//!     </column>
+//!   </schema>
+//!   <rows>
+//!     <row>
+//!       <country>Slovenia</country>
+//!       <cities>
+//!         <row>
+//!           <city>Ljubljana</city>
+//!         </row>
+//!       </cities>
+//!     </row>
+//!   </rows>
+//! </table>
+//! ```
+//!
+//! Legacy XML feeds have their own structure. XmlImport maps it to a TableSchema:
+//! the path of the row elements and the source of every column.
+//! The source is a path relative to the row element: `price/amount`, `@id`, `price/@currency`
+//! or `.` for the text of the row element. The source of a SubTable column is the path of the sub row elements.
+//! Namespace prefixes are ignored.
+
+use crate::qvs20_constraints_mod::*;
+use crate::qvs20_reader_mod::*;
+use crate::qvs20_table_mod::*;
+use crate::qvs20_table_rows_mod::*;
+use crate::qvs20_table_schema_mod::*;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const INDENT: &str = "  ";
+
+/// element with the attributes, child elements and the text directly inside
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// all the descendants on the path of element names
+    fn select(&self, path: &[&str]) -> Vec<&Element> {
+        let mut elements = vec![self];
+        for name in path.iter().filter(|n| !n.is_empty() && **n != ".") {
+            elements = elements
+                .iter()
+                .flat_map(|e| e.children.iter().filter(|c| c.name == *name))
+                .collect();
+        }
+        //return
+        elements
+    }
+}
+
+fn xml_error(msg: &str, position: u64) -> Qvs20Error {
+    Qvs20Error::Error {
+        msg: format!("XML error at byte {}: {}", position, msg),
+    }
+}
+
+fn element_from_start(start: &BytesStart, position: u64) -> Result<Element, Qvs20Error> {
+    let mut element = Element {
+        name: String::from_utf8_lossy(start.local_name().as_ref()).to_string(),
+        ..Element::default()
+    };
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| xml_error(&e.to_string(), position))?;
+        let value = attribute.unescape_value().map_err(|e| xml_error(&e.to_string(), position))?;
+        let name = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+        element.attributes.push((name, value.to_string()));
+    }
+    //return
+    Ok(element)
+}
+
+/// the element tree of the document
+fn parse_xml(xml: &str) -> Result<Element, Qvs20Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = vec![];
+    let mut root = None;
+    loop {
+        let position = reader.buffer_position();
+        let event = reader.read_event().map_err(|e| xml_error(&e.to_string(), position))?;
+        let finished = match event {
+            Event::Start(start) => {
+                stack.push(element_from_start(&start, position)?);
+                None
+            }
+            Event::Empty(start) => Some(element_from_start(&start, position)?),
+            Event::End(_) => stack.pop(),
+            Event::Text(text) => {
+                if let Some(element) = stack.last_mut() {
+                    let text = text.unescape().map_err(|e| xml_error(&e.to_string(), position))?;
+                    element.text.push_str(&text);
+                }
+                None
+            }
+            Event::CData(data) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&String::from_utf8_lossy(&data));
+                }
+                None
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        if let Some(element) = finished {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None if root.is_none() => root = Some(element),
+                None => return Err(xml_error("More than one root element.", position)),
+            }
+        }
+    }
+    //return
+    root.ok_or_else(|| xml_error("There is no root element.", 0))
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // keep the carriage return, parsers normalize it to line feed
+            '\r' => escaped.push_str("&#13;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// valid XML element name from the column name
+fn xml_name(name: &str) -> String {
+    let mut xml_name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "_-.".contains(c) { c } else { '_' })
+        .collect();
+    if !xml_name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        xml_name.insert(0, '_');
+    }
+    xml_name
+}
+
+fn write_schema_xml(schema: &TableSchema, indent: &str, xml: &mut String) {
+    xml.push_str(&format!("{}<schema>\n", indent));
+    for (column, column_name) in schema.column_names.iter().enumerate() {
+        xml.push_str(&format!(
+            "{}{}<column name=\"{}\" data_type=\"{}\"",
+            indent,
+            INDENT,
+            escape_xml(column_name),
+            schema.data_types[column]
+        ));
+        if !schema.additional_properties[column].is_empty() {
+            xml.push_str(&format!(" additional_properties=\"{}\"", escape_xml(&schema.additional_properties[column])));
+        }
+        match &schema.sub_table_schemas[column] {
+            Some(sub_schema) => {
+                xml.push_str(">\n");
+                let sub_indent = format!("{}{}{}", indent, INDENT, INDENT);
+                write_table_start(sub_schema, &sub_indent, xml);
+                write_schema_xml(sub_schema, &format!("{}{}", sub_indent, INDENT), xml);
+                xml.push_str(&format!("{}</table>\n{}{}</column>\n", sub_indent, indent, INDENT));
+            }
+            None => xml.push_str("/>\n"),
+        }
+    }
+    xml.push_str(&format!("{}</schema>\n", indent));
+}
+
+fn write_table_start(schema: &TableSchema, indent: &str, xml: &mut String) {
+    xml.push_str(&format!("{}<table name=\"{}\"", indent, escape_xml(&schema.table_name)));
+    if !schema.table_description.is_empty() {
+        xml.push_str(&format!(" description=\"{}\"", escape_xml(&schema.table_description)));
+    }
+    xml.push_str(">\n");
+}
+
+fn write_rows_xml(schema: &TableSchema, table_rows: &TableRows, indent: &str, xml: &mut String) {
+    let column_indent = format!("{}{}", indent, INDENT);
+    for row in table_rows.rows.iter() {
+        xml.push_str(&format!("{}<row>\n", indent));
+        for (column, value) in row.values.iter().enumerate() {
+            let name = xml_name(&schema.column_names[column]);
+            match (value, &schema.sub_table_schemas[column]) {
+                (Value::SubTable(sub_table_rows), Some(sub_schema)) if !sub_table_rows.rows.is_empty() => {
+                    xml.push_str(&format!("{}<{}>\n", column_indent, name));
+                    write_rows_xml(sub_schema, sub_table_rows, &format!("{}{}", column_indent, INDENT), xml);
+                    xml.push_str(&format!("{}</{}>\n", column_indent, name));
+                }
+                (Value::SubTable(_), _) | (Value::Null, _) => xml.push_str(&format!("{}<{}/>\n", column_indent, name)),
+                (Value::String(s), _) if s.is_empty() => xml.push_str(&format!("{}<{}/>\n", column_indent, name)),
+                (value, _) => xml.push_str(&format!(
+                    "{}<{}>{}</{}>\n",
+                    column_indent,
+                    name,
+                    escape_xml(&value_to_string(value)),
+                    name
+                )),
+            }
+        }
+        xml.push_str(&format!("{}</row>\n", indent));
+    }
+}
+
+/// the schema from the `table` element of the header
+fn schema_from_xml(element: &Element) -> Result<TableSchema, Qvs20Error> {
+    let mut schema = TableSchema::new_simple_strings(0);
+    schema.table_name = s!(element.attribute("name").unwrap_or_default());
+    schema.table_description = s!(element.attribute("description").unwrap_or_default());
+    let columns = match element.child("schema") {
+        Some(s) => s.select(&["column"]),
+        None => {
+            return Err(Qvs20Error::Error {
+                msg: format!("The table {} has no schema element.", schema.table_name),
+            })
+        }
+    };
+    for column in columns {
+        let column_name = column.attribute("name").unwrap_or_default();
+        let data_type_text = column.attribute("data_type").unwrap_or_default();
+        let data_type = DataType::from_str(data_type_text).map_err(|_| Qvs20Error::Error {
+            msg: format!("Column {} has unknown data type {}.", column_name, data_type_text),
+        })?;
+        let sub_schema = match (&data_type, column.child("table")) {
+            (DataType::SubTable, Some(sub_table)) => Some(schema_from_xml(sub_table)?),
+            (DataType::SubTable, None) => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("SubTable column {} has no table element.", column_name),
+                })
+            }
+            _ => None,
+        };
+        let additional_property = column.attribute("additional_properties").unwrap_or_default();
+        schema.push_column(column_name, data_type, sub_schema, additional_property);
+    }
+    //return
+    Ok(schema)
+}
+
+/// import of an XML structure into a typed table
+pub struct XmlImport {
+    /// element names from the root to the row element
+    row_path: Vec<String>,
+    schema: TableSchema,
+    /// the source for the column path like `cities.city`
+    sources: HashMap<String, String>,
+}
+
+impl XmlImport {
+    /// the rows are the elements on the path from the root element like `feed/items/item`.
+    /// The default source of a column is the child element with the same name
+    /// and `column/row` for a SubTable column.
+    pub fn new(row_path: &str, schema: TableSchema) -> XmlImport {
+        XmlImport {
+            row_path: row_path.split('/').filter(|n| !n.is_empty()).map(|n| s!(n)).collect(),
+            schema,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// the source of the column. For sub table columns use the column path like `cities.city`.
+    pub fn map_column(mut self, column_path: &str, source: &str) -> Result<XmlImport, Qvs20Error> {
+        let mut schema = &self.schema;
+        let names: Vec<&str> = column_path.split('.').collect();
+        for (i, name) in names.iter().enumerate() {
+            let column = match schema.column_index(name) {
+                Some(c) => c,
+                None => {
+                    return Err(Qvs20Error::Error {
+                        msg: format!("Column {} is not in the table {}.", column_path, self.schema.table_name),
+                    })
+                }
+            };
+            if i + 1 < names.len() {
+                schema = match &schema.sub_table_schemas[column] {
+                    Some(s) => s,
+                    None => {
+                        return Err(Qvs20Error::Error {
+                            msg: format!("Column {} is not a SubTable.", names[..i + 1].join(".")),
+                        })
+                    }
+                };
+            }
+        }
+        self.sources.insert(s!(column_path), s!(source));
+        //return
+        Ok(self)
+    }
+
+    /// the table from the XML document
+    pub fn import(&self, xml: &str) -> Result<Table, Qvs20Error> {
+        let root = parse_xml(xml)?;
+        self.import_element(&root)
+    }
+
+    fn import_element(&self, root: &Element) -> Result<Table, Qvs20Error> {
+        let mut schema = self.schema.clone();
+        schema.set_depth(0)?;
+        let row_elements = match self.row_path.split_first() {
+            Some((root_name, path)) if *root_name == root.name => {
+                root.select(&path.iter().map(|n| n.as_str()).collect::<Vec<&str>>())
+            }
+            _ => {
+                return Err(Qvs20Error::Error {
+                    msg: format!("The root element {} is not on the row path {}.", root.name, self.row_path.join("/")),
+                })
+            }
+        };
+        let mut table_rows = TableRows::new(&schema.table_name, schema.row_delimiter)?;
+        for (row_index, row_element) in row_elements.iter().enumerate() {
+            let row = self.import_row(&schema, "", row_element).map_err(|e| Qvs20Error::Error {
+                msg: format!("Row {}: {}", row_index + 1, err_trim!(e)),
+            })?;
+            table_rows.rows.push(row);
+        }
+        table_rows.set_depth(0)?;
+        table_rows.check_constraints(&schema)?;
+        //return
+        Ok(Table { schema, table_rows })
+    }
+
+    fn import_row(&self, schema: &TableSchema, prefix: &str, row_element: &Element) -> Result<Row, Qvs20Error> {
+        let mut values = vec![];
+        for (column, column_name) in schema.column_names.iter().enumerate() {
+            let column_path = format!("{}{}", prefix, column_name);
+            let source = match self.sources.get(&column_path) {
+                Some(source) => source.clone(),
+                None if schema.data_types[column] == DataType::SubTable => format!("{}/row", xml_name(column_name)),
+                None => xml_name(column_name),
+            };
+            let path: Vec<&str> = source.split('/').collect();
+            let value = match &schema.sub_table_schemas[column] {
+                Some(sub_schema) => {
+                    let mut sub_table_rows = TableRows::default();
+                    for sub_row_element in row_element.select(&path) {
+                        let sub_row = self.import_row(sub_schema, &format!("{}.", column_path), sub_row_element)?;
+                        sub_table_rows.rows.push(sub_row);
+                    }
+                    Value::SubTable(sub_table_rows)
+                }
+                None => {
+                    let text = match path.split_last() {
+                        Some((last, path)) if last.starts_with('@') => row_element
+                            .select(path)
+                            .first()
+                            .and_then(|e| e.attribute(&last[1..]))
+                            .unwrap_or_default(),
+                        _ => row_element.select(&path).first().map(|e| e.text.as_str()).unwrap_or_default(),
+                    };
+                    let data_type = &schema.data_types[column];
+                    // the text of other data types is often indented
+                    let text = if *data_type == DataType::String { text } else { text.trim() };
+                    TableRows::value_from_str(text, data_type).map_err(|e| Qvs20Error::Error {
+                        msg: format!("Column {} from {}: {}", column_path, source, err_trim!(e)),
+                    })?
+                }
+            };
+            values.push(value);
+        }
+        //return
+        Ok(Row { values })
+    }
+}
+
+impl Table {
+    /// XML with the schema header and one element for every row
+    pub fn to_xml(&self) -> String {
+        let mut xml = s!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        write_table_start(&self.schema, "", &mut xml);
+        write_schema_xml(&self.schema, INDENT, &mut xml);
+        xml.push_str(&format!("{}<rows>\n", INDENT));
+        write_rows_xml(&self.schema, &self.table_rows, &format!("{}{}", INDENT, INDENT), &mut xml);
+        xml.push_str(&format!("{}</rows>\n</table>\n", INDENT));
+        //return
+        xml
+    }
+
+    /// import the XML written by to_xml
+    pub fn from_xml(xml: &str) -> Result<Table, Qvs20Error> {
+        let root = parse_xml(xml)?;
+        if root.name != "table" {
+            return Err(Qvs20Error::Error {
+                msg: format!("The root element is {} and not table.", root.name),
+            });
+        }
+        let schema = schema_from_xml(&root)?;
+        //return
+        XmlImport::new("table/rows/row", schema).import_element(&root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unwrap::unwrap;
+
+    #[test]
+    pub fn t01_xml_round_trip_and_import() {
+        let s = r"[T][countries][big & small]
+[String][Integer][Date][SubTable]
+[][][][1[U][cities][]1[String][Bool]1[][]1[][]1[city][capital]1]
+[primary_key=1][min=0][][]
+[country][population][founded][cities]
+[Slovenia <SI>][2000000][1991-06-25][1[Ljubljana][T]1[Koper][F]1]
+[Croatia][][][]
+";
+        let table = unwrap!(Table::from_qvs20_str_with_schema(&s));
+        let xml = table.to_xml();
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<table name="countries" description="big &amp; small">
+  <schema>
+    <column name="country" data_type="String" additional_properties="primary_key=1"/>
+    <column name="population" data_type="Integer" additional_properties="min=0"/>
+    <column name="founded" data_type="Date"/>
+    <column name="cities" data_type="SubTable">
+      <table name="cities">
+        <schema>
+          <column name="city" data_type="String"/>
+          <column name="capital" data_type="Bool"/>
+        </schema>
+      </table>
+    </column>
+  </schema>
+  <rows>
+    <row>
+      <country>Slovenia &lt;SI&gt;</country>
+      <population>2000000</population>
+      <founded>1991-06-25</founded>
+      <cities>
+        <row>
+          <city>Ljubljana</city>
+          <capital>T</capital>
+        </row>
+        <row>
+          <city>Koper</city>
+          <capital>F</capital>
+        </row>
+      </cities>
+    </row>
+    <row>
+      <country>Croatia</country>
+      <population/>
+      <founded/>
+      <cities/>
+    </row>
+  </rows>
+</table>
+"#
+        );
+        let table2 = unwrap!(Table::from_xml(&xml));
+        assert_eq!(table2.write_table(), table.write_table());
+        let err = Table::from_xml(&xml.replace("<population>2000000</population>", "<population>-1</population>")).unwrap_err();
+        assert_eq!(remove_src_loc(err), "Error: Constraint violation row 0 col 1 population: value -1 is less than min 0");
+
+        // legacy feed
+        let feed = r#"<feed><items>
+  <item id="SI"><name> Slovenia </name><stats><population>
+    2000000
+  </population></stats><city name="Ljubljana"/><city name="Koper"/></item>
+  <item id="HR"><name>Croatia</name></item>
+</items></feed>"#;
+        let mut schema = TableSchema::new_simple_strings(0);
+        schema.table_name = s!("countries");
+        let mut sub_schema = TableSchema::new_simple_strings(0);
+        sub_schema.table_name = s!("cities");
+        sub_schema.push_column("city", DataType::String, None, "");
+        schema.push_column("code", DataType::String, None, "");
+        schema.push_column("population", DataType::Integer, None, "");
+        schema.push_column("cities", DataType::SubTable, Some(sub_schema), "");
+        let import = unwrap!(unwrap!(unwrap!(unwrap!(XmlImport::new("feed/items/item", schema.clone())
+            .map_column("code", "@id"))
+        .map_column("population", "stats/population"))
+        .map_column("cities", "city"))
+        .map_column("cities.city", "@name"));
+        let table3 = unwrap!(import.import(feed));
+        assert_eq!(
+            table3.table_rows.write_table_rows(),
+            "[R][countries]\n[SI][2000000][1[Ljubljana]1[Koper]1]\n[HR][][]\n"
+        );
+        let import = unwrap!(XmlImport::new("feed/items/item", schema).map_column("population", "name"));
+        assert_eq!(
+            remove_src_loc(import.import(feed).unwrap_err()),
+            "Error: Row 1: Column population from name: Failed conversion to integer. invalid digit found in string"
+        );
+    }
+}